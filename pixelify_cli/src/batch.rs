@@ -0,0 +1,254 @@
+//! Batch processing for pixelify_cli.
+//!
+//! Expands input directories and glob patterns into one job per image, mirrors their directory
+//! structure into an output directory, and runs the jobs in parallel across CPU cores.
+//! A failing file is reported in the summary without stopping the rest of the batch.
+
+use crate::cli_utils::{Encoding, process_pipeline};
+use image::ImageFormat;
+use pixelify_core::pipeline::Pipeline;
+use pixelify_core::pixelify_errors::ImageProcessingError;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// One input image and where its result is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchJob {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+/// The outcome of a batch, in the same order as its jobs.
+pub struct BatchReport {
+    pub results: Vec<(BatchJob, Result<(), ImageProcessingError>)>,
+}
+
+impl BatchReport {
+    pub fn failed(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, result)| result.is_err())
+            .count()
+    }
+
+    /// Prints one line per file, followed by the totals.
+    pub fn print_summary(&self) {
+        for (job, result) in &self.results {
+            match result {
+                Ok(()) => println!(
+                    "ok      {} -> {}",
+                    job.input.display(),
+                    job.output.display()
+                ),
+                Err(e) => eprintln!("failed  {}: {e}", job.input.display()),
+            }
+        }
+
+        let failed = self.failed();
+        println!(
+            "{} succeeded, {failed} failed, {} total",
+            self.results.len() - failed,
+            self.results.len()
+        );
+    }
+}
+
+/// Expands `inputs` into jobs that write files with `extension` under `output_dir`.
+///
+/// Inputs are expanded as described in `collect_inputs`, and directory structure is mirrored under `output_dir`.
+/// The same file matched by several inputs is only processed once.
+///
+/// # Errors
+///
+/// Returns the `ImageProcessingError` of `collect_inputs`, or an error if two different images
+/// would be written to the same output file, such as `a.png` and `a.bmp` or files of the same name
+/// from two single-file inputs.
+pub fn collect_jobs(
+    inputs: &[String],
+    output_dir: &Path,
+    extension: &str,
+) -> Result<Vec<BatchJob>, ImageProcessingError> {
+    let mut seen_inputs = HashSet::new();
+    let mut outputs: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut jobs = Vec::new();
+
+    for (input, relative) in collect_inputs(inputs)? {
+        let canonical = fs::canonicalize(&input).unwrap_or_else(|_| input.clone());
+        if !seen_inputs.insert(canonical) {
+            continue;
+        }
+
+        let output = output_dir.join(relative).with_extension(extension);
+        if let Some(earlier) = outputs.insert(output.clone(), input.clone()) {
+            return Err(input_error(format!(
+                "{} and {} would both be written to {}",
+                earlier.display(),
+                input.display(),
+                output.display()
+            )));
+        }
+
+        jobs.push(BatchJob { input, output });
+    }
+
+    Ok(jobs)
+}
+
+/// Expands `inputs` into image files, each with its path relative to the input it was found through.
+///
+/// Each input can be:
+/// - a directory, searched recursively, with paths relative to the directory,
+/// - a glob pattern such as `assets/**/*.png`, with paths relative to the part before the first wildcard,
+/// - a single file, with just its file name.
+///
+/// Only files with an image extension are picked up from directories and patterns.
+///
+/// # Errors
+///
+/// Returns `Err(ImageProcessingError)` if an input does not exist, a pattern is invalid,
+/// a directory cannot be read, or no images are found at all.
+pub fn collect_inputs(inputs: &[String]) -> Result<Vec<(PathBuf, PathBuf)>, ImageProcessingError> {
+    let mut collected = Vec::new();
+
+    for input in inputs {
+        let path = Path::new(input);
+
+        // (file, path relative to the mirrored root)
+        let mut files: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+        if path.is_dir() {
+            collect_dir(path, path, &mut files)?;
+        } else if is_pattern(input) {
+            let base = pattern_base(input);
+            let paths = glob::glob(input)
+                .map_err(|e| input_error(format!("invalid pattern {input}: {e}")))?;
+
+            for entry in paths {
+                let file =
+                    entry.map_err(|e| input_error(format!("failed to read {input}: {e}")))?;
+                if file.is_file() && is_image(&file) {
+                    let relative = relative_to(&file, &base);
+                    files.push((file, relative));
+                }
+            }
+        } else if path.is_file() {
+            let name = path.file_name().map(PathBuf::from).unwrap_or_default();
+            files.push((path.to_path_buf(), name));
+        } else {
+            return Err(input_error(format!("{input}: no such file or directory")));
+        }
+
+        files.sort();
+        collected.extend(files);
+    }
+
+    if collected.is_empty() {
+        return Err(input_error("no images found in the given inputs"));
+    }
+
+    Ok(collected)
+}
+
+/// Runs `pipeline` on every job in parallel and collects the result of each one.
+///
+/// Output directories are created as needed. `threads` limits the number of worker threads,
+/// by default one per CPU core is used.
+pub fn run_batch(
+    jobs: Vec<BatchJob>,
+    threads: Option<usize>,
+    encoding: &Encoding,
+    pipeline: &Pipeline,
+) -> BatchReport {
+    let run = || {
+        jobs.into_par_iter()
+            .map(|job| {
+                let result = create_parent(&job.output)
+                    .and_then(|()| process_pipeline(&job.input, &job.output, encoding, pipeline));
+                (job, result)
+            })
+            .collect()
+    };
+
+    let pool = threads.and_then(|threads| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .ok()
+    });
+
+    let results = match pool {
+        Some(pool) => pool.install(run),
+        // Rayon's global pool already uses one thread per core
+        None => run(),
+    };
+
+    BatchReport { results }
+}
+
+fn collect_dir(
+    root: &Path,
+    dir: &Path,
+    files: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), ImageProcessingError> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| input_error(format!("failed to read {}: {e}", dir.display())))?;
+
+    for entry in entries {
+        let path = entry
+            .map_err(|e| input_error(format!("failed to read {}: {e}", dir.display())))?
+            .path();
+
+        if path.is_dir() {
+            collect_dir(root, &path, files)?;
+        } else if is_image(&path) {
+            let relative = relative_to(&path, root);
+            files.push((path, relative));
+        }
+    }
+
+    Ok(())
+}
+
+fn create_parent(output: &Path) -> Result<(), ImageProcessingError> {
+    match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent).map_err(|e| {
+            ImageProcessingError::failed(
+                "write",
+                format!("Failed to create {}: {e}", parent.display()),
+            )
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    ImageFormat::from_path(path).is_ok_and(|format| format.reading_enabled())
+}
+
+fn is_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+/// Returns the directory part of a glob pattern that comes before the first wildcard.
+fn pattern_base(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|component| match component {
+            Component::Normal(part) => !is_pattern(&part.to_string_lossy()),
+            _ => true,
+        })
+        .collect()
+}
+
+/// `path` relative to `base`, or just its file name if it is not under `base`.
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    path.strip_prefix(base)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|_| path.file_name().map(PathBuf::from).unwrap_or_default())
+}
+
+fn input_error(message: impl Into<String>) -> ImageProcessingError {
+    ImageProcessingError::failed("input", message)
+}
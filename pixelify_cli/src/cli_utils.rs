@@ -1,9 +1,18 @@
 //! Utility file for pixelify_cli
 
-use image::{GenericImageView, ImageFormat, RgbaImage};
+use crate::batch::collect_inputs;
+use image::RgbaImage;
 use pixelify_core::PixelifyImage;
+use pixelify_core::animation::{Animation, decode_animation};
+use pixelify_core::atlas::{AtlasOptions, FrameMapLayout, Sprite, pack_atlas};
+use pixelify_core::encode::{EncodeOptions, OutputFormat, encode_animation};
+use pixelify_core::metadata::{Metadata, SourceInfo};
+use pixelify_core::palette::Palette;
+use pixelify_core::pipeline::{Operation, Pipeline};
 use pixelify_core::pixelify_errors::ImageProcessingError;
-use std::path::Path;
+use pixelify_core::quantize::{QuantizeMethod, generate_shared_palette};
+use pixelify_core::tilemap::{TilemapOptions, slice_tilemap};
+use std::path::{Path, PathBuf};
 use std::{fs, io};
 
 /// Clears the `outputs/` directory's contents.
@@ -43,130 +52,392 @@ pub fn clear_outputs() -> io::Result<()> {
     Ok(())
 }
 
+/// How results are encoded when they are written.
+#[derive(Debug, Clone, Default)]
+pub struct Encoding {
+    /// Overrides the format picked from the output file extension.
+    pub format: Option<OutputFormat>,
+    pub options: EncodeOptions,
+    /// Writes a JSON metadata sidecar next to every output, see `metadata_path`.
+    pub metadata: bool,
+}
+
+impl Encoding {
+    /// Returns the format to write `output` in.
+    ///
+    /// Without an explicit format, it is picked from the output extension,
+    /// and files without an extension are written as PNG.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(ImageProcessingError)` if the output has an extension of an unsupported format.
+    pub fn format_for(&self, output: &Path) -> Result<OutputFormat, ImageProcessingError> {
+        if let Some(format) = self.format {
+            return Ok(format);
+        }
+
+        match output.extension() {
+            None => Ok(OutputFormat::Png),
+            Some(extension) => OutputFormat::from_path(output).ok_or_else(|| {
+                ImageProcessingError::failed(
+                    "write",
+                    format!(
+                        "Cannot write .{} files, use --format with one of: png, gif, webp, bmp, tga, qoi, ico",
+                        extension.to_string_lossy()
+                    ),
+                )
+            }),
+        }
+    }
+}
+
 /// Runs an image-processing operation on an input file and writes the result to an output file.
 ///
-/// This helper reads the entire input file into memory, applies the provided operation
-/// to the file bytes, and writes the resulting PNG bytes to the output path.
-///
-/// The operation is provided as a function or closure that takes the input bytes
-/// and returns either encoded PNG bytes or an error.
+/// The operation is provided as a function or closure that takes the decoded RGBA image
+/// and returns either a `PixelifyImage` in any `PixelFormat` or an error.
+/// `operations` describes what it does for the metadata sidecar.
+/// See `process_file` for how the input is read and the output written.
 ///
 /// # Errors
 ///
-/// - Exits the process with a non-zero status if the input file cannot be read
-/// - Exits the process if the operation returns an error
-/// - Panics if the output file cannot be written
-pub fn run_op<F, E>(input: &str, output: &str, op: F)
+/// Exits the process with a non-zero status if the input cannot be read,
+/// the operation fails or the output cannot be written.
+pub fn run_op<F>(input: &str, output: &str, encoding: &Encoding, operations: &[Operation], op: F)
 where
-    F: FnOnce(&[u8]) -> Result<PixelifyImage, E>,
-    E: std::fmt::Display,
+    F: Fn(RgbaImage) -> Result<PixelifyImage, ImageProcessingError>,
 {
-    let mut bytes = fs::read(input).expect("failed to read input");
-
-    // Check if the image type is a png
-    // If not, then convert and then run operation
-    let format = image::guess_format(&bytes).unwrap_or_else(|e| {
-        eprintln!("Format guess failed {}", e);
-        // if the guess fails, default to png
-        ImageFormat::Png
-    });
-
-    // If the bytes are not of type png, convert them into a png format
-    if format != ImageFormat::Png {
-        bytes = into_png(bytes).expect("").into_bytes();
+    if let Err(e) = process_file(
+        Path::new(input),
+        Path::new(output),
+        encoding,
+        operations,
+        op,
+    ) {
+        eprintln!("operation failed: {e}");
+        std::process::exit(1);
     }
+}
 
-    let image = match op(&bytes) {
-        Ok(image) => image,
-        Err(e) => {
-            eprintln!("operation failed: {e}");
-            std::process::exit(1);
-        }
+/// Runs `pipeline` on an input file and writes the result to an output file.
+///
+/// Unlike `run_op`, the frames of an animated input go through the pipeline together,
+/// so adaptive palettes are shared across all frames. See `process_pipeline`.
+///
+/// # Errors
+///
+/// Exits the process with a non-zero status if the input cannot be read,
+/// the pipeline fails or the output cannot be written.
+pub fn run_pipeline(input: &str, output: &str, encoding: &Encoding, pipeline: &Pipeline) {
+    if let Err(e) = process_pipeline(Path::new(input), Path::new(output), encoding, pipeline) {
+        eprintln!("operation failed: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Runs an image-processing operation on one file, returning any failure instead of exiting.
+///
+/// This helper reads the entire input file into memory, decodes it once into RGBA pixels,
+/// applies the provided operation to them, encodes the result as described by `encoding`
+/// and writes it to the output path.
+///
+/// Animated GIFs and APNGs are processed frame by frame, each frame passed to `op`,
+/// and written as an animation with the original delays and loop count.
+///
+/// With `encoding.metadata`, a sidecar recording the source, `operations` and the result is written
+/// to `metadata_path(output)`.
+///
+/// # Errors
+///
+/// Returns `Err(ImageProcessingError)` if the input cannot be read or decoded,
+/// the output format is unknown, the operation fails, or the output cannot be encoded or written.
+pub fn process_file<F>(
+    input: &Path,
+    output: &Path,
+    encoding: &Encoding,
+    operations: &[Operation],
+    op: F,
+) -> Result<(), ImageProcessingError>
+where
+    F: Fn(RgbaImage) -> Result<PixelifyImage, ImageProcessingError>,
+{
+    process(input, output, encoding, operations, &op, |animation| {
+        let frames = animation
+            .images()
+            .into_iter()
+            .map(|frame| op(frame)?.to_rgba_image())
+            .collect::<Result<Vec<_>, _>>()?;
+        animation.with_images(frames)
+    })
+}
+
+/// Runs `pipeline` on one file, returning any failure instead of exiting.
+///
+/// Works like `process_file`, except that all frames of an animated GIF or APNG
+/// go through `Pipeline::run_animation` together and share one palette.
+///
+/// # Errors
+///
+/// Returns `Err(ImageProcessingError)` if the input cannot be read or decoded,
+/// the output format is unknown, the pipeline fails, or the output cannot be encoded or written.
+pub fn process_pipeline(
+    input: &Path,
+    output: &Path,
+    encoding: &Encoding,
+    pipeline: &Pipeline,
+) -> Result<(), ImageProcessingError> {
+    process(
+        input,
+        output,
+        encoding,
+        pipeline.get_operations(),
+        |image| pipeline.run_rgba(image).map(PixelifyImage::from),
+        |animation| pipeline.run_animation(animation),
+    )
+}
+
+fn process<S, A>(
+    input: &Path,
+    output: &Path,
+    encoding: &Encoding,
+    operations: &[Operation],
+    still: S,
+    animated: A,
+) -> Result<(), ImageProcessingError>
+where
+    S: FnOnce(RgbaImage) -> Result<PixelifyImage, ImageProcessingError>,
+    A: FnOnce(Animation) -> Result<Animation, ImageProcessingError>,
+{
+    // Fail before doing any work if the output cannot be written anyway
+    let output_format = encoding.format_for(output)?;
+
+    let bytes = fs::read(input).map_err(|e| {
+        ImageProcessingError::failed("read", format!("Failed to read {}: {e}", input.display()))
+    })?;
+
+    let source = if encoding.metadata {
+        Some(SourceInfo::new(&bytes)?)
+    } else {
+        None
     };
 
-    let png_bytes = match write_to_png_format(image) {
-        Ok(png_bytes) => png_bytes,
-        Err(e) => {
-            eprintln!("operation failed: {e}");
-            std::process::exit(1);
-        }
+    let encoded = if let Some(animation) = decode_animation(&bytes)? {
+        let animation = animated(animation)?;
+        encode_animation(&animation, output_format, &encoding.options)?
+    } else {
+        let decoded = image::load_from_memory(&bytes)
+            .map_err(|_| {
+                ImageProcessingError::failed(
+                    "decode",
+                    format!("Failed to decode {}", input.display()),
+                )
+            })?
+            .to_rgba8();
+
+        still(decoded)?.encode(output_format, &encoding.options)?
     };
 
-    fs::write(output, png_bytes.as_bytes()).expect("failed to write output");
+    write_file(output, &encoded)?;
+
+    if let Some(source) = source {
+        let source = SourceInfo {
+            file: Some(input.display().to_string()),
+            ..source
+        };
+        let mut metadata = Metadata::from_encoded(source, operations, &encoded)?;
+        metadata.output.file = Some(output.display().to_string());
+        metadata.output.format = Some(output_format.to_string());
+        write_file(&metadata_path(output), metadata.to_json()?)?;
+    }
+
+    Ok(())
+}
+
+/// Where the metadata sidecar of `output` is written: next to it, as `NAME.meta.json`.
+pub fn metadata_path(output: &Path) -> PathBuf {
+    output.with_extension("meta.json")
 }
 
-/// Encodes a `PixelifyImage` (raw RGBA pixels) into PNG file bytes.
+/// Replaces the seed of a k-means quantizer, other methods are returned as is.
+pub fn with_seed(method: QuantizeMethod, seed: u64) -> QuantizeMethod {
+    match method {
+        QuantizeMethod::KMeans { .. } => QuantizeMethod::KMeans { seed },
+        other => other,
+    }
+}
+
+/// Loads a palette file, `.gpl` files are read as GIMP palettes and everything else as `.hex`.
 ///
-/// This function treats `pixelify_image.as_bytes()` as a **raw RGBA buffer**
-/// (4 bytes per pixel, row-major order) and encodes it into the **PNG file
-/// format**. The returned `PixelifyImage` contains **PNG-encoded bytes**, not
-/// raw pixel bytes.
+/// # Errors
 ///
-/// The dimensions are preserved (`width`/`height` are copied over).
+/// Returns `Err(ImageProcessingError)` if the file cannot be read or parsed.
+pub fn load_palette(path: &str) -> Result<Palette, ImageProcessingError> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        ImageProcessingError::failed("palette", format!("Failed to read {path}: {e}"))
+    })?;
+
+    if is_gpl(path) {
+        Palette::from_gpl(&text)
+    } else {
+        let name = Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("hex"));
+        Palette::from_hex(name, &text)
+    }
+}
+
+/// Derives one palette from all `inputs` and writes it to `output` as `.gpl` or `.hex`.
 ///
 /// # Errors
 ///
-/// Returns `Err(ImageProcessingError)` if:
-/// - the input buffer length does not match `width * height * 4` (invalid RGBA buffer),
-/// - PNG encoding fails.
-///
-/// # Notes
-///
-/// - This does **not** modify the original image in-place; it creates a new
-///   byte buffer containing a PNG file (headers + compressed image data).
-/// - After calling this, `PixelifyImage::as_bytes()` is **not** raw pixels
-///   anymore, so only use this at the “output boundary” (save/send/download)
-///   unless your type explicitly tracks the encoding.
-fn write_to_png_format(
-    pixelify_image: PixelifyImage,
-) -> Result<PixelifyImage, ImageProcessingError> {
-    let rgba = RgbaImage::from_raw(
-        pixelify_image.get_width(),
-        pixelify_image.get_height(),
-        pixelify_image.as_bytes().to_vec(),
-    )
-    .ok_or_else(|| ImageProcessingError::failed("pixelify", "Bad buffer length"))?;
+/// Returns `Err(ImageProcessingError)` if an input cannot be decoded,
+/// the palette cannot be generated, or the output cannot be written.
+pub fn run_generate_palette(
+    inputs: &[String],
+    output: &str,
+    colors: usize,
+    method: QuantizeMethod,
+) -> Result<(), ImageProcessingError> {
+    let images = inputs
+        .iter()
+        .map(|input| decode_rgba(input))
+        .collect::<Result<Vec<_>, _>>()?;
+    let image_refs: Vec<&PixelifyImage> = images.iter().collect();
 
-    let mut cursor = io::Cursor::new(Vec::new());
+    let palette = generate_shared_palette(&image_refs, colors, method)?;
 
-    rgba.write_to(&mut cursor, ImageFormat::Png)
-        .map_err(|_| ImageProcessingError::failed("crop", "Failed to encode PNG"))?;
+    let contents = if is_gpl(output) {
+        palette.to_gpl()
+    } else {
+        palette.to_hex()
+    };
 
-    Ok(PixelifyImage::new(
-        cursor.into_inner(),
-        pixelify_image.get_width(),
-        pixelify_image.get_height(),
-    ))
+    fs::write(output, contents).map_err(|e| {
+        ImageProcessingError::failed("generate_palette", format!("Failed to write {output}: {e}"))
+    })
 }
 
-/// Decodes an image from memory (auto-detecting its format) and re-encodes it as PNG.
+/// Packs every image found in `inputs` into one atlas.
 ///
-/// This function accepts image file bytes in any format supported by the `image` crate
-/// (e.g., PNG, JPEG, GIF, WebP, etc.), decodes them into pixels, then encodes the result
-/// into **PNG file bytes**. The returned [`PixelifyImage`] contains **PNG-encoded bytes**
-/// (not raw RGBA pixels).
-///
-/// The returned `width` and `height` are taken from the decoded image.
+/// Each sprite is first run through `pipeline`, if given. The atlas image is written to `output`,
+/// and its frame map to `data`, with sprites named by their path relative to the input they were found through.
 ///
 /// # Errors
 ///
-/// Returns `Err(ImageProcessingError)` if:
-/// - the input bytes cannot be decoded as a supported image format,
-/// - PNG encoding fails.
+/// Returns `Err(ImageProcessingError)` if `encoding.metadata` is set, an input cannot be found or decoded,
+/// the pipeline fails, the sprites cannot be packed, or the outputs cannot be encoded or written.
+pub fn run_atlas(
+    inputs: &[String],
+    output: &Path,
+    data: &Path,
+    layout: FrameMapLayout,
+    options: &AtlasOptions,
+    pipeline: Option<&Pipeline>,
+    encoding: &Encoding,
+) -> Result<(), ImageProcessingError> {
+    reject_metadata(encoding, "atlas")?;
+    let output_format = encoding.format_for(output)?;
+
+    let sprites = collect_inputs(inputs)?
+        .into_iter()
+        .map(|(file, relative)| {
+            let image = decode_rgba(&file.to_string_lossy())?;
+            Ok(Sprite {
+                name: relative.to_string_lossy().replace('\\', "/"),
+                image: match pipeline {
+                    Some(pipeline) => pipeline.run_image(&image)?,
+                    None => image,
+                },
+            })
+        })
+        .collect::<Result<Vec<_>, ImageProcessingError>>()?;
+
+    let atlas = pack_atlas(&sprites, options)?;
+
+    let image_name = output
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let frame_map = atlas.frame_map(layout, &image_name)?;
+    let encoded = atlas.image.encode(output_format, &encoding.options)?;
+
+    write_file(output, encoded)?;
+    write_file(data, frame_map)
+}
+
+/// Cuts `input` into a deduplicated tileset written to `output`, with its map written as JSON to `map`
+/// and as CSV to `csv`.
 ///
-/// # Notes
+/// The image is first run through `pipeline`, if given.
 ///
-/// - This is a **format conversion** step (file bytes → pixels → PNG file bytes).
-pub fn into_png(bytes: Vec<u8>) -> Result<PixelifyImage, ImageProcessingError> {
-    let image = image::load_from_memory(&bytes)
-        .map_err(|_| ImageProcessingError::failed("into_png", "Failed to decode input image"))?;
+/// # Errors
+///
+/// Returns `Err(ImageProcessingError)` if `encoding.metadata` is set, the input cannot be read or decoded,
+/// the pipeline fails, the image cannot be sliced, or the outputs cannot be encoded or written.
+pub fn run_tilemap(
+    input: &str,
+    output: &Path,
+    map: &Path,
+    csv: &Path,
+    options: &TilemapOptions,
+    pipeline: Option<&Pipeline>,
+    encoding: &Encoding,
+) -> Result<(), ImageProcessingError> {
+    reject_metadata(encoding, "tilemap")?;
+    let output_format = encoding.format_for(output)?;
+
+    let image = decode_rgba(input)?;
+    let image = match pipeline {
+        Some(pipeline) => pipeline.run_image(&image)?,
+        None => image,
+    };
+
+    let tilemap = slice_tilemap(&image, options)?;
+
+    let tileset_name = output
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let json = tilemap.to_json(&tileset_name)?;
+    let encoded = tilemap.tileset.encode(output_format, &encoding.options)?;
+
+    write_file(output, encoded)?;
+    write_file(map, json)?;
+    write_file(csv, tilemap.to_csv())
+}
+
+// A sidecar describes one source run through operations, which atlases and tilemaps are not
+fn reject_metadata(encoding: &Encoding, command: &str) -> Result<(), ImageProcessingError> {
+    if encoding.metadata {
+        return Err(ImageProcessingError::failed(
+            "metadata",
+            format!("--metadata is not supported by the {command} command"),
+        ));
+    }
+    Ok(())
+}
 
-    let (width, height) = image.dimensions();
+fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), ImageProcessingError> {
+    fs::write(path, contents).map_err(|e| {
+        ImageProcessingError::failed("write", format!("Failed to write {}: {e}", path.display()))
+    })
+}
+
+fn is_gpl(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gpl"))
+}
 
-    let mut cursor = io::Cursor::new(Vec::new());
-    image
-        .write_to(&mut cursor, ImageFormat::Png)
-        .map_err(|_| ImageProcessingError::failed("into_png", "Failed to encode PNG"))?;
+/// Reads an image file of any supported format into a raw RGBA `PixelifyImage`.
+fn decode_rgba(path: &str) -> Result<PixelifyImage, ImageProcessingError> {
+    let bytes = fs::read(path).map_err(|e| {
+        ImageProcessingError::failed("decode", format!("Failed to read {path}: {e}"))
+    })?;
+
+    let image = image::load_from_memory(&bytes)
+        .map_err(|_| ImageProcessingError::failed("decode", format!("Failed to decode {path}")))?
+        .to_rgba8();
 
-    Ok(PixelifyImage::new(cursor.into_inner(), width, height))
+    Ok(PixelifyImage::from(image))
 }
@@ -2,39 +2,219 @@
 //! Pixelify is a Rust + WebAssembly or CLI tool that converts normal images into pixel-art sprites.
 //! As well as having some more basic editing features like a crop or grayscale functionality, for example.
 
-use clap::{Parser, Subcommand};
-use pixelify_core::crop::crop_png;
-use pixelify_core::grayscale::grayscale_png;
-use pixelify_core::pixelify::*;
+use clap::{Args, Parser, Subcommand};
+use pixelify_core::PixelifyImage;
+use pixelify_core::atlas::{AtlasOptions, FrameMapLayout, PackingMethod};
+use pixelify_core::background::{BackgroundKey, BackgroundOptions, DEFAULT_TOLERANCE};
+use pixelify_core::color::{ColorMetric, parse_hex_color};
+use pixelify_core::dither::DitherMethod;
+use pixelify_core::encode::{
+    EncodeOptions, GifOptions, IcoOptions, OutputFormat, PngOptions, TgaOptions,
+};
+use pixelify_core::grayscale::grayscale;
+use pixelify_core::outline::{Connectivity, OutlineColor, OutlineOptions, OutlinePlacement};
+use pixelify_core::palette::{BuiltinPalette, Palette};
+use pixelify_core::pipeline::{Operation, PaletteSource, Pipeline};
+use pixelify_core::pixelify::{
+    AlphaMode, BlockReducer, BlockSize, EdgePolicy, PixelifyOptions, ResizeMode,
+};
+use pixelify_core::pixelify_errors::ImageProcessingError;
+use pixelify_core::quantize::QuantizeMethod;
+use pixelify_core::tilemap::{TileMatching, TilemapOptions};
+use pixelify_core::upscale::{DEFAULT_GRID_COLOR, UpscaleMethod, UpscaleOptions, UpscaleSize};
+use std::path::{Path, PathBuf};
+mod batch;
 mod cli_utils;
+mod recipe;
+use batch::{collect_jobs, run_batch};
 use cli_utils::*;
+use recipe::load_recipe;
 
 fn main() {
     let cli = Cli::parse();
+    let encoding = cli.encode.encoding().unwrap_or_else(|e| {
+        eprintln!("operation failed: {e}");
+        std::process::exit(1);
+    });
 
     match cli.cmd {
         Command::DownscaleByPixelSize {
             input,
             output,
             pixel_size,
-        } => run_op(&input, &output, |b| {
-            pixelify_downscale_by_pixel_size(b, pixel_size)
-        }),
+            pixelify,
+            palette,
+        } => {
+            let pipeline = Pipeline::new().downscale_by_pixel_size(pixel_size, pixelify.options());
+            let pipeline = or_exit(palette.append_to(pipeline));
+            run_pipeline(&input, &output, &encoding, &pipeline);
+        }
         Command::FalseDownscaleByPixelSize {
             input,
             output,
             pixel_size,
-        } => run_op(&input, &output, |b| {
-            pixelify_false_downscale_by_pixel_size(b, pixel_size)
-        }),
+            pixelify,
+            palette,
+        } => {
+            let pipeline =
+                Pipeline::new().false_downscale_by_pixel_size(pixel_size, pixelify.options());
+            let pipeline = or_exit(palette.append_to(pipeline));
+            run_pipeline(&input, &output, &encoding, &pipeline);
+        }
         Command::DownscaleByImageSize {
             input,
             output,
             width,
             height,
-        } => run_op(&input, &output, |b| {
-            pixelify_by_image_size(b, width, height)
-        }),
+            resize,
+            pixelify,
+            palette,
+        } => {
+            let options = PixelifyOptions {
+                resize,
+                ..pixelify.options()
+            };
+            let pipeline = Pipeline::new().then(Operation::DownscaleByImageSize {
+                width,
+                height,
+                options,
+            });
+            let pipeline = or_exit(palette.append_to(pipeline));
+            run_pipeline(&input, &output, &encoding, &pipeline);
+        }
+        Command::Apply {
+            input,
+            output,
+            recipe,
+        } => run_pipeline(&input, &output, &encoding, &or_exit(load_recipe(&recipe))),
+        Command::Batch {
+            inputs,
+            output_dir,
+            recipe,
+            jobs,
+        } => {
+            let pipeline = load_recipe(&recipe).unwrap_or_else(|e| {
+                eprintln!("operation failed: {e}");
+                std::process::exit(1);
+            });
+            let extension = encoding.format.unwrap_or_default().extension();
+            let batch =
+                collect_jobs(&inputs, Path::new(&output_dir), extension).unwrap_or_else(|e| {
+                    eprintln!("operation failed: {e}");
+                    std::process::exit(1);
+                });
+
+            let report = run_batch(batch, jobs, &encoding, &pipeline);
+            report.print_summary();
+            if report.failed() > 0 {
+                std::process::exit(1);
+            }
+        }
+        Command::Atlas {
+            inputs,
+            output,
+            data,
+            packing,
+            columns,
+            trim,
+            padding,
+            extrude,
+            pot,
+            layout,
+            recipe,
+        } => {
+            let output = Path::new(&output);
+            let data = data
+                .map(PathBuf::from)
+                .unwrap_or_else(|| output.with_extension("json"));
+            let options = AtlasOptions {
+                packing,
+                columns,
+                trim,
+                padding,
+                extrude,
+                power_of_two: pot,
+            };
+
+            let result = recipe
+                .map(|recipe| load_recipe(&recipe))
+                .transpose()
+                .and_then(|pipeline| {
+                    run_atlas(
+                        &inputs,
+                        output,
+                        &data,
+                        layout,
+                        &options,
+                        pipeline.as_ref(),
+                        &encoding,
+                    )
+                });
+            if let Err(e) = result {
+                eprintln!("operation failed: {e}");
+                std::process::exit(1);
+            }
+        }
+        Command::Tilemap {
+            input,
+            output,
+            tile_size,
+            tile_width,
+            tile_height,
+            matching,
+            skip_empty,
+            columns,
+            map,
+            csv,
+            recipe,
+        } => {
+            let output = Path::new(&output);
+            let map = map
+                .map(PathBuf::from)
+                .unwrap_or_else(|| output.with_extension("json"));
+            let csv = csv
+                .map(PathBuf::from)
+                .unwrap_or_else(|| output.with_extension("csv"));
+            let options = TilemapOptions {
+                tile_width: tile_width.unwrap_or(tile_size),
+                tile_height: tile_height.unwrap_or(tile_size),
+                matching,
+                skip_empty,
+                columns,
+            };
+
+            let result = recipe
+                .map(|recipe| load_recipe(&recipe))
+                .transpose()
+                .and_then(|pipeline| {
+                    run_tilemap(
+                        &input,
+                        output,
+                        &map,
+                        &csv,
+                        &options,
+                        pipeline.as_ref(),
+                        &encoding,
+                    )
+                });
+            if let Err(e) = result {
+                eprintln!("operation failed: {e}");
+                std::process::exit(1);
+            }
+        }
+        Command::GeneratePalette {
+            inputs,
+            output,
+            colors,
+            method,
+            seed,
+        } => {
+            if let Err(e) = run_generate_palette(&inputs, &output, colors, with_seed(method, seed))
+            {
+                eprintln!("operation failed: {e}");
+                std::process::exit(1);
+            }
+        }
         Command::ClearOutputs => {
             if let Err(e) = clear_outputs() {
                 eprintln!("{e}");
@@ -43,7 +223,13 @@ fn main() {
         }
 
         Command::Grayscale { input, output } => {
-            run_op(&input, &output, |b| grayscale_png(b));
+            run_op(
+                &input,
+                &output,
+                &encoding,
+                &[Operation::Grayscale],
+                |image| grayscale(&PixelifyImage::from(image)),
+            );
         }
 
         Command::Crop {
@@ -54,44 +240,334 @@ fn main() {
             w,
             h,
         } => {
-            run_op(&input, &output, |b| crop_png(b, x, y, w, h));
+            run_pipeline(
+                &input,
+                &output,
+                &encoding,
+                &Pipeline::new().crop(x, y, w, h),
+            );
+        }
+        Command::Upscale {
+            input,
+            output,
+            method,
+            factor,
+            fit_width,
+            fit_height,
+            grid,
+            grid_color,
+        } => {
+            let size = match factor {
+                Some(factor) => UpscaleSize::Factor(factor),
+                // clap requires both fit options whenever factor is missing
+                None => UpscaleSize::Fit {
+                    width: fit_width.unwrap_or_default(),
+                    height: fit_height.unwrap_or_default(),
+                },
+            };
+            let options = UpscaleOptions {
+                method,
+                pixel_grid: grid_color.or(grid.then_some(DEFAULT_GRID_COLOR)),
+            };
+            run_pipeline(
+                &input,
+                &output,
+                &encoding,
+                &Pipeline::new().upscale(size, options),
+            );
         }
-        _ => {
-            // So the match statement doesn't get angry when adding in new enums
-            // without adding in their arm into the match statement immediately
-            eprintln!("Unsupported or command not found");
+        Command::RemoveBackground {
+            input,
+            output,
+            color,
+            seeds,
+            tolerance,
+            feather,
+        } => {
+            let key = match (color, seeds.is_empty()) {
+                (Some([r, g, b, _]), _) => BackgroundKey::Color([r, g, b]),
+                (None, false) => BackgroundKey::Seeds(seeds),
+                (None, true) => BackgroundKey::Corners,
+            };
+            let options = BackgroundOptions {
+                key,
+                tolerance,
+                feather,
+            };
+            run_pipeline(
+                &input,
+                &output,
+                &encoding,
+                &Pipeline::new().remove_background(options),
+            );
+        }
+        Command::Outline {
+            input,
+            output,
+            placement,
+            connectivity,
+            thickness,
+            color,
+        } => {
+            let options = OutlineOptions {
+                placement,
+                connectivity,
+                thickness,
+                color,
+            };
+            run_pipeline(
+                &input,
+                &output,
+                &encoding,
+                &Pipeline::new().outline(options),
+            );
+        }
+        Command::IntoPng { input, output } => {
+            // Decoding and encoding as the output format is the whole conversion
+            run_op(&input, &output, &encoding, &[], |image| {
+                Ok(PixelifyImage::from(image))
+            });
         }
     }
 }
 
+/// Parses an `X,Y` point.
+fn parse_point(s: &str) -> Result<(u32, u32), String> {
+    let error = || format!("invalid point '{s}', expected X,Y such as 0,0");
+    let (x, y) = s.split_once(',').ok_or_else(error)?;
+    let parse = |part: &str| part.trim().parse::<u32>().map_err(|_| error());
+    Ok((parse(x)?, parse(y)?))
+}
+
+/// Returns the value, or prints the error and exits with a non-zero status.
+fn or_exit<T>(result: Result<T, ImageProcessingError>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("operation failed: {e}");
+        std::process::exit(1);
+    })
+}
+
 #[derive(Parser)]
-#[command(author, version, about)]
+#[command(
+    author,
+    version,
+    about = "Convert images into crisp pixel-art sprites, and prepare them for games"
+)]
 struct Cli {
     #[command(subcommand)]
     cmd: Command,
+    #[command(flatten)]
+    encode: EncodeArgs,
+}
+
+// Output encoding options, accepted by every command that writes an image.
+#[derive(Args)]
+struct EncodeArgs {
+    /// Output format (png, gif, webp, bmp, tga, qoi, ico), picked from the output extension by default
+    #[arg(long, global = true)]
+    format: Option<OutputFormat>,
+    /// Also write a JSON metadata sidecar (NAME.meta.json) with the source hash, operations and palette.
+    /// Not supported by atlas and tilemap
+    #[arg(long, global = true)]
+    metadata: bool,
+    /// PNG: write a paletted PNG instead of RGBA
+    #[arg(long, global = true)]
+    png_indexed: bool,
+    /// PNG: palette of the indexed PNG, in this exact order (nes, gameboy, pico8, cga or a .hex/.gpl file).
+    /// By default the image's own colors are used
+    #[arg(long, global = true, requires = "png_indexed")]
+    png_palette: Option<String>,
+    /// PNG: bits per pixel of the indexed PNG (1, 2, 4 or 8), the smallest that fits by default
+    #[arg(long, global = true, requires = "png_indexed")]
+    png_bit_depth: Option<u8>,
+    /// PNG: palette entry used for transparent pixels, by default one is added after the palette
+    #[arg(long, global = true, requires = "png_palette")]
+    png_transparent_index: Option<u8>,
+    /// PNG: with --png-palette, pixels with an alpha below this become transparent
+    #[arg(long, global = true, default_value_t = 128, requires = "png_palette")]
+    png_alpha_threshold: u8,
+    /// GIF: palette index used for transparent pixels
+    #[arg(long, global = true)]
+    gif_transparent_index: Option<u8>,
+    /// GIF: pixels with an alpha below this become transparent
+    #[arg(long, global = true, default_value_t = 128)]
+    gif_alpha_threshold: u8,
+    /// TGA: write uncompressed pixels instead of run-length encoding them
+    #[arg(long, global = true)]
+    tga_no_rle: bool,
+    /// ICO: comma separated icon sizes from 1 to 256, e.g. 16,32,48,256
+    #[arg(long, global = true, value_delimiter = ',')]
+    ico_sizes: Vec<u32>,
+}
+
+impl EncodeArgs {
+    fn encoding(&self) -> Result<Encoding, ImageProcessingError> {
+        let palette = match &self.png_palette {
+            Some(name) => Some(match name.parse::<BuiltinPalette>() {
+                Ok(builtin) => Palette::builtin(builtin),
+                Err(_) => load_palette(name)?,
+            }),
+            None => None,
+        };
+
+        Ok(Encoding {
+            format: self.format,
+            metadata: self.metadata,
+            options: EncodeOptions {
+                png: PngOptions {
+                    indexed: self.png_indexed,
+                    palette,
+                    bit_depth: self.png_bit_depth,
+                    transparent_index: self.png_transparent_index,
+                    alpha_threshold: self.png_alpha_threshold,
+                },
+                gif: GifOptions {
+                    transparent_index: self.gif_transparent_index,
+                    alpha_threshold: self.gif_alpha_threshold,
+                },
+                tga: TgaOptions {
+                    rle: !self.tga_no_rle,
+                },
+                ico: IcoOptions {
+                    sizes: self.ico_sizes.clone(),
+                },
+            },
+        })
+    }
+}
+
+// Block options shared by the pixelify commands. A `///` here would become the about text of those commands.
+#[derive(Args)]
+struct PixelifyArgs {
+    /// Average block colors in linear light, which keeps mixed blocks from darkening
+    #[arg(long)]
+    linear: bool,
+    /// How each block is reduced to one color (mean, median, mode, dominant, center, darkest, brightest).
+    /// Line art keeps its lines best with mode or darkest
+    #[arg(long, default_value = "mean")]
+    reducer: BlockReducer,
+    /// What happens to the pixels left over when the image size is not a multiple of the pixel size
+    /// (crop, center, partial, pad-replicate, pad-transparent, pad-color:RRGGBB).
+    /// False downscales keep the image size, crop and center leave the leftover pixels as they were
+    #[arg(long, default_value = "crop")]
+    edges: EdgePolicy,
+    /// Snaps block alpha for engines without semi-transparency: keep, threshold (at 128), threshold:N to make
+    /// blocks with alpha N or more opaque and the rest transparent, or levels:N for N evenly spaced alpha levels
+    #[arg(long, default_value = "keep")]
+    alpha: AlphaMode,
+}
+
+impl PixelifyArgs {
+    fn options(&self) -> PixelifyOptions {
+        PixelifyOptions {
+            linear_light: self.linear,
+            reducer: self.reducer,
+            edges: self.edges,
+            alpha: self.alpha,
+            ..PixelifyOptions::default()
+        }
+    }
+}
+
+// Palette reduction options shared by the pixelify commands.
+#[derive(Args)]
+#[group(skip)]
+struct PaletteArgs {
+    /// Limit the output to a built-in palette (nes, gameboy, pico8, cga)
+    #[arg(long, group = "palette_source")]
+    palette: Option<BuiltinPalette>,
+    /// Limit the output to a palette file (.hex or .gpl)
+    #[arg(long, group = "palette_source")]
+    palette_file: Option<String>,
+    /// Limit the output to an adaptive palette of this many colors, derived from the image
+    #[arg(long, group = "palette_source")]
+    colors: Option<usize>,
+    /// Algorithm used to derive the adaptive palette (median-cut, octree, kmeans)
+    #[arg(long, default_value = "median-cut", requires = "colors")]
+    quantize: QuantizeMethod,
+    /// Seed for the kmeans quantizer
+    #[arg(long, default_value_t = 0, requires = "colors")]
+    seed: u64,
+    /// How nearest palette colors are matched (rgb, oklab, ciede2000)
+    #[arg(long, default_value = "oklab", requires = "palette_source")]
+    metric: ColorMetric,
+    /// Dither while mapping to the palette (bayer2x2, bayer4x4, bayer8x8, floyd-steinberg,
+    /// atkinson, jarvis-judice-ninke, sierra)
+    #[arg(long, requires = "palette_source")]
+    dither: Option<DitherMethod>,
+    /// How strongly to dither, from 0.0 to 1.0
+    #[arg(long, default_value_t = 1.0, requires = "dither")]
+    dither_strength: f32,
+}
+
+impl PaletteArgs {
+    /// Appends the requested palette or dither step, if any, to `pipeline`.
+    fn append_to(&self, pipeline: Pipeline) -> Result<Pipeline, ImageProcessingError> {
+        let source = if let Some(builtin) = self.palette {
+            PaletteSource::Fixed(Palette::builtin(builtin).with_metric(self.metric))
+        } else if let Some(path) = &self.palette_file {
+            PaletteSource::Fixed(load_palette(path)?.with_metric(self.metric))
+        } else if let Some(colors) = self.colors {
+            PaletteSource::Adaptive {
+                colors,
+                method: with_seed(self.quantize, self.seed),
+                metric: self.metric,
+            }
+        } else {
+            return Ok(pipeline);
+        };
+
+        Ok(match self.dither {
+            Some(method) => pipeline.dither(source, method, self.dither_strength),
+            None => pipeline.then(Operation::Palette(source)),
+        })
+    }
 }
 
 #[derive(Subcommand)]
 enum Command {
+    /// Average each block of pixels into one pixel, shrinking the image by the block size
     DownscaleByPixelSize {
         input: String,
         output: String,
+        /// Block size in source pixels, N for square blocks or WxH, such as 2x1 for wide C64 pixels
         #[arg(long)]
-        pixel_size: u32,
+        pixel_size: BlockSize,
+        #[command(flatten)]
+        pixelify: PixelifyArgs,
+        #[command(flatten)]
+        palette: PaletteArgs,
     },
+    /// Average each block of pixels into one color, keeping the block at its original size
     FalseDownscaleByPixelSize {
         input: String,
         output: String,
+        /// Block size in source pixels, N for square blocks or WxH, such as 2x1 for wide C64 pixels
         #[arg(long)]
-        pixel_size: u32,
+        pixel_size: BlockSize,
+        #[command(flatten)]
+        pixelify: PixelifyArgs,
+        #[command(flatten)]
+        palette: PaletteArgs,
     },
+    /// Resample the image to a given output size, averaging the source pixels under each output pixel
     DownscaleByImageSize {
         input: String,
         output: String,
+        /// Output width, the height follows the aspect ratio when it is not given
+        #[arg(long, required_unless_present = "height")]
+        width: Option<u32>,
+        /// Output height, the width follows the aspect ratio when it is not given
         #[arg(long)]
-        width: u32,
-        #[arg(long)]
-        height: u32,
+        height: Option<u32>,
+        /// How a size of another aspect ratio is reached: stretch, fit with transparent bars,
+        /// fit:RRGGBB with colored bars, fill cropping the center, or fill:ANCHOR such as fill:top-left
+        #[arg(long, default_value = "stretch")]
+        resize: ResizeMode,
+        #[command(flatten)]
+        pixelify: PixelifyArgs,
+        #[command(flatten)]
+        palette: PaletteArgs,
     },
     Grayscale {
         input: String,
@@ -109,6 +585,65 @@ enum Command {
         #[arg(long)]
         h: u32,
     },
+    /// Scale pixel art up by a whole factor
+    #[command(group(clap::ArgGroup::new("size").required(true).args(["factor", "fit_width"])))]
+    Upscale {
+        input: String,
+        output: String,
+        /// nearest (any factor), epx (Scale2x/Scale3x, factors made of 2s and 3s),
+        /// hqx or xbr (factors 2 to 4)
+        #[arg(long, default_value = "nearest")]
+        method: UpscaleMethod,
+        /// Turn every pixel into a factor x factor block
+        #[arg(long)]
+        factor: Option<u32>,
+        /// Use the largest factor that fits within this width (and --fit-height)
+        #[arg(long, requires = "fit_height")]
+        fit_width: Option<u32>,
+        #[arg(long, requires = "fit_width")]
+        fit_height: Option<u32>,
+        /// Draw a pixel grid between the upscaled pixels
+        #[arg(long)]
+        grid: bool,
+        /// Color of the pixel grid as RRGGBB or RRGGBBAA, implies --grid
+        #[arg(long, value_parser = parse_hex_color)]
+        grid_color: Option<[u8; 4]>,
+    },
+    /// Make a flat or green-screen background transparent, flood filling from the corners by default
+    RemoveBackground {
+        input: String,
+        output: String,
+        /// Remove every pixel close to this RRGGBB color, anywhere in the image
+        #[arg(long, value_parser = parse_hex_color, conflicts_with = "seeds")]
+        color: Option<[u8; 4]>,
+        /// Flood fill from this X,Y point instead of the corners, can be repeated
+        #[arg(long = "seed", value_parser = parse_point)]
+        seeds: Vec<(u32, u32)>,
+        /// How far in OKLab a color may be from the background color, 0.02 is barely visible
+        #[arg(long, default_value_t = DEFAULT_TOLERANCE)]
+        tolerance: f32,
+        /// Fade the edge of the kept pixels over this many pixels
+        #[arg(long, default_value_t = 0)]
+        feather: u32,
+    },
+    /// Draw an outline around the opaque shapes of a sprite
+    Outline {
+        input: String,
+        output: String,
+        /// outer draws around the sprite, inner along the inside of its edge
+        #[arg(long, default_value = "outer")]
+        placement: OutlinePlacement,
+        /// 4 leaves the corners open, 8 closes them
+        #[arg(long, default_value = "4")]
+        connectivity: Connectivity,
+        /// Width of the outline in pixels
+        #[arg(long, default_value_t = 1)]
+        thickness: u32,
+        /// RRGGBB or RRGGBBAA, or shade for a darker shade of the neighboring sprite color
+        /// (shade:AMOUNT from 0.0 to 1.0, 0.5 by default)
+        #[arg(long, default_value = "000000")]
+        color: OutlineColor,
+    },
     #[command(
         visible_alias = "convert",
         visible_alias = "ConvertToPng",
@@ -119,6 +654,112 @@ enum Command {
         input: String,
         output: String,
     },
+    /// Run the steps of a .toml or .json recipe file
+    Apply {
+        input: String,
+        output: String,
+        #[arg(long)]
+        recipe: String,
+    },
+    /// Run a recipe over every image in some directories or glob patterns, in parallel
+    Batch {
+        /// Directories (searched recursively), glob patterns or files
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Where the results are written, mirroring the input directories
+        #[arg(long)]
+        output_dir: String,
+        #[arg(long)]
+        recipe: String,
+        /// Number of worker threads, one per CPU core by default
+        #[arg(long)]
+        jobs: Option<usize>,
+    },
+    /// Pack many sprites into one texture atlas, along with a TexturePacker style JSON frame map
+    Atlas {
+        /// Directories (searched recursively), glob patterns or files
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// The atlas image
+        #[arg(long)]
+        output: String,
+        /// The JSON frame map, the output path with a .json extension by default
+        #[arg(long)]
+        data: Option<String>,
+        /// grid, maxrects or skyline
+        #[arg(long, default_value = "maxrects")]
+        packing: PackingMethod,
+        /// Number of grid columns, as square as possible by default
+        #[arg(long)]
+        columns: Option<u32>,
+        /// Crop the transparent border off every sprite
+        #[arg(long)]
+        trim: bool,
+        /// Transparent pixels between sprites and around the atlas
+        #[arg(long, default_value_t = 0)]
+        padding: u32,
+        /// Repeat the edge pixels of every sprite this many times
+        #[arg(long, default_value_t = 0)]
+        extrude: u32,
+        /// Round the atlas size up to powers of two
+        #[arg(long)]
+        pot: bool,
+        /// Layout of the frame map (hash or array)
+        #[arg(long, default_value = "hash")]
+        layout: FrameMapLayout,
+        /// Run every sprite through a recipe before packing
+        #[arg(long)]
+        recipe: Option<String>,
+    },
+    /// Cut an image into a deduplicated tileset, plus a map of Tiled style tile IDs as JSON and CSV
+    Tilemap {
+        input: String,
+        /// The tileset image
+        #[arg(long)]
+        output: String,
+        /// Width and height of the tiles
+        #[arg(long, default_value_t = 16)]
+        tile_size: u32,
+        /// Tile width, overriding --tile-size
+        #[arg(long)]
+        tile_width: Option<u32>,
+        /// Tile height, overriding --tile-size
+        #[arg(long)]
+        tile_height: Option<u32>,
+        /// Which copies count as the same tile: exact, flips, or rotations (flips and 90 degree turns)
+        #[arg(long, default_value = "exact")]
+        matching: TileMatching,
+        /// Leave fully transparent tiles out, their cells get ID 0
+        #[arg(long)]
+        skip_empty: bool,
+        /// Number of tile columns in the tileset, as square as possible by default
+        #[arg(long)]
+        columns: Option<u32>,
+        /// The JSON map, the output path with a .json extension by default
+        #[arg(long)]
+        map: Option<String>,
+        /// The CSV map, the output path with a .csv extension by default
+        #[arg(long)]
+        csv: Option<String>,
+        /// Run the image through a recipe before slicing it
+        #[arg(long)]
+        recipe: Option<String>,
+    },
+    /// Derive one palette from one or more images and save it as .hex or .gpl
+    GeneratePalette {
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        #[arg(long)]
+        output: String,
+        #[arg(long, default_value_t = 16)]
+        colors: usize,
+        /// median-cut, octree or kmeans
+        #[arg(long, default_value = "median-cut")]
+        method: QuantizeMethod,
+        /// Seed for the kmeans quantizer
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
     #[command(
         visible_alias = "clear_outputs",
         visible_alias = "clearoutputs",
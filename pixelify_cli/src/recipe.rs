@@ -0,0 +1,531 @@
+//! Declarative pipeline recipes for pixelify_cli.
+//!
+//! A recipe is a TOML or JSON file that describes an ordered list of operations and their parameters,
+//! so the same settings can be reused across many assets. For example:
+//!
+//! ```toml
+//! version = 1
+//!
+//! [[steps]]
+//! op = "crop"
+//! x = 0
+//! y = 0
+//! width = 256
+//! height = 256
+//!
+//! [[steps]]
+//! op = "downscale"
+//! pixel_size = 8
+//! linear = true
+//!
+//! [[steps]]
+//! op = "dither"
+//! method = "floyd-steinberg"
+//! strength = 0.75
+//! palette = { builtin = "pico8" }
+//! ```
+//!
+//! Every recipe carries a `version`, recipes written for a newer version are rejected
+//! instead of being half understood.
+
+use crate::cli_utils::{load_palette, with_seed};
+use pixelify_core::background::{BackgroundKey, BackgroundOptions};
+use pixelify_core::color::{ColorMetric, parse_hex_color};
+use pixelify_core::dither::DitherMethod;
+use pixelify_core::outline::{Connectivity, OutlineColor, OutlineOptions, OutlinePlacement};
+use pixelify_core::palette::{BuiltinPalette, Palette};
+use pixelify_core::pipeline::{Operation, PaletteSource, Pipeline};
+use pixelify_core::pixelify::{
+    AlphaMode, BlockReducer, BlockSize, EdgePolicy, PixelifyOptions, ResizeMode,
+};
+use pixelify_core::pixelify_errors::ImageProcessingError;
+use pixelify_core::quantize::QuantizeMethod;
+use pixelify_core::upscale::{DEFAULT_GRID_COLOR, UpscaleMethod, UpscaleOptions, UpscaleSize};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::path::Path;
+
+/// The recipe version this build understands.
+pub const RECIPE_VERSION: u32 = 1;
+
+/// Only the version, read first so newer recipes fail with a version error rather than a parse error.
+#[derive(Deserialize)]
+struct RecipeHeader {
+    version: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Recipe {
+    #[allow(dead_code)]
+    version: u32,
+    #[serde(default)]
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Step {
+    Crop(CropStep),
+    Downscale(DownscaleStep),
+    Palette(PaletteStep),
+    Dither(DitherStep),
+    Grayscale,
+    Upscale(UpscaleStep),
+    Outline(OutlineStep),
+    RemoveBackground(RemoveBackgroundStep),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CropStep {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Either `pixel_size`, or `width` and/or `height`, a missing one follows the aspect ratio.
+/// `resize` picks how a size of another aspect ratio is reached, like `--resize`.
+/// `keep_size` keeps the original dimensions, like `false-downscale-by-pixel-size`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DownscaleStep {
+    pixel_size: Option<PixelSize>,
+    width: Option<u32>,
+    height: Option<u32>,
+    #[serde(default)]
+    keep_size: bool,
+    #[serde(default)]
+    linear: bool,
+    reducer: Option<String>,
+    edges: Option<String>,
+    resize: Option<String>,
+    alpha: Option<String>,
+}
+
+/// A number for square blocks, or a `"WxH"` string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PixelSize {
+    Square(u32),
+    Text(String),
+}
+
+impl PixelSize {
+    fn into_block_size(self) -> Result<BlockSize, String> {
+        match self {
+            PixelSize::Square(size) => Ok(BlockSize::from(size)),
+            PixelSize::Text(text) => text.parse::<BlockSize>(),
+        }
+    }
+}
+
+/// Exactly one of `builtin`, `file`, `hex` or `colors`.
+/// `hex` lists the palette colors as `RRGGBB` strings.
+/// `quantize` and `seed` only apply to `colors`, the adaptive palette.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PaletteStep {
+    builtin: Option<String>,
+    file: Option<String>,
+    hex: Option<Vec<String>>,
+    colors: Option<usize>,
+    quantize: Option<String>,
+    seed: Option<u64>,
+    metric: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DitherStep {
+    method: String,
+    strength: Option<f32>,
+    palette: PaletteStep,
+}
+
+/// Either `factor`, or `width` and `height` for the largest factor that fits.
+/// `grid_color` implies `grid`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UpscaleStep {
+    method: Option<String>,
+    factor: Option<u32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    #[serde(default)]
+    grid: bool,
+    grid_color: Option<String>,
+}
+
+/// At most one of `color`, an `RRGGBB` color removed everywhere, or `seeds`, `[x, y]` points to flood fill from.
+/// Without either, the background is flood filled from the corners.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemoveBackgroundStep {
+    color: Option<String>,
+    seeds: Option<Vec<[u32; 2]>>,
+    tolerance: Option<f32>,
+    feather: Option<u32>,
+}
+
+/// Every field is optional, an outer black 1 pixel outline with 4-connectivity by default.
+/// `color` is `RRGGBB`, `RRGGBBAA`, `shade` or `shade:AMOUNT`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutlineStep {
+    placement: Option<String>,
+    connectivity: Option<u32>,
+    thickness: Option<u32>,
+    color: Option<String>,
+}
+
+/// Loads a `.toml` or `.json` recipe and turns it into a `Pipeline`.
+///
+/// Palette files referenced by the recipe are resolved relative to the recipe itself.
+///
+/// # Errors
+///
+/// Returns `Err(ImageProcessingError)` if:
+/// - the file cannot be read or has an unknown extension,
+/// - the recipe is malformed, with the parser's line and column,
+/// - the version is missing or unsupported,
+/// - a step has invalid or conflicting parameters, with the step number.
+pub fn load_recipe(path: &str) -> Result<Pipeline, ImageProcessingError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| recipe_error(format!("failed to read {path}: {e}")))?;
+
+    let extension = Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+
+    let format = match extension.as_deref() {
+        Some("toml") => RecipeFormat::Toml,
+        Some("json") => RecipeFormat::Json,
+        _ => {
+            return Err(recipe_error(format!(
+                "{path}: recipes must be .toml or .json files"
+            )));
+        }
+    };
+
+    let header: RecipeHeader = format.parse(&text)?;
+    match header.version {
+        Some(RECIPE_VERSION) => {}
+        Some(version) => {
+            return Err(recipe_error(format!(
+                "unsupported recipe version {version}, this build supports version {RECIPE_VERSION}"
+            )));
+        }
+        None => {
+            return Err(recipe_error(format!(
+                "missing `version`, add `version = {RECIPE_VERSION}` at the top of the recipe"
+            )));
+        }
+    }
+
+    let recipe: Recipe = format.parse(&text)?;
+    if recipe.steps.is_empty() {
+        return Err(recipe_error("recipe has no steps"));
+    }
+
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    recipe
+        .steps
+        .into_iter()
+        .enumerate()
+        .try_fold(Pipeline::new(), |pipeline, (i, step)| {
+            let name = step.name();
+            step.into_operation(base_dir)
+                .map(|operation| pipeline.then(operation))
+                .map_err(|message| recipe_error(format!("step {} ({name}): {message}", i + 1)))
+        })
+}
+
+#[derive(Clone, Copy)]
+enum RecipeFormat {
+    Toml,
+    Json,
+}
+
+impl RecipeFormat {
+    fn parse<T: DeserializeOwned>(self, text: &str) -> Result<T, ImageProcessingError> {
+        match self {
+            RecipeFormat::Toml => toml::from_str(text).map_err(|e| recipe_error(e.to_string())),
+            RecipeFormat::Json => {
+                serde_json::from_str(text).map_err(|e| recipe_error(e.to_string()))
+            }
+        }
+    }
+}
+
+impl Step {
+    fn name(&self) -> &'static str {
+        match self {
+            Step::Crop(_) => "crop",
+            Step::Downscale(_) => "downscale",
+            Step::Palette(_) => "palette",
+            Step::Dither(_) => "dither",
+            Step::Grayscale => "grayscale",
+            Step::Upscale(_) => "upscale",
+            Step::Outline(_) => "outline",
+            Step::RemoveBackground(_) => "remove_background",
+        }
+    }
+
+    fn into_operation(self, base_dir: &Path) -> Result<Operation, String> {
+        match self {
+            Step::Crop(crop) => {
+                if crop.width == 0 || crop.height == 0 {
+                    return Err(String::from("width and height must be at least 1"));
+                }
+
+                Ok(Operation::Crop {
+                    x: crop.x,
+                    y: crop.y,
+                    width: crop.width,
+                    height: crop.height,
+                })
+            }
+            Step::Downscale(downscale) => downscale.into_operation(),
+            Step::Palette(palette) => Ok(Operation::Palette(palette.into_source(base_dir)?)),
+            Step::Dither(dither) => {
+                let strength = dither.strength.unwrap_or(1.0);
+                if !(0.0..=1.0).contains(&strength) {
+                    return Err(format!(
+                        "strength must be between 0.0 and 1.0, got {strength}"
+                    ));
+                }
+
+                Ok(Operation::Dither {
+                    method: dither.method.parse::<DitherMethod>()?,
+                    strength,
+                    palette: dither
+                        .palette
+                        .into_source(base_dir)
+                        .map_err(|message| format!("palette: {message}"))?,
+                })
+            }
+            Step::Grayscale => Ok(Operation::Grayscale),
+            Step::Upscale(upscale) => upscale.into_operation(),
+            Step::Outline(outline) => outline.into_operation(),
+            Step::RemoveBackground(background) => background.into_operation(),
+        }
+    }
+}
+
+impl DownscaleStep {
+    fn into_operation(self) -> Result<Operation, String> {
+        let reducer = match &self.reducer {
+            Some(reducer) => reducer.parse::<BlockReducer>()?,
+            None => BlockReducer::default(),
+        };
+        let edges = match &self.edges {
+            Some(edges) => edges.parse::<EdgePolicy>()?,
+            None => EdgePolicy::default(),
+        };
+        let resize = match &self.resize {
+            Some(resize) => resize.parse::<ResizeMode>()?,
+            None => ResizeMode::default(),
+        };
+        let alpha = match &self.alpha {
+            Some(alpha) => alpha.parse::<AlphaMode>()?,
+            None => AlphaMode::default(),
+        };
+        let options = PixelifyOptions {
+            linear_light: self.linear,
+            reducer,
+            edges,
+            resize,
+            alpha,
+        };
+
+        let pixel_size = self
+            .pixel_size
+            .map(PixelSize::into_block_size)
+            .transpose()?;
+        if pixel_size.is_some_and(|size| size.width == 0 || size.height == 0) {
+            return Err(String::from("pixel_size must be at least 1"));
+        }
+        if self.width == Some(0) || self.height == Some(0) {
+            return Err(String::from("width and height must be at least 1"));
+        }
+
+        match (pixel_size, self.width, self.height) {
+            (Some(pixel_size), None, None) if self.keep_size => {
+                Ok(Operation::FalseDownscaleByPixelSize {
+                    pixel_size,
+                    options,
+                })
+            }
+            (Some(pixel_size), None, None) => Ok(Operation::DownscaleByPixelSize {
+                pixel_size,
+                options,
+            }),
+            (None, width, height) if width.is_some() || height.is_some() => {
+                if self.keep_size {
+                    return Err(String::from("keep_size can only be used with pixel_size"));
+                }
+                Ok(Operation::DownscaleByImageSize {
+                    width,
+                    height,
+                    options,
+                })
+            }
+            _ => Err(String::from(
+                "set either pixel_size, or a width and/or height",
+            )),
+        }
+    }
+}
+
+impl RemoveBackgroundStep {
+    fn into_operation(self) -> Result<Operation, String> {
+        let key = match (self.color, self.seeds) {
+            (Some(color), None) => BackgroundKey::Color(parse_rgb(&color)?),
+            (None, Some(seeds)) => {
+                BackgroundKey::Seeds(seeds.into_iter().map(|[x, y]| (x, y)).collect())
+            }
+            (None, None) => BackgroundKey::Corners,
+            (Some(_), Some(_)) => return Err(String::from("set either color or seeds, not both")),
+        };
+
+        let defaults = BackgroundOptions::default();
+        Ok(Operation::RemoveBackground(BackgroundOptions {
+            key,
+            tolerance: self.tolerance.unwrap_or(defaults.tolerance),
+            feather: self.feather.unwrap_or(defaults.feather),
+        }))
+    }
+}
+
+impl OutlineStep {
+    fn into_operation(self) -> Result<Operation, String> {
+        let defaults = OutlineOptions::default();
+        let placement = match &self.placement {
+            Some(placement) => placement.parse::<OutlinePlacement>()?,
+            None => defaults.placement,
+        };
+        let connectivity = match self.connectivity {
+            Some(connectivity) => connectivity.to_string().parse::<Connectivity>()?,
+            None => defaults.connectivity,
+        };
+        let color = match &self.color {
+            Some(color) => color.parse::<OutlineColor>()?,
+            None => defaults.color,
+        };
+
+        Ok(Operation::Outline(OutlineOptions {
+            placement,
+            connectivity,
+            thickness: self.thickness.unwrap_or(defaults.thickness),
+            color,
+        }))
+    }
+}
+
+impl UpscaleStep {
+    fn into_operation(self) -> Result<Operation, String> {
+        let size = match (self.factor, self.width, self.height) {
+            (Some(factor), None, None) => UpscaleSize::Factor(factor),
+            (None, Some(width), Some(height)) => UpscaleSize::Fit { width, height },
+            _ => return Err(String::from("set either factor, or both width and height")),
+        };
+        match size {
+            UpscaleSize::Factor(0) => return Err(String::from("factor must be at least 1")),
+            UpscaleSize::Fit { width, height } if width == 0 || height == 0 => {
+                return Err(String::from("width and height must be at least 1"));
+            }
+            _ => {}
+        }
+
+        let pixel_grid = match &self.grid_color {
+            Some(color) => Some(parse_hex_color(color)?),
+            None if self.grid => Some(DEFAULT_GRID_COLOR),
+            None => None,
+        };
+
+        let method = match &self.method {
+            Some(method) => method.parse::<UpscaleMethod>()?,
+            None => UpscaleMethod::default(),
+        };
+
+        Ok(Operation::Upscale {
+            size,
+            options: UpscaleOptions { method, pixel_grid },
+        })
+    }
+}
+
+impl PaletteStep {
+    fn into_source(self, base_dir: &Path) -> Result<PaletteSource, String> {
+        let metric = match &self.metric {
+            Some(metric) => metric.parse::<ColorMetric>()?,
+            None => ColorMetric::default(),
+        };
+
+        if self.colors.is_none() && (self.quantize.is_some() || self.seed.is_some()) {
+            return Err(String::from(
+                "quantize and seed can only be used with colors",
+            ));
+        }
+
+        match (self.builtin, self.file, self.hex, self.colors) {
+            (Some(builtin), None, None, None) => {
+                let builtin = builtin.parse::<BuiltinPalette>()?;
+                Ok(PaletteSource::Fixed(
+                    Palette::builtin(builtin).with_metric(metric),
+                ))
+            }
+            (None, Some(file), None, None) => {
+                let path = base_dir.join(file);
+                let palette = load_palette(&path.to_string_lossy()).map_err(|e| e.message)?;
+                Ok(PaletteSource::Fixed(palette.with_metric(metric)))
+            }
+            (None, None, Some(hex), None) => {
+                let colors = hex
+                    .iter()
+                    .map(|color| parse_rgb(color))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let palette = Palette::new("recipe", colors).map_err(|e| e.message)?;
+                Ok(PaletteSource::Fixed(palette.with_metric(metric)))
+            }
+            (None, None, None, Some(colors)) => {
+                if colors == 0 {
+                    return Err(String::from("colors must be at least 1"));
+                }
+
+                let method = match &self.quantize {
+                    Some(method) => method.parse::<QuantizeMethod>()?,
+                    None => QuantizeMethod::MedianCut,
+                };
+
+                Ok(PaletteSource::Adaptive {
+                    colors,
+                    method: with_seed(method, self.seed.unwrap_or(0)),
+                    metric,
+                })
+            }
+            _ => Err(String::from(
+                "set exactly one of builtin, file, hex or colors",
+            )),
+        }
+    }
+}
+
+/// Parses an opaque `RRGGBB` color, rejecting `RRGGBBAA` rather than silently dropping its alpha.
+fn parse_rgb(color: &str) -> Result<[u8; 3], String> {
+    let [r, g, b, _] = parse_hex_color(color)?;
+    if color.trim().trim_start_matches('#').len() != 6 {
+        return Err(format!(
+            "invalid color '{color}', expected RRGGBB without alpha"
+        ));
+    }
+    Ok([r, g, b])
+}
+
+fn recipe_error(message: impl Into<String>) -> ImageProcessingError {
+    ImageProcessingError::failed("recipe", message)
+}
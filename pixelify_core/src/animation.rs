@@ -0,0 +1,170 @@
+//! Animated GIF and APNG support.
+//!
+//! `image::load_from_memory` only returns the first frame of an animation.
+//! `decode_animation` reads every frame instead, composited onto the full canvas,
+//! along with the frame delays and how often the animation loops,
+//! so the frames can go through a `Pipeline` together and be written back with `encode::encode_animation`.
+
+use crate::pixelify_errors::ImageProcessingError;
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::{AnimationDecoder, Delay, ImageFormat, RgbaImage};
+use std::io::Cursor;
+
+/// How many times an animation plays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoopCount {
+    #[default]
+    Infinite,
+    /// Plays this many times in total, then stops on the last frame.
+    Times(u32),
+}
+
+/// One frame of an animation, covering the whole canvas.
+#[derive(Debug, Clone)]
+pub struct AnimationFrame {
+    pub image: RgbaImage,
+    /// How long the frame is shown before the next one.
+    pub delay: Delay,
+}
+
+/// The frames of an animated GIF or APNG.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub frames: Vec<AnimationFrame>,
+    pub loop_count: LoopCount,
+}
+
+impl Animation {
+    /// Replaces the image of every frame, keeping the delays.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if `images` does not hold one image per frame.
+    pub fn with_images(self, images: Vec<RgbaImage>) -> Result<Animation, ImageProcessingError> {
+        if images.len() != self.frames.len() {
+            return Err(ImageProcessingError::failed(
+                "animation",
+                format!(
+                    "Expected {} frames, got {}",
+                    self.frames.len(),
+                    images.len()
+                ),
+            ));
+        }
+
+        let frames = self
+            .frames
+            .into_iter()
+            .zip(images)
+            .map(|(frame, image)| AnimationFrame {
+                image,
+                delay: frame.delay,
+            })
+            .collect();
+
+        Ok(Animation {
+            frames,
+            loop_count: self.loop_count,
+        })
+    }
+
+    /// The frame images, in order.
+    pub fn images(&self) -> Vec<RgbaImage> {
+        self.frames
+            .iter()
+            .map(|frame| frame.image.clone())
+            .collect()
+    }
+}
+
+/// Decodes every frame of an animated GIF or APNG.
+///
+/// Returns `None` for any other image, including GIFs and PNGs with a single frame,
+/// which are better handled as still images.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if the bytes look like a GIF or PNG but its frames cannot be decoded.
+pub fn decode_animation(bytes: &[u8]) -> Result<Option<Animation>, ImageProcessingError> {
+    let animation = match image::guess_format(bytes) {
+        Ok(ImageFormat::Gif) => decode_gif(bytes)?,
+        Ok(ImageFormat::Png) => match decode_apng(bytes)? {
+            Some(animation) => animation,
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    if animation.frames.len() < 2 {
+        return Ok(None);
+    }
+
+    Ok(Some(animation))
+}
+
+fn decode_gif(bytes: &[u8]) -> Result<Animation, ImageProcessingError> {
+    // The image crate composites the frames, but only the gif crate reports the loop count
+    let loop_count = match gif::DecodeOptions::new()
+        .read_info(Cursor::new(bytes))
+        .map_err(|e| animation_error(format!("Failed to decode gif: {e}")))?
+        .repeat()
+    {
+        gif::Repeat::Infinite => LoopCount::Infinite,
+        // GIFs count the repeats after the first play
+        gif::Repeat::Finite(repeats) => LoopCount::Times(repeats as u32 + 1),
+    };
+
+    let decoder = GifDecoder::new(Cursor::new(bytes))
+        .map_err(|e| animation_error(format!("Failed to decode gif: {e}")))?;
+
+    Ok(Animation {
+        frames: collect_frames(decoder)?,
+        loop_count,
+    })
+}
+
+/// Returns `None` for a PNG that is not animated.
+fn decode_apng(bytes: &[u8]) -> Result<Option<Animation>, ImageProcessingError> {
+    let reader = png::Decoder::new(Cursor::new(bytes))
+        .read_info()
+        .map_err(|e| animation_error(format!("Failed to decode png: {e}")))?;
+    let Some(control) = reader.info().animation_control else {
+        return Ok(None);
+    };
+
+    let loop_count = match control.num_plays {
+        0 => LoopCount::Infinite,
+        plays => LoopCount::Times(plays),
+    };
+
+    let decoder = PngDecoder::new(Cursor::new(bytes))
+        .and_then(PngDecoder::apng)
+        .map_err(|e| animation_error(format!("Failed to decode png: {e}")))?;
+
+    Ok(Some(Animation {
+        frames: collect_frames(decoder)?,
+        loop_count,
+    }))
+}
+
+fn collect_frames<'a>(
+    decoder: impl AnimationDecoder<'a>,
+) -> Result<Vec<AnimationFrame>, ImageProcessingError> {
+    let frames = decoder
+        .into_frames()
+        .collect_frames()
+        .map_err(|e| animation_error(format!("Failed to decode frames: {e}")))?;
+
+    Ok(frames
+        .into_iter()
+        .map(|frame| AnimationFrame {
+            delay: frame.delay(),
+            image: frame.into_buffer(),
+        })
+        .collect())
+}
+
+fn animation_error(message: impl Into<String>) -> ImageProcessingError {
+    ImageProcessingError::failed("animation", message)
+}
@@ -0,0 +1,828 @@
+//! Sprite sheets, packing many sprites into one texture atlas.
+//!
+//! Sprites are either laid out on a fixed grid or bin packed with MaxRects or skyline packing.
+//! Each sprite can be trimmed of its transparent border, spaced out with padding, and extruded
+//! by repeating its edge pixels, which keeps texture filtering from bleeding neighbours into it.
+//!
+//! Where every sprite ended up is described by a frame map, written as the "hash" or "array"
+//! JSON layout of TexturePacker so existing engine importers can read it.
+
+use crate::PixelifyImage;
+use crate::pixelify_errors::ImageProcessingError;
+use image::{RgbaImage, imageops};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// How sprites are arranged in the atlas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PackingMethod {
+    /// Every sprite gets a cell the size of the largest sprite, row by row.
+    Grid,
+    /// MaxRects bin packing, the tightest packing for sprites of mixed sizes.
+    #[default]
+    MaxRects,
+    /// Skyline bin packing, faster than MaxRects and close to it for sprites of similar heights.
+    Skyline,
+}
+
+impl PackingMethod {
+    pub const ALL: [PackingMethod; 3] = [
+        PackingMethod::Grid,
+        PackingMethod::MaxRects,
+        PackingMethod::Skyline,
+    ];
+}
+
+impl fmt::Display for PackingMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PackingMethod::Grid => "grid",
+            PackingMethod::MaxRects => "maxrects",
+            PackingMethod::Skyline => "skyline",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for PackingMethod {
+    type Err = String;
+
+    /// Parses a packing method name, ignoring case, `-` and `_`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match normalized.as_str() {
+            "grid" => Ok(PackingMethod::Grid),
+            "maxrects" | "maxrect" => Ok(PackingMethod::MaxRects),
+            "skyline" => Ok(PackingMethod::Skyline),
+            _ => Err(format!(
+                "unknown packing method '{s}', expected one of: grid, maxrects, skyline"
+            )),
+        }
+    }
+}
+
+/// The JSON layouts of a frame map, named after their TexturePacker counterparts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameMapLayout {
+    /// `frames` is an object keyed by sprite name.
+    #[default]
+    Hash,
+    /// `frames` is an array, with the sprite name in `filename`.
+    Array,
+}
+
+impl fmt::Display for FrameMapLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FrameMapLayout::Hash => "hash",
+            FrameMapLayout::Array => "array",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for FrameMapLayout {
+    type Err = String;
+
+    /// Parses a frame map layout name, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hash" => Ok(FrameMapLayout::Hash),
+            "array" => Ok(FrameMapLayout::Array),
+            _ => Err(format!(
+                "unknown frame map layout '{s}', expected one of: hash, array"
+            )),
+        }
+    }
+}
+
+/// Options for `pack_atlas`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AtlasOptions {
+    pub packing: PackingMethod,
+    /// The number of grid columns, by default the grid is as square as possible.
+    pub columns: Option<u32>,
+    /// Crops the fully transparent border off every sprite before packing.
+    pub trim: bool,
+    /// Transparent pixels between sprites and around the edges of the atlas.
+    pub padding: u32,
+    /// Repeats the edge pixels of every sprite this many times outwards.
+    pub extrude: u32,
+    /// Rounds the atlas width and height up to powers of two.
+    pub power_of_two: bool,
+}
+
+/// A named sprite to pack.
+#[derive(Debug, Clone)]
+pub struct Sprite {
+    /// The key of the sprite in the frame map, usually its file name.
+    pub name: String,
+    pub image: PixelifyImage,
+}
+
+/// A rectangle in pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    #[serde(rename = "w")]
+    pub width: u32,
+    #[serde(rename = "h")]
+    pub height: u32,
+}
+
+/// Where a sprite ended up in the atlas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtlasFrame {
+    pub name: String,
+    /// The sprite pixels within the atlas, without padding or extrusion.
+    pub frame: Rect,
+    /// True if transparent borders were cropped off the sprite.
+    pub trimmed: bool,
+    /// The part of the original sprite that `frame` holds.
+    pub source: Rect,
+    pub source_width: u32,
+    pub source_height: u32,
+}
+
+/// A packed atlas and its frames, in the order the sprites were given.
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    /// The atlas as raw RGBA pixels.
+    pub image: PixelifyImage,
+    pub frames: Vec<AtlasFrame>,
+}
+
+#[derive(Serialize)]
+struct Size {
+    w: u32,
+    h: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameEntry<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filename: Option<&'a str>,
+    frame: Rect,
+    rotated: bool,
+    trimmed: bool,
+    sprite_source_size: Rect,
+    source_size: Size,
+}
+
+#[derive(Serialize)]
+struct Meta<'a> {
+    app: &'static str,
+    version: &'static str,
+    image: &'a str,
+    format: &'static str,
+    size: Size,
+    scale: &'static str,
+}
+
+#[derive(Serialize)]
+struct FrameMap<'a, F> {
+    frames: F,
+    meta: Meta<'a>,
+}
+
+impl Atlas {
+    /// Describes the atlas as TexturePacker compatible JSON.
+    ///
+    /// `image_name` is the file name the atlas image is saved under, as stored in `meta.image`.
+    /// The hash layout lists frames by name, the array layout in packing order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if the JSON cannot be serialized.
+    pub fn frame_map(
+        &self,
+        layout: FrameMapLayout,
+        image_name: &str,
+    ) -> Result<String, ImageProcessingError> {
+        let entry = |frame: &'_ AtlasFrame, filename| FrameEntry {
+            filename,
+            frame: frame.frame,
+            rotated: false,
+            trimmed: frame.trimmed,
+            sprite_source_size: frame.source,
+            source_size: Size {
+                w: frame.source_width,
+                h: frame.source_height,
+            },
+        };
+
+        let meta = Meta {
+            app: "pixelify",
+            version: "1.0",
+            image: image_name,
+            format: "RGBA8888",
+            size: Size {
+                w: self.image.get_width(),
+                h: self.image.get_height(),
+            },
+            scale: "1",
+        };
+
+        let json = match layout {
+            FrameMapLayout::Hash => {
+                let frames: BTreeMap<&str, FrameEntry> = self
+                    .frames
+                    .iter()
+                    .map(|frame| (frame.name.as_str(), entry(frame, None)))
+                    .collect();
+                serde_json::to_string_pretty(&FrameMap { frames, meta })
+            }
+            FrameMapLayout::Array => {
+                let frames: Vec<FrameEntry> = self
+                    .frames
+                    .iter()
+                    .map(|frame| entry(frame, Some(frame.name.as_str())))
+                    .collect();
+                serde_json::to_string_pretty(&FrameMap { frames, meta })
+            }
+        };
+
+        json.map_err(|e| {
+            ImageProcessingError::failed("atlas", format!("Failed to write frame map: {e}"))
+        })
+    }
+}
+
+/// Packs `sprites` into one atlas image.
+///
+/// Bin packing tries a range of atlas widths and keeps the one with the smallest area,
+/// so the atlas comes out roughly square.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if:
+/// - there are no sprites, or two sprites share a name,
+/// - a sprite cannot be converted to RGBA,
+/// - the grid has 0 columns,
+/// - the atlas would be too large to address.
+pub fn pack_atlas(
+    sprites: &[Sprite],
+    options: &AtlasOptions,
+) -> Result<Atlas, ImageProcessingError> {
+    if sprites.is_empty() {
+        return Err(atlas_error("An atlas needs at least one sprite"));
+    }
+
+    let mut names = HashSet::new();
+    if let Some(duplicate) = sprites.iter().find(|sprite| !names.insert(&sprite.name)) {
+        return Err(atlas_error(format!(
+            "Two sprites are named '{}'",
+            duplicate.name
+        )));
+    }
+
+    // (cropped pixels, part of the source they hold)
+    let mut pieces = Vec::with_capacity(sprites.len());
+    for sprite in sprites {
+        let rgba = sprite.image.to_rgba_image()?;
+        let source = if options.trim {
+            opaque_bounds(&rgba)
+        } else {
+            full_rect(&rgba)
+        };
+        let cropped =
+            imageops::crop_imm(&rgba, source.x, source.y, source.width, source.height).to_image();
+        pieces.push((cropped, source, rgba.dimensions()));
+    }
+
+    // Every piece reserves room for its extrusion on both sides and padding on one,
+    // the padding on the other side comes from the atlas border
+    let margin = options
+        .extrude
+        .checked_mul(2)
+        .and_then(|extrude| extrude.checked_add(options.padding))
+        .ok_or_else(too_large)?;
+    let sizes = pieces
+        .iter()
+        .map(|(piece, _, _)| {
+            piece
+                .width()
+                .checked_add(margin)
+                .zip(piece.height().checked_add(margin))
+                .ok_or_else(too_large)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let round = |used: u32| -> Result<u32, ImageProcessingError> {
+        let size = used.checked_add(options.padding).ok_or_else(too_large)?;
+        if options.power_of_two {
+            size.checked_next_power_of_two().ok_or_else(too_large)
+        } else {
+            Ok(size)
+        }
+    };
+
+    let positions = match options.packing {
+        PackingMethod::Grid => pack_grid(&sizes, options.columns)?,
+        PackingMethod::MaxRects => pack_smallest(&sizes, &round, pack_max_rects)?,
+        PackingMethod::Skyline => pack_smallest(&sizes, &round, pack_skyline)?,
+    };
+
+    let (used_width, used_height) = used_size(&sizes, &positions);
+    let (width, height) = (round(used_width)?, round(used_height)?);
+
+    let mut atlas = RgbaImage::new(width, height);
+    let mut frames = Vec::with_capacity(sprites.len());
+
+    for ((sprite, (piece, source, (source_width, source_height))), (x, y)) in
+        sprites.iter().zip(pieces).zip(positions)
+    {
+        let left = options.padding + x + options.extrude;
+        let top = options.padding + y + options.extrude;
+        draw_extruded(&mut atlas, &piece, left, top, options.extrude);
+
+        frames.push(AtlasFrame {
+            name: sprite.name.clone(),
+            frame: Rect {
+                x: left,
+                y: top,
+                width: piece.width(),
+                height: piece.height(),
+            },
+            trimmed: (source.width, source.height) != (source_width, source_height),
+            source,
+            source_width,
+            source_height,
+        });
+    }
+
+    Ok(Atlas {
+        image: PixelifyImage::from(atlas),
+        frames,
+    })
+}
+
+/// The smallest rectangle holding every pixel that is not fully transparent.
+///
+/// A fully transparent sprite keeps a single pixel, so it still has a frame.
+fn opaque_bounds(image: &RgbaImage) -> Rect {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[3] > 0 {
+            bounds = Some(match bounds {
+                None => (x, y, x, y),
+                Some((left, top, right, bottom)) => {
+                    (left.min(x), top.min(y), right.max(x), bottom.max(y))
+                }
+            });
+        }
+    }
+
+    match bounds {
+        Some((left, top, right, bottom)) => Rect {
+            x: left,
+            y: top,
+            width: right - left + 1,
+            height: bottom - top + 1,
+        },
+        None => Rect {
+            x: 0,
+            y: 0,
+            width: 1.min(image.width()),
+            height: 1.min(image.height()),
+        },
+    }
+}
+
+fn full_rect(image: &RgbaImage) -> Rect {
+    Rect {
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    }
+}
+
+/// Draws `piece` with its top left corner at `left`, `top`,
+/// surrounded by `extrude` copies of its edge pixels.
+fn draw_extruded(atlas: &mut RgbaImage, piece: &RgbaImage, left: u32, top: u32, extrude: u32) {
+    let (width, height) = piece.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    for dy in 0..height + 2 * extrude {
+        for dx in 0..width + 2 * extrude {
+            let source_x = dx.saturating_sub(extrude).min(width - 1);
+            let source_y = dy.saturating_sub(extrude).min(height - 1);
+            atlas.put_pixel(
+                left - extrude + dx,
+                top - extrude + dy,
+                *piece.get_pixel(source_x, source_y),
+            );
+        }
+    }
+}
+
+fn used_size(sizes: &[(u32, u32)], positions: &[(u32, u32)]) -> (u32, u32) {
+    sizes
+        .iter()
+        .zip(positions)
+        .fold((0, 0), |(width, height), (&(w, h), &(x, y))| {
+            (width.max(x + w), height.max(y + h))
+        })
+}
+
+fn pack_grid(
+    sizes: &[(u32, u32)],
+    columns: Option<u32>,
+) -> Result<Vec<(u32, u32)>, ImageProcessingError> {
+    let count = sizes.len() as u32;
+    let columns = match columns {
+        Some(0) => return Err(atlas_error("A grid needs at least one column")),
+        Some(columns) => columns.min(count),
+        None => (count as f64).sqrt().ceil() as u32,
+    };
+
+    let cell_width = sizes.iter().map(|&(w, _)| w).max().unwrap_or(0);
+    let cell_height = sizes.iter().map(|&(_, h)| h).max().unwrap_or(0);
+    let rows = count.div_ceil(columns);
+
+    cell_width
+        .checked_mul(columns)
+        .zip(cell_height.checked_mul(rows))
+        .ok_or_else(too_large)?;
+
+    Ok((0..count)
+        .map(|i| ((i % columns) * cell_width, (i / columns) * cell_height))
+        .collect())
+}
+
+/// Packs rectangles of the given sizes into a bin of the given width, returning their positions.
+type Packer = fn(&[(u32, u32)], u32) -> Vec<(u32, u32)>;
+
+/// Runs `pack` over a range of bin widths and keeps the packing with the smallest atlas.
+fn pack_smallest(
+    sizes: &[(u32, u32)],
+    round: &dyn Fn(u32) -> Result<u32, ImageProcessingError>,
+    pack: Packer,
+) -> Result<Vec<(u32, u32)>, ImageProcessingError> {
+    let widest = sizes.iter().map(|&(w, _)| w as u64).max().unwrap_or(0);
+    let total_width: u64 = sizes.iter().map(|&(w, _)| w as u64).sum();
+    let area: u64 = sizes.iter().map(|&(w, h)| w as u64 * h as u64).sum();
+    let total_height: u64 = sizes.iter().map(|&(_, h)| h as u64).sum();
+
+    if total_height > u32::MAX as u64 {
+        return Err(too_large());
+    }
+
+    // A sample of widths between the widest sprite and a single row, plus a square
+    const SAMPLES: u64 = 64;
+    let narrowest = widest.max(1);
+    let widest_row = total_width.clamp(narrowest, u32::MAX as u64);
+    let mut widths: Vec<u32> = (0..=SAMPLES)
+        .map(|i| (narrowest + (widest_row - narrowest) * i / SAMPLES) as u32)
+        .collect();
+    widths.push(((area as f64).sqrt().ceil() as u64).clamp(narrowest, widest_row) as u32);
+    widths.sort_unstable();
+    widths.dedup();
+
+    let mut best_score = (u64::MAX, u32::MAX, u32::MAX);
+    let mut best = None;
+    for width in widths {
+        let positions = pack(sizes, width);
+        let (used_width, used_height) = used_size(sizes, &positions);
+        let (atlas_width, atlas_height) = (round(used_width)?, round(used_height)?);
+
+        // Smallest area first, then the squarest
+        let score = (
+            atlas_width as u64 * atlas_height as u64,
+            atlas_width.max(atlas_height),
+            atlas_width,
+        );
+        if best.is_none() || score < best_score {
+            best_score = score;
+            best = Some(positions);
+        }
+    }
+
+    best.ok_or_else(|| atlas_error("Failed to pack the sprites"))
+}
+
+/// Indices of `sizes` with the tallest first, then the widest.
+fn tallest_first(sizes: &[(u32, u32)]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse((sizes[i].1, sizes[i].0)));
+    order
+}
+
+/// MaxRects packing into a bin `width` wide and tall enough for any layout.
+///
+/// Each rectangle goes to the free spot where its bottom edge ends up highest, then leftmost.
+fn pack_max_rects(sizes: &[(u32, u32)], width: u32) -> Vec<(u32, u32)> {
+    let height = sizes.iter().map(|&(_, h)| h).sum();
+    let mut free = vec![Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    }];
+    let mut positions = vec![(0, 0); sizes.len()];
+
+    for i in tallest_first(sizes) {
+        let (w, h) = sizes[i];
+        let Some(spot) = free
+            .iter()
+            .filter(|rect| rect.width >= w && rect.height >= h)
+            .min_by_key(|rect| (rect.y + h, rect.x))
+            .copied()
+        else {
+            continue;
+        };
+
+        let placed = Rect {
+            x: spot.x,
+            y: spot.y,
+            width: w,
+            height: h,
+        };
+        positions[i] = (placed.x, placed.y);
+
+        if w == 0 || h == 0 {
+            continue;
+        }
+
+        // Split every free rectangle the placed one overlaps into the parts around it
+        let mut split = Vec::with_capacity(free.len() + 4);
+        for rect in free {
+            if !overlaps(&rect, &placed) {
+                split.push(rect);
+                continue;
+            }
+            if placed.x > rect.x {
+                split.push(Rect {
+                    width: placed.x - rect.x,
+                    ..rect
+                });
+            }
+            if placed.x + placed.width < rect.x + rect.width {
+                split.push(Rect {
+                    x: placed.x + placed.width,
+                    width: rect.x + rect.width - placed.x - placed.width,
+                    ..rect
+                });
+            }
+            if placed.y > rect.y {
+                split.push(Rect {
+                    height: placed.y - rect.y,
+                    ..rect
+                });
+            }
+            if placed.y + placed.height < rect.y + rect.height {
+                split.push(Rect {
+                    y: placed.y + placed.height,
+                    height: rect.y + rect.height - placed.y - placed.height,
+                    ..rect
+                });
+            }
+        }
+
+        // Drop free rectangles that lie within another one
+        free = split
+            .iter()
+            .enumerate()
+            .filter(|&(j, rect)| {
+                !split
+                    .iter()
+                    .enumerate()
+                    .any(|(k, other)| j != k && contains(other, rect) && (other != rect || k < j))
+            })
+            .map(|(_, rect)| *rect)
+            .collect();
+    }
+
+    positions
+}
+
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+}
+
+fn contains(outer: &Rect, inner: &Rect) -> bool {
+    inner.x >= outer.x
+        && inner.y >= outer.y
+        && inner.x + inner.width <= outer.x + outer.width
+        && inner.y + inner.height <= outer.y + outer.height
+}
+
+/// Skyline packing into a bin `width` wide, placing each rectangle as low as possible, then leftmost.
+fn pack_skyline(sizes: &[(u32, u32)], width: u32) -> Vec<(u32, u32)> {
+    // (x, y, width) segments of the skyline, left to right
+    let mut skyline: Vec<(u32, u32, u32)> = vec![(0, 0, width)];
+    let mut positions = vec![(0, 0); sizes.len()];
+
+    for i in tallest_first(sizes) {
+        let (w, h) = sizes[i];
+
+        // The lowest spot starting at a segment, where the rectangle rests on the highest segment below it
+        let mut best: Option<(u32, u32, usize)> = None;
+        for (start, &(x, _, _)) in skyline.iter().enumerate() {
+            if x + w > width {
+                break;
+            }
+
+            let mut y = 0;
+            let mut covered = 0;
+            for &(_, segment_y, segment_width) in &skyline[start..] {
+                if covered >= w {
+                    break;
+                }
+                y = y.max(segment_y);
+                covered += segment_width;
+            }
+
+            if best.is_none_or(|(best_y, best_x, _)| (y + h, x) < (best_y + h, best_x)) {
+                best = Some((y, x, start));
+            }
+        }
+
+        let Some((y, x, start)) = best else {
+            continue;
+        };
+        positions[i] = (x, y);
+
+        if w == 0 {
+            continue;
+        }
+
+        // Raise the skyline under the rectangle
+        skyline.insert(start, (x, y + h, w));
+        let right = x + w;
+        let next = start + 1;
+        while next < skyline.len() {
+            let (segment_x, segment_y, segment_width) = skyline[next];
+            if segment_x >= right {
+                break;
+            }
+            let segment_right = segment_x + segment_width;
+            if segment_right <= right {
+                skyline.remove(next);
+            } else {
+                skyline[next] = (right, segment_y, segment_right - right);
+                break;
+            }
+        }
+
+        // Merge neighbours at the same height
+        skyline.dedup_by(|current, previous| {
+            if previous.1 == current.1 {
+                previous.2 += current.2;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    positions
+}
+
+fn too_large() -> ImageProcessingError {
+    atlas_error("The atlas would be too large")
+}
+
+fn atlas_error(message: impl Into<String>) -> ImageProcessingError {
+    ImageProcessingError::failed("atlas", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sprite sizes from a small linear congruential generator, so every run packs the same sprites.
+    fn sizes(count: usize, seed: u32) -> Vec<(u32, u32)> {
+        let mut state = seed;
+        let mut next = |max: u32| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) % max + 1
+        };
+        (0..count).map(|_| (next(40), next(40))).collect()
+    }
+
+    fn assert_packed(sizes: &[(u32, u32)], positions: &[(u32, u32)], width: u32) {
+        let rects: Vec<Rect> = sizes
+            .iter()
+            .zip(positions)
+            .map(|(&(w, h), &(x, y))| Rect {
+                x,
+                y,
+                width: w,
+                height: h,
+            })
+            .collect();
+
+        for (i, a) in rects.iter().enumerate() {
+            assert!(
+                a.x + a.width <= width,
+                "{a:?} is outside the {width} wide bin"
+            );
+            for b in &rects[i + 1..] {
+                assert!(!overlaps(a, b), "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn packers_never_overlap() {
+        for packer in [pack_max_rects as Packer, pack_skyline] {
+            for seed in 0..20 {
+                let sizes = sizes(30, seed);
+                let widest = sizes.iter().map(|&(w, _)| w).max().unwrap();
+                for width in [widest, widest + 7, 100, 250, 1200] {
+                    assert_packed(&sizes, &packer(&sizes, width), width);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packers_handle_equal_and_thin_sizes() {
+        let sizes = [vec![(16, 16); 25], vec![(1, 30), (30, 1), (1, 1), (30, 30)]].concat();
+        for packer in [pack_max_rects as Packer, pack_skyline] {
+            for width in [30, 31, 64, 80] {
+                assert_packed(&sizes, &packer(&sizes, width), width);
+            }
+        }
+    }
+
+    #[test]
+    fn packers_fill_a_row_exactly() {
+        let sizes = vec![(8, 8); 4];
+        for packer in [pack_max_rects as Packer, pack_skyline] {
+            let mut positions = packer(&sizes, 32);
+            positions.sort_unstable();
+            assert_eq!(positions, vec![(0, 0), (8, 0), (16, 0), (24, 0)]);
+        }
+    }
+
+    #[test]
+    fn atlas_frames_keep_their_padding() {
+        let sprites: Vec<Sprite> = sizes(12, 7)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (w, h))| Sprite {
+                name: format!("sprite{i}"),
+                image: PixelifyImage::from(RgbaImage::from_pixel(
+                    w,
+                    h,
+                    image::Rgba([i as u8, 0, 0, 255]),
+                )),
+            })
+            .collect();
+
+        for packing in [PackingMethod::MaxRects, PackingMethod::Skyline] {
+            let options = AtlasOptions {
+                packing,
+                padding: 2,
+                extrude: 1,
+                ..AtlasOptions::default()
+            };
+            let atlas = pack_atlas(&sprites, &options).unwrap();
+            let rgba = atlas.image.to_rgba_image().unwrap();
+
+            // Frames grown by their extrusion and padding still never touch
+            let grown: Vec<Rect> = atlas
+                .frames
+                .iter()
+                .map(|frame| Rect {
+                    x: frame.frame.x - 1,
+                    y: frame.frame.y - 1,
+                    width: frame.frame.width + 4,
+                    height: frame.frame.height + 4,
+                })
+                .collect();
+            for (i, a) in grown.iter().enumerate() {
+                assert!(a.x + a.width <= rgba.width() && a.y + a.height <= rgba.height());
+                for b in &grown[i + 1..] {
+                    assert!(!overlaps(a, b), "{packing}: {a:?} overlaps {b:?}");
+                }
+            }
+
+            for (i, frame) in atlas.frames.iter().enumerate() {
+                let Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                } = frame.frame;
+                assert_eq!(rgba.get_pixel(x, y)[0], i as u8);
+                assert_eq!(rgba.get_pixel(x + width - 1, y + height - 1)[0], i as u8);
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Background removal, turning a flat or green-screen background transparent.
+//!
+//! A background pixel is one whose color is within a tolerance of a key color, measured as the
+//! euclidean distance in OKLab so the tolerance matches how different colors look.
+//! The key is either one color removed everywhere in the image, or the colors under the image corners
+//! or seed points, removed by flood filling from there so matching colors inside the subject survive.
+//!
+//! Removed pixels become fully transparent, ready for the premultiplied averaging of the downscale functions.
+
+use crate::PixelifyImage;
+use crate::color::rgb_to_oklab;
+use crate::pixelify_errors::ImageProcessingError;
+use image::RgbaImage;
+use std::collections::VecDeque;
+
+/// The OKLab distance used when no tolerance is given, loose enough for compression noise and soft gradients.
+pub const DEFAULT_TOLERANCE: f32 = 0.08;
+
+/// Where the background color comes from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum BackgroundKey {
+    /// Flood fills from the four corners, each with the color under it.
+    #[default]
+    Corners,
+    /// Flood fills from these `(x, y)` points, each with the color under it.
+    Seeds(Vec<(u32, u32)>),
+    /// Removes every pixel close to this RGB color, anywhere in the image.
+    Color([u8; 3]),
+}
+
+/// Options for `remove_background`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundOptions {
+    pub key: BackgroundKey,
+    /// The largest OKLab distance from the key color that still counts as background.
+    /// OKLab lightness goes from 0.0 to 1.0, so 0.02 is barely visible and 0.2 is a clearly different color.
+    pub tolerance: f32,
+    /// Fades the kept pixels next to the removed background over this many pixels, 0 keeps hard edges.
+    pub feather: u32,
+}
+
+impl Default for BackgroundOptions {
+    fn default() -> Self {
+        Self {
+            key: BackgroundKey::default(),
+            tolerance: DEFAULT_TOLERANCE,
+            feather: 0,
+        }
+    }
+}
+
+/// Makes the background of an image transparent.
+///
+/// The result holds raw RGBA pixels of the same size as the input.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if:
+/// - `image` cannot be converted to RGBA,
+/// - the tolerance is negative or not a number,
+/// - a seed point lies outside the image.
+pub fn remove_background(
+    image: &PixelifyImage,
+    options: &BackgroundOptions,
+) -> Result<PixelifyImage, ImageProcessingError> {
+    let rgba = image.to_rgba_image()?;
+    remove_background_image(&rgba, options).map(PixelifyImage::from)
+}
+
+/// Removes the background of an in-memory RGBA image, see `remove_background`.
+pub(crate) fn remove_background_image(
+    image: &RgbaImage,
+    options: &BackgroundOptions,
+) -> Result<RgbaImage, ImageProcessingError> {
+    if options.tolerance.is_nan() || options.tolerance < 0.0 {
+        return Err(ImageProcessingError::failed(
+            "remove_background",
+            "Tolerance must be a number of at least 0.0",
+        ));
+    }
+
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return Ok(image.clone());
+    }
+
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+
+    // Transparent pixels have no color and always count as background, a transparent seed only fills those
+    let oklab: Vec<Option<[f32; 3]>> = image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            (a > 0).then(|| rgb_to_oklab([r, g, b]))
+        })
+        .collect();
+
+    let matches = |key: Option<[f32; 3]>, i: usize| match (key, oklab[i]) {
+        (_, None) => true,
+        (Some(key), Some(color)) => distance(key, color) <= options.tolerance,
+        (None, Some(_)) => false,
+    };
+
+    let seeds = match &options.key {
+        BackgroundKey::Color(color) => {
+            let key = Some(rgb_to_oklab(*color));
+            let removed: Vec<bool> = (0..oklab.len()).map(|i| matches(key, i)).collect();
+            return Ok(apply_mask(image, &removed, options.feather));
+        }
+        BackgroundKey::Corners => vec![
+            (0, 0),
+            (width - 1, 0),
+            (0, height - 1),
+            (width - 1, height - 1),
+        ],
+        BackgroundKey::Seeds(seeds) => {
+            if let Some((x, y)) = seeds.iter().find(|(x, y)| *x >= width || *y >= height) {
+                return Err(ImageProcessingError::failed(
+                    "remove_background",
+                    format!("Seed point {x},{y} lies outside the {width}x{height} image"),
+                ));
+            }
+            seeds.clone()
+        }
+    };
+
+    let mut removed = vec![false; oklab.len()];
+
+    // Every seed fills the pixels connected to it that match its own color,
+    // a seed already inside the background of an earlier one has nothing left to fill
+    for (seed_x, seed_y) in seeds {
+        if removed[index(seed_x, seed_y)] {
+            continue;
+        }
+
+        let key = oklab[index(seed_x, seed_y)];
+        let mut queue = VecDeque::from([(seed_x, seed_y)]);
+        removed[index(seed_x, seed_y)] = true;
+
+        while let Some((x, y)) = queue.pop_front() {
+            for (nx, ny) in neighbors(x, y, width, height) {
+                let i = index(nx, ny);
+                if !removed[i] && matches(key, i) {
+                    removed[i] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+    }
+
+    Ok(apply_mask(image, &removed, options.feather))
+}
+
+/// Clears the removed pixels, and fades the kept pixels within `feather` steps of them.
+fn apply_mask(image: &RgbaImage, removed: &[bool], feather: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+    let mut result = image.clone();
+
+    let mut frontier: Vec<(u32, u32)> = Vec::new();
+    let mut reached = removed.to_vec();
+    for (x, y, pixel) in result.enumerate_pixels_mut() {
+        if removed[index(x, y)] {
+            pixel.0 = [0; 4];
+            frontier.push((x, y));
+        }
+    }
+
+    // Pixels `step` away from the background keep `step / (feather + 1)` of their alpha
+    for step in 1..=feather {
+        let mut next = Vec::new();
+        for &(x, y) in &frontier {
+            for (nx, ny) in neighbors(x, y, width, height) {
+                if !reached[index(nx, ny)] {
+                    reached[index(nx, ny)] = true;
+                    next.push((nx, ny));
+                }
+            }
+        }
+
+        for &(x, y) in &next {
+            let pixel = result.get_pixel_mut(x, y);
+            pixel.0[3] = (pixel.0[3] as u32 * step / (feather + 1)) as u8;
+        }
+        frontier = next;
+    }
+
+    result
+}
+
+/// The 4-connected neighbors of a pixel that lie inside the image.
+fn neighbors(x: u32, y: u32, width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+    [(-1i64, 0i64), (1, 0), (0, -1), (0, 1)]
+        .into_iter()
+        .map(move |(dx, dy)| (x as i64 + dx, y as i64 + dy))
+        .filter(move |&(nx, ny)| nx >= 0 && ny >= 0 && nx < width as i64 && ny < height as i64)
+        .map(|(nx, ny)| (nx as u32, ny as u32))
+}
+
+/// Euclidean distance between two OKLab colors.
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
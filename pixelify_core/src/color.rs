@@ -0,0 +1,257 @@
+//! Color space conversions and perceptual color distances.
+//!
+//! Image bytes are gamma-encoded sRGB, which is fine for storage but not for math on colors.
+//! Averaging sRGB values darkens the result, and euclidean distance in sRGB does not match how
+//! different two colors look. This module converts to:
+//! - linear RGB, for averaging light,
+//! - CIELAB (D65), for the CIEDE2000 color difference,
+//! - OKLab, a perceptual space where plain euclidean distance works well.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// How the distance between two colors is measured when matching colors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMetric {
+    /// Euclidean distance between gamma-encoded sRGB values. Fast, but not perceptual.
+    Rgb,
+    /// Euclidean distance in OKLab.
+    #[default]
+    Oklab,
+    /// The CIEDE2000 color difference, computed in CIELAB.
+    Ciede2000,
+}
+
+impl ColorMetric {
+    /// Converts an sRGB color into the space this metric measures in.
+    pub fn to_space(self, color: [u8; 3]) -> [f32; 3] {
+        match self {
+            ColorMetric::Rgb => color.map(|c| c as f32),
+            ColorMetric::Oklab => rgb_to_oklab(color),
+            ColorMetric::Ciede2000 => rgb_to_lab(color),
+        }
+    }
+
+    /// Returns the distance between two colors previously converted with `to_space`.
+    ///
+    /// The values are only comparable within one metric. Rgb and Oklab return squared distances,
+    /// since only the ordering matters when searching for the nearest color.
+    pub fn distance(self, a: [f32; 3], b: [f32; 3]) -> f32 {
+        match self {
+            ColorMetric::Rgb | ColorMetric::Oklab => {
+                (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+            }
+            ColorMetric::Ciede2000 => ciede2000(a, b),
+        }
+    }
+}
+
+impl fmt::Display for ColorMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorMetric::Rgb => "rgb",
+            ColorMetric::Oklab => "oklab",
+            ColorMetric::Ciede2000 => "ciede2000",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for ColorMetric {
+    type Err = String;
+
+    /// Parses a color metric name, ignoring case, `-` and `_`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match normalized.as_str() {
+            "rgb" | "srgb" => Ok(ColorMetric::Rgb),
+            "oklab" => Ok(ColorMetric::Oklab),
+            "ciede2000" | "de2000" | "lab" => Ok(ColorMetric::Ciede2000),
+            _ => Err(format!(
+                "unknown color metric '{s}', expected one of: rgb, oklab, ciede2000"
+            )),
+        }
+    }
+}
+
+/// Converts a gamma-encoded sRGB channel into linear light, in `0.0..=1.0`.
+pub fn srgb_to_linear(channel: u8) -> f32 {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+
+    // Only 256 possible inputs, so decode each once instead of calling powf per pixel
+    let table = TABLE.get_or_init(|| {
+        std::array::from_fn(|i| {
+            let c = i as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    });
+
+    table[channel as usize]
+}
+
+/// Converts a linear light value in `0.0..=1.0` back into a gamma-encoded sRGB channel.
+///
+/// Values outside of the range are clamped.
+pub fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let encoded = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// Converts an sRGB color into linear RGB.
+pub fn rgb_to_linear(color: [u8; 3]) -> [f32; 3] {
+    color.map(srgb_to_linear)
+}
+
+/// Converts a linear RGB color back into sRGB.
+pub fn linear_to_rgb(color: [f32; 3]) -> [u8; 3] {
+    color.map(linear_to_srgb)
+}
+
+/// Converts an sRGB color into CIELAB, relative to the D65 white point.
+pub fn rgb_to_lab(color: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb_to_linear(color);
+
+    let x = 0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = 0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b;
+
+    let fx = lab_f(x / 0.950_47);
+    let fy = lab_f(y);
+    let fz = lab_f(z / 1.088_83);
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn lab_f(t: f32) -> f32 {
+    const DELTA: f32 = 6.0 / 29.0;
+    if t > DELTA * DELTA * DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+/// Converts an sRGB color into OKLab.
+pub fn rgb_to_oklab(color: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb_to_linear(color);
+
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+/// Returns the CIEDE2000 color difference between two CIELAB colors.
+///
+/// A difference of about 1.0 is the smallest a person can notice.
+pub fn ciede2000(lab1: [f32; 3], lab2: [f32; 3]) -> f32 {
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+
+    let pow25_7 = 25f32.powi(7);
+
+    let c_bar = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt());
+
+    let a1p = (1.0 + g) * a1;
+    let a2p = (1.0 + g) * a2;
+    let c1p = (a1p * a1p + b1 * b1).sqrt();
+    let c2p = (a2p * a2p + b2 * b2).sqrt();
+    let h1p = hue_degrees(b1, a1p);
+    let h2p = hue_degrees(b2, a2p);
+
+    let delta_lp = l2 - l1;
+    let delta_cp = c2p - c1p;
+
+    let delta_hp = if c1p * c2p == 0.0 {
+        0.0
+    } else if (h2p - h1p).abs() <= 180.0 {
+        h2p - h1p
+    } else if h2p - h1p > 180.0 {
+        h2p - h1p - 360.0
+    } else {
+        h2p - h1p + 360.0
+    };
+    let delta_big_hp = 2.0 * (c1p * c2p).sqrt() * (delta_hp.to_radians() / 2.0).sin();
+
+    let l_bar_p = (l1 + l2) / 2.0;
+    let c_bar_p = (c1p + c2p) / 2.0;
+    let h_bar_p = if c1p * c2p == 0.0 {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= 180.0 {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 360.0 {
+        (h1p + h2p + 360.0) / 2.0
+    } else {
+        (h1p + h2p - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_bar_p - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar_p).to_radians().cos()
+        + 0.32 * (3.0 * h_bar_p + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar_p - 63.0).to_radians().cos();
+
+    let delta_theta = 30.0 * (-((h_bar_p - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (c_bar_p.powi(7) / (c_bar_p.powi(7) + pow25_7)).sqrt();
+    let s_l = 1.0 + 0.015 * (l_bar_p - 50.0).powi(2) / (20.0 + (l_bar_p - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar_p;
+    let s_h = 1.0 + 0.015 * c_bar_p * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let l_term = delta_lp / s_l;
+    let c_term = delta_cp / s_c;
+    let h_term = delta_big_hp / s_h;
+
+    (l_term * l_term + c_term * c_term + h_term * h_term + r_t * c_term * h_term)
+        .max(0.0)
+        .sqrt()
+}
+
+/// Returns the hue angle of `(a, b)` in degrees, within `0.0..360.0`.
+fn hue_degrees(b: f32, a: f32) -> f32 {
+    if a == 0.0 && b == 0.0 {
+        return 0.0;
+    }
+
+    let hue = b.atan2(a).to_degrees();
+    if hue < 0.0 { hue + 360.0 } else { hue }
+}
+
+/// Parses an `RRGGBB` or `RRGGBBAA` hex color, with an optional leading `#`, into RGBA.
+///
+/// Colors without an alpha part are opaque.
+pub fn parse_hex_color(s: &str) -> Result<[u8; 4], String> {
+    let digits = s.trim().strip_prefix('#').unwrap_or(s.trim());
+
+    let value = u32::from_str_radix(digits, 16)
+        .ok()
+        .filter(|_| digits.len() == 6 || digits.len() == 8)
+        .ok_or_else(|| format!("invalid color '{s}', expected RRGGBB or RRGGBBAA"))?;
+
+    let [a, b, c, d] = value.to_be_bytes();
+    Ok(if digits.len() == 6 {
+        [b, c, d, 255]
+    } else {
+        [a, b, c, d]
+    })
+}
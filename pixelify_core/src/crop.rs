@@ -1,6 +1,6 @@
 use crate::PixelifyImage;
 use crate::pixelify_errors::ImageProcessingError;
-use image::GenericImageView;
+use image::RgbaImage;
 
 /// Crops a rectangular portion of an image.
 ///
@@ -10,13 +10,15 @@ use image::GenericImageView;
 /// If the requested crop size x + w and y + h are outside the bounds of the image,
 /// then the values will be clamped to fit within the image.
 ///
+/// The result holds raw RGBA pixels.
+///
 /// # Errors
 ///
 /// Returns an error if:
 /// - loading the bytes from memory fails,
 /// - x or y is outside the image dimensions,
-/// - w or h is 0,
-/// - writing the bytes into the output fails.
+/// - w or h is 0.
+///
 /// Each error is of the type `ImageProcessingError` with a related message.
 pub fn crop_png(
     bytes: &[u8],
@@ -26,8 +28,20 @@ pub fn crop_png(
     height: u32,
 ) -> Result<PixelifyImage, ImageProcessingError> {
     let image = image::load_from_memory(bytes)
-        .map_err(|_| ImageProcessingError::failed("crop", "Failed to decode input image"))?;
+        .map_err(|_| ImageProcessingError::failed("crop", "Failed to decode input image"))?
+        .to_rgba8();
 
+    crop_image(&image, x, y, width, height).map(PixelifyImage::from)
+}
+
+/// Crops an in-memory RGBA image, with the same clamping rules as `crop_png`.
+pub(crate) fn crop_image(
+    image: &RgbaImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage, ImageProcessingError> {
     let (img_w, img_h) = image.dimensions();
 
     if x >= img_w || y >= img_h {
@@ -47,12 +61,5 @@ pub fn crop_png(
         return Err(ImageProcessingError::failed("crop", "Crop size is zero"));
     }
 
-    let cropped = image.crop_imm(x, y, width, height);
-
-    let mut cursor = std::io::Cursor::new(Vec::new());
-    cropped
-        .write_to(&mut cursor, image::ImageFormat::Png)
-        .map_err(|_| ImageProcessingError::failed("crop", "Failed to encode PNG"))?;
-
-    Ok(PixelifyImage::new(cursor.into_inner(), width, height))
+    Ok(image::imageops::crop_imm(image, x, y, width, height).to_image())
 }
@@ -0,0 +1,340 @@
+//! Dithering for palette reduction.
+//!
+//! Mapping straight to the nearest palette color produces flat bands wherever a gradient
+//! falls between two palette colors. Dithering trades those bands for a pattern of palette colors
+//! that reads as the in-between color from a distance.
+//!
+//! Two families are supported:
+//! - ordered dithering, which offsets each pixel by a threshold from a Bayer matrix,
+//! - error diffusion, which pushes the quantization error of each pixel onto its unprocessed neighbours.
+//!
+//! Both operate on raw RGBA pixels, other formats are converted first.
+//! Fully transparent pixels are left untouched and do not take part in error diffusion.
+
+use crate::palette::Palette;
+use crate::pixelify_errors::ImageProcessingError;
+use crate::{PixelFormat, PixelifyImage};
+use std::fmt;
+use std::str::FromStr;
+
+/// The dithering algorithms Pixelify offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DitherMethod {
+    Bayer2x2,
+    Bayer4x4,
+    Bayer8x8,
+    FloydSteinberg,
+    Atkinson,
+    JarvisJudiceNinke,
+    Sierra,
+}
+
+impl DitherMethod {
+    pub const ALL: [DitherMethod; 7] = [
+        DitherMethod::Bayer2x2,
+        DitherMethod::Bayer4x4,
+        DitherMethod::Bayer8x8,
+        DitherMethod::FloydSteinberg,
+        DitherMethod::Atkinson,
+        DitherMethod::JarvisJudiceNinke,
+        DitherMethod::Sierra,
+    ];
+}
+
+impl fmt::Display for DitherMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DitherMethod::Bayer2x2 => "bayer2x2",
+            DitherMethod::Bayer4x4 => "bayer4x4",
+            DitherMethod::Bayer8x8 => "bayer8x8",
+            DitherMethod::FloydSteinberg => "floyd-steinberg",
+            DitherMethod::Atkinson => "atkinson",
+            DitherMethod::JarvisJudiceNinke => "jarvis-judice-ninke",
+            DitherMethod::Sierra => "sierra",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for DitherMethod {
+    type Err = String;
+
+    /// Parses a dithering method name, ignoring case, `-` and `_`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match normalized.as_str() {
+            "bayer2x2" | "bayer2" => Ok(DitherMethod::Bayer2x2),
+            "bayer4x4" | "bayer4" | "bayer" => Ok(DitherMethod::Bayer4x4),
+            "bayer8x8" | "bayer8" => Ok(DitherMethod::Bayer8x8),
+            "floydsteinberg" | "fs" => Ok(DitherMethod::FloydSteinberg),
+            "atkinson" => Ok(DitherMethod::Atkinson),
+            "jarvisjudiceninke" | "jjn" => Ok(DitherMethod::JarvisJudiceNinke),
+            "sierra" => Ok(DitherMethod::Sierra),
+            _ => Err(format!(
+                "unknown dither method '{s}', expected one of: bayer2x2, bayer4x4, bayer8x8, \
+                 floyd-steinberg, atkinson, jarvis-judice-ninke, sierra"
+            )),
+        }
+    }
+}
+
+/// An error diffusion kernel.
+///
+/// Each entry is `(dx, dy, weight)` relative to the current pixel,
+/// and the weights are divided by `divisor` before being applied.
+struct DiffusionKernel {
+    entries: &'static [(i32, i32, f32)],
+    divisor: f32,
+}
+
+const FLOYD_STEINBERG: DiffusionKernel = DiffusionKernel {
+    entries: &[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)],
+    divisor: 16.0,
+};
+
+// Atkinson only diffuses 6/8 of the error, which keeps highlights and shadows crisp
+const ATKINSON: DiffusionKernel = DiffusionKernel {
+    entries: &[
+        (1, 0, 1.0),
+        (2, 0, 1.0),
+        (-1, 1, 1.0),
+        (0, 1, 1.0),
+        (1, 1, 1.0),
+        (0, 2, 1.0),
+    ],
+    divisor: 8.0,
+};
+
+const JARVIS_JUDICE_NINKE: DiffusionKernel = DiffusionKernel {
+    entries: &[
+        (1, 0, 7.0),
+        (2, 0, 5.0),
+        (-2, 1, 3.0),
+        (-1, 1, 5.0),
+        (0, 1, 7.0),
+        (1, 1, 5.0),
+        (2, 1, 3.0),
+        (-2, 2, 1.0),
+        (-1, 2, 3.0),
+        (0, 2, 5.0),
+        (1, 2, 3.0),
+        (2, 2, 1.0),
+    ],
+    divisor: 48.0,
+};
+
+const SIERRA: DiffusionKernel = DiffusionKernel {
+    entries: &[
+        (1, 0, 5.0),
+        (2, 0, 3.0),
+        (-2, 1, 2.0),
+        (-1, 1, 4.0),
+        (0, 1, 5.0),
+        (1, 1, 4.0),
+        (2, 1, 2.0),
+        (-1, 2, 2.0),
+        (0, 2, 3.0),
+        (1, 2, 2.0),
+    ],
+    divisor: 32.0,
+};
+
+/// Maps a raw RGBA image onto `palette` using the given dithering method.
+///
+/// `strength` scales the effect of the dither, where `0.0` is identical to a plain nearest color
+/// mapping and `1.0` is the full effect of the algorithm.
+/// The alpha channel of each pixel is kept as is.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if:
+/// - `image` cannot be converted to RGBA,
+/// - `strength` is not within `0.0..=1.0`.
+pub fn dither(
+    image: &PixelifyImage,
+    palette: &Palette,
+    method: DitherMethod,
+    strength: f32,
+) -> Result<PixelifyImage, ImageProcessingError> {
+    let rgba = image.to_rgba8()?;
+    let width = rgba.get_width() as usize;
+    let height = rgba.get_height() as usize;
+    let mut pixels = rgba.into_bytes();
+
+    dither_pixels(&mut pixels, width, height, palette, method, strength)?;
+
+    Ok(PixelifyImage::new(
+        pixels,
+        width as u32,
+        height as u32,
+        PixelFormat::Rgba8,
+    ))
+}
+
+/// Dithers a raw RGBA buffer of `width` x `height` onto `palette` in place.
+pub(crate) fn dither_pixels(
+    pixels: &mut [u8],
+    width: usize,
+    height: usize,
+    palette: &Palette,
+    method: DitherMethod,
+    strength: f32,
+) -> Result<(), ImageProcessingError> {
+    if !(0.0..=1.0).contains(&strength) {
+        return Err(ImageProcessingError::failed(
+            "dither",
+            "Dither strength must be between 0.0 and 1.0",
+        ));
+    }
+
+    match method {
+        DitherMethod::Bayer2x2 => ordered(pixels, width, palette, 2, strength),
+        DitherMethod::Bayer4x4 => ordered(pixels, width, palette, 4, strength),
+        DitherMethod::Bayer8x8 => ordered(pixels, width, palette, 8, strength),
+        DitherMethod::FloydSteinberg => {
+            diffuse(pixels, width, height, palette, &FLOYD_STEINBERG, strength)
+        }
+        DitherMethod::Atkinson => diffuse(pixels, width, height, palette, &ATKINSON, strength),
+        DitherMethod::JarvisJudiceNinke => diffuse(
+            pixels,
+            width,
+            height,
+            palette,
+            &JARVIS_JUDICE_NINKE,
+            strength,
+        ),
+        DitherMethod::Sierra => diffuse(pixels, width, height, palette, &SIERRA, strength),
+    }
+
+    Ok(())
+}
+
+fn ordered(pixels: &mut [u8], width: usize, palette: &Palette, size: u32, strength: f32) {
+    let cells = (size * size) as f32;
+    let spread = palette_spread(palette) * strength;
+
+    for (i, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+        if pixel[3] == 0 {
+            continue;
+        }
+
+        let x = (i % width) as u32;
+        let y = (i / width) as u32;
+
+        // Center the thresholds around zero so the image doesn't brighten on average
+        let threshold = (bayer_index(size, x % size, y % size) as f32 + 0.5) / cells - 0.5;
+        let offset = threshold * spread;
+
+        let color = [
+            (pixel[0] as f32 + offset).clamp(0.0, 255.0) as u8,
+            (pixel[1] as f32 + offset).clamp(0.0, 255.0) as u8,
+            (pixel[2] as f32 + offset).clamp(0.0, 255.0) as u8,
+        ];
+
+        pixel[..3].copy_from_slice(&palette.nearest(color));
+    }
+}
+
+fn diffuse(
+    pixels: &mut [u8],
+    width: usize,
+    height: usize,
+    palette: &Palette,
+    kernel: &DiffusionKernel,
+    strength: f32,
+) {
+    // Errors accumulate past the 0..=255 range, so work on a float copy of the colors
+    let mut buffer: Vec<[f32; 3]> = pixels
+        .chunks_exact(4)
+        .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+        .collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            if pixels[i * 4 + 3] == 0 {
+                continue;
+            }
+
+            let old = buffer[i].map(|c| c.clamp(0.0, 255.0));
+            let new = palette.nearest(old.map(|c| c.round() as u8));
+            pixels[i * 4..i * 4 + 3].copy_from_slice(&new);
+
+            let error = [
+                (old[0] - new[0] as f32) * strength,
+                (old[1] - new[1] as f32) * strength,
+                (old[2] - new[2] as f32) * strength,
+            ];
+
+            for &(dx, dy, weight) in kernel.entries {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+                if nx < 0 || nx >= width as i32 || ny >= height as i32 {
+                    continue;
+                }
+
+                let ni = ny as usize * width + nx as usize;
+                let factor = weight / kernel.divisor;
+                for c in 0..3 {
+                    buffer[ni][c] += error[c] * factor;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the threshold rank of `(x, y)` in a Bayer matrix of `size` x `size`.
+///
+/// Built recursively, each matrix is four copies of the half sized one,
+/// scaled by 4 and offset by `[[0, 2], [3, 1]]`.
+fn bayer_index(size: u32, x: u32, y: u32) -> u32 {
+    if size <= 1 {
+        return 0;
+    }
+
+    let half = size / 2;
+    let quadrant = match (x / half, y / half) {
+        (0, 0) => 0,
+        (1, 0) => 2,
+        (0, _) => 3,
+        _ => 1,
+    };
+
+    4 * bayer_index(half, x % half, y % half) + quadrant
+}
+
+/// Estimates how far apart the colors of `palette` are.
+///
+/// Ordered dithering needs to nudge a pixel far enough to reach the neighbouring palette colors,
+/// so the threshold is scaled by the average distance from each color to its closest neighbour.
+fn palette_spread(palette: &Palette) -> f32 {
+    let colors = palette.get_colors();
+    if colors.len() < 2 {
+        return 0.0;
+    }
+
+    let total: f32 = colors
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            colors
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, b)| {
+                    let dr = a[0] as f32 - b[0] as f32;
+                    let dg = a[1] as f32 - b[1] as f32;
+                    let db = a[2] as f32 - b[2] as f32;
+                    (dr * dr + dg * dg + db * db).sqrt()
+                })
+                .fold(f32::MAX, f32::min)
+        })
+        .sum();
+
+    total / colors.len() as f32
+}
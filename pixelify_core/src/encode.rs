@@ -0,0 +1,1170 @@
+//! Encoding images into files.
+//!
+//! Core operations return raw pixels, and `PixelifyImage::to_png` covers the common case.
+//! This module writes the other formats game engines and tools ask for:
+//! GIF, lossless WebP, BMP, TGA, QOI and ICO, each with its own options,
+//! as well as indexed PNGs that keep the exact palette order an engine expects.
+//! Animations are written as animated GIFs or APNGs.
+
+use crate::PixelifyImage;
+use crate::animation::{Animation, LoopCount};
+use crate::palette::Palette;
+use crate::pixelify_errors::ImageProcessingError;
+use crate::quantize::{QuantizeMethod, generate_palette_from_pixels};
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::codecs::qoi::QoiEncoder;
+use image::codecs::tga::TgaEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::{self, FilterType};
+use image::{ExtendedColorType, ImageEncoder, RgbaImage};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The file formats Pixelify can write.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Gif,
+    /// Always lossless, lossy WebP would smear the pixels.
+    WebP,
+    Bmp,
+    Tga,
+    Qoi,
+    Ico,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 7] = [
+        OutputFormat::Png,
+        OutputFormat::Gif,
+        OutputFormat::WebP,
+        OutputFormat::Bmp,
+        OutputFormat::Tga,
+        OutputFormat::Qoi,
+        OutputFormat::Ico,
+    ];
+
+    /// The usual file extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Gif => "gif",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Bmp => "bmp",
+            OutputFormat::Tga => "tga",
+            OutputFormat::Qoi => "qoi",
+            OutputFormat::Ico => "ico",
+        }
+    }
+
+    /// Picks the format from the extension of `path`, ignoring case.
+    ///
+    /// Returns `None` if the path has no extension or an unknown one.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<OutputFormat> {
+        let extension = path
+            .as_ref()
+            .extension()?
+            .to_string_lossy()
+            .to_ascii_lowercase();
+        extension.parse().ok()
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    /// Parses a format name or file extension, ignoring case and a leading `.`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.strip_prefix('.').unwrap_or(s).to_ascii_lowercase();
+
+        match normalized.as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "gif" => Ok(OutputFormat::Gif),
+            "webp" => Ok(OutputFormat::WebP),
+            "bmp" => Ok(OutputFormat::Bmp),
+            "tga" => Ok(OutputFormat::Tga),
+            "qoi" => Ok(OutputFormat::Qoi),
+            "ico" => Ok(OutputFormat::Ico),
+            _ => Err(format!(
+                "unknown output format '{s}', expected one of: png, gif, webp, bmp, tga, qoi, ico"
+            )),
+        }
+    }
+}
+
+/// PNG specific options.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PngOptions {
+    /// Writes a paletted PNG with a PLTE chunk, and a tRNS chunk when the image has transparency.
+    pub indexed: bool,
+    /// The palette of an indexed PNG, written in this exact order. Pixels are matched to their nearest color.
+    ///
+    /// By default the image's own colors are written in order of first appearance, alpha included,
+    /// which needs the image to have at most 256 of them.
+    pub palette: Option<Palette>,
+    /// 1, 2, 4 or 8 bits per pixel. By default the smallest depth that holds the palette.
+    pub bit_depth: Option<u8>,
+    /// With a `palette`: the entry transparent pixels use, which opaque pixels are then never matched to.
+    /// By default a transparent entry is added after the palette, and only when the image has transparency.
+    pub transparent_index: Option<u8>,
+    /// With a `palette`: pixels with an alpha below this become transparent, the others opaque.
+    pub alpha_threshold: u8,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        PngOptions {
+            indexed: false,
+            palette: None,
+            bit_depth: None,
+            transparent_index: None,
+            alpha_threshold: 128,
+        }
+    }
+}
+
+/// GIF specific options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GifOptions {
+    /// The palette index that marks transparent pixels.
+    /// By default the first index after the image colors is used, and only when the image has transparency.
+    pub transparent_index: Option<u8>,
+    /// GIF transparency is all or nothing, pixels with an alpha below this become transparent.
+    pub alpha_threshold: u8,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        GifOptions {
+            transparent_index: None,
+            alpha_threshold: 128,
+        }
+    }
+}
+
+/// TGA specific options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TgaOptions {
+    /// Run-length encode the pixels, which suits flat pixel art well.
+    pub rle: bool,
+}
+
+impl Default for TgaOptions {
+    fn default() -> Self {
+        TgaOptions { rle: true }
+    }
+}
+
+/// ICO specific options.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IcoOptions {
+    /// Square sizes to include, from 1 to 256 pixels, e.g. `[16, 32, 48, 256]`.
+    /// Each one is scaled with nearest-neighbor sampling and centered if the image is not square.
+    /// When empty, the icon holds the image at its own size.
+    pub sizes: Vec<u32>,
+}
+
+/// Options for `encode`, only the ones of the chosen format are used.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EncodeOptions {
+    pub png: PngOptions,
+    pub gif: GifOptions,
+    pub tga: TgaOptions,
+    pub ico: IcoOptions,
+}
+
+/// Encodes an image in any `PixelFormat` into the file bytes of `format`.
+///
+/// PNG output is the same as `PixelifyImage::to_png`, so grayscale images stay grayscale,
+/// unless `options.png.indexed` asks for a paletted PNG.
+/// GIF output keeps the exact colors when the image has at most 256 of them,
+/// and reduces larger images to 256 colors with median cut.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if:
+/// - the image cannot be converted to RGBA,
+/// - the image is too large for the format (65535 pixels for GIF, 256 pixels for ICO without `sizes`),
+/// - an ICO size is outside of 1 to 256,
+/// - an indexed PNG has more than 256 colors, or more than its bit depth can hold,
+/// - the encoder fails.
+pub fn encode(
+    image: &PixelifyImage,
+    format: OutputFormat,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, ImageProcessingError> {
+    if format == OutputFormat::Png && !options.png.indexed {
+        return image.to_png().map(PixelifyImage::into_bytes);
+    }
+
+    let rgba = image.to_rgba_image()?;
+    let (width, height) = rgba.dimensions();
+    let mut bytes = Vec::new();
+
+    let result = match format {
+        OutputFormat::Png => return encode_indexed_png(&rgba, &options.png),
+        OutputFormat::Gif => return encode_gif(&rgba, &options.gif),
+        OutputFormat::WebP => WebPEncoder::new_lossless(&mut bytes).write_image(
+            rgba.as_raw(),
+            width,
+            height,
+            ExtendedColorType::Rgba8,
+        ),
+        OutputFormat::Bmp => BmpEncoder::new(&mut bytes).write_image(
+            rgba.as_raw(),
+            width,
+            height,
+            ExtendedColorType::Rgba8,
+        ),
+        OutputFormat::Tga => {
+            let encoder = TgaEncoder::new(&mut bytes);
+            let encoder = if options.tga.rle {
+                encoder
+            } else {
+                encoder.disable_rle()
+            };
+            encoder.write_image(rgba.as_raw(), width, height, ExtendedColorType::Rgba8)
+        }
+        OutputFormat::Qoi => QoiEncoder::new(&mut bytes).write_image(
+            rgba.as_raw(),
+            width,
+            height,
+            ExtendedColorType::Rgba8,
+        ),
+        OutputFormat::Ico => {
+            let frames = ico_frames(&rgba, &options.ico)?;
+            IcoEncoder::new(&mut bytes).encode_images(&frames)
+        }
+    };
+
+    result.map_err(|e| {
+        ImageProcessingError::failed("encode", format!("Failed to encode {format}: {e}"))
+    })?;
+
+    Ok(bytes)
+}
+
+/// Writes a paletted PNG, see `PngOptions`.
+fn encode_indexed_png(
+    rgba: &RgbaImage,
+    options: &PngOptions,
+) -> Result<Vec<u8>, ImageProcessingError> {
+    let (palette, indices) = match &options.palette {
+        Some(palette) => index_to_palette(rgba, palette, options)?,
+        None => index_exact(rgba)?,
+    };
+
+    let bit_depth = match options.bit_depth {
+        None => [1, 2, 4, 8]
+            .into_iter()
+            .find(|bits| palette.len() <= 1 << bits)
+            .unwrap_or(8),
+        Some(bits @ (1 | 2 | 4 | 8)) if palette.len() <= 1 << bits => bits,
+        Some(bits @ (1 | 2 | 4 | 8)) => {
+            return Err(png_error(format!(
+                "A {bits}-bit PNG holds at most {} colors, the palette has {}",
+                1 << bits,
+                palette.len()
+            )));
+        }
+        Some(bits) => {
+            return Err(png_error(format!(
+                "Indexed PNGs are 1, 2, 4 or 8-bit, got {bits}"
+            )));
+        }
+    };
+
+    let (width, height) = rgba.dimensions();
+    let data = pack_indices(&indices, width as usize, bit_depth);
+
+    let plte: Vec<u8> = palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+    // tRNS may stop at the last translucent entry, the rest default to opaque
+    let trns_len = palette
+        .iter()
+        .rposition(|c| c[3] < 255)
+        .map_or(0, |i| i + 1);
+    let trns: Vec<u8> = palette[..trns_len].iter().map(|c| c[3]).collect();
+
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(match bit_depth {
+        1 => png::BitDepth::One,
+        2 => png::BitDepth::Two,
+        4 => png::BitDepth::Four,
+        _ => png::BitDepth::Eight,
+    });
+    encoder.set_palette(plte);
+    if !trns.is_empty() {
+        encoder.set_trns(trns);
+    }
+
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| png_error(format!("Failed to encode png: {e}")))?;
+    writer
+        .write_image_data(&data)
+        .map_err(|e| png_error(format!("Failed to encode png: {e}")))?;
+    writer
+        .finish()
+        .map_err(|e| png_error(format!("Failed to encode png: {e}")))?;
+
+    Ok(bytes)
+}
+
+/// Indexes an image with its own RGBA colors, in order of first appearance.
+///
+/// Fully transparent pixels all share one entry, whatever their hidden color.
+fn index_exact(rgba: &RgbaImage) -> Result<(Vec<[u8; 4]>, Vec<u8>), ImageProcessingError> {
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut indices = Vec::with_capacity(rgba.as_raw().len() / 4);
+
+    for pixel in rgba.pixels() {
+        let color = if pixel[3] == 0 { [0; 4] } else { pixel.0 };
+
+        let index = match lookup.get(&color) {
+            Some(&index) => index,
+            None if palette.len() == 256 => {
+                return Err(png_error(
+                    "The image has more than 256 colors, reduce it to a palette first",
+                ));
+            }
+            None => {
+                let index = palette.len() as u8;
+                lookup.insert(color, index);
+                palette.push(color);
+                index
+            }
+        };
+        indices.push(index);
+    }
+
+    Ok((palette, indices))
+}
+
+/// Indexes an image with a given palette, keeping its order.
+fn index_to_palette(
+    rgba: &RgbaImage,
+    palette: &Palette,
+    options: &PngOptions,
+) -> Result<(Vec<[u8; 4]>, Vec<u8>), ImageProcessingError> {
+    let is_transparent = |alpha: u8| alpha < options.alpha_threshold;
+    let has_transparency = rgba.pixels().any(|p| is_transparent(p[3]));
+
+    let mut entries: Vec<[u8; 4]> = palette
+        .get_colors()
+        .iter()
+        .map(|c| [c[0], c[1], c[2], 255])
+        .collect();
+
+    let transparent = match options.transparent_index {
+        Some(index) if index as usize >= entries.len() => {
+            return Err(png_error(format!(
+                "The transparent index {index} is outside of the {} color palette",
+                entries.len()
+            )));
+        }
+        Some(index) => Some(index as usize),
+        None if has_transparency && entries.len() == 256 => {
+            return Err(png_error(
+                "A 256 color palette leaves no room for transparency, set a transparent index",
+            ));
+        }
+        None if has_transparency => {
+            entries.push([0, 0, 0, 255]);
+            Some(entries.len() - 1)
+        }
+        None => None,
+    };
+
+    if entries.len() > 256 {
+        return Err(png_error(format!(
+            "Indexed PNGs hold at most 256 colors, the palette has {}",
+            entries.len()
+        )));
+    }
+
+    // Opaque pixels may only match the entries that are not reserved for transparency
+    let opaque: Vec<usize> = (0..palette.len())
+        .filter(|&i| Some(i) != transparent)
+        .collect();
+    let matching = if opaque.len() == palette.len() {
+        palette.clone()
+    } else {
+        let colors = opaque.iter().map(|&i| palette.get_colors()[i]).collect();
+        Palette::new(palette.get_name(), colors)
+            .map_err(|_| png_error("The palette has no colors left for opaque pixels"))?
+            .with_metric(palette.get_metric())
+    };
+
+    if let Some(index) = transparent {
+        entries[index][3] = 0;
+    }
+
+    let mut cache: HashMap<[u8; 3], u8> = HashMap::new();
+    let indices = rgba
+        .pixels()
+        .map(|pixel| {
+            if is_transparent(pixel[3]) {
+                // `transparent` is always set when a pixel is transparent
+                return transparent.unwrap_or(0) as u8;
+            }
+
+            let color = [pixel[0], pixel[1], pixel[2]];
+            *cache
+                .entry(color)
+                .or_insert_with(|| opaque[matching.nearest_index(color)] as u8)
+        })
+        .collect();
+
+    Ok((entries, indices))
+}
+
+/// Packs one index per byte into rows of `bit_depth` bits per pixel, most significant bits first.
+/// Every row starts on a new byte.
+fn pack_indices(indices: &[u8], width: usize, bit_depth: u8) -> Vec<u8> {
+    if bit_depth == 8 {
+        return indices.to_vec();
+    }
+
+    let per_byte = (8 / bit_depth) as usize;
+    indices
+        .chunks(width.max(1))
+        .flat_map(|row| {
+            row.chunks(per_byte).map(|group| {
+                group.iter().enumerate().fold(0u8, |byte, (i, &index)| {
+                    byte | index << (8 - bit_depth as usize * (i + 1))
+                })
+            })
+        })
+        .collect()
+}
+
+fn png_error(message: impl Into<String>) -> ImageProcessingError {
+    ImageProcessingError::failed("encode", message)
+}
+
+/// Frames as indices into one shared palette of at most 256 colors.
+struct IndexedFrames {
+    palette: Vec<[u8; 3]>,
+    frames: Vec<Vec<u8>>,
+    transparent: Option<u8>,
+}
+
+/// Turns the RGBA pixels of one or more frames into indices of one palette for GIF.
+///
+/// Pixels with an alpha below `options.alpha_threshold` all share the transparent index.
+fn index_for_gif(
+    frames: &[&RgbaImage],
+    options: &GifOptions,
+) -> Result<IndexedFrames, ImageProcessingError> {
+    let is_transparent = |pixel: &[u8]| pixel[3] < options.alpha_threshold;
+    let pixels = || {
+        frames
+            .iter()
+            .flat_map(|frame| frame.as_raw().chunks_exact(4))
+    };
+
+    let has_transparency = pixels().any(is_transparent);
+    let reserve = has_transparency || options.transparent_index.is_some();
+    let max_colors = if reserve { 255 } else { 256 };
+
+    // Keep the exact colors when they fit, in order of first appearance
+    let mut colors: Vec<[u8; 3]> = Vec::new();
+    let mut lookup: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut exact = true;
+    for pixel in pixels().filter(|p| !is_transparent(p)) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        if lookup.contains_key(&color) {
+            continue;
+        }
+        if colors.len() == max_colors {
+            exact = false;
+            break;
+        }
+        lookup.insert(color, colors.len() as u8);
+        colors.push(color);
+    }
+
+    let palette = if exact {
+        None
+    } else {
+        // Transparent pixels must not pull the palette towards their hidden colors
+        let opaque: Vec<Vec<u8>> = frames
+            .iter()
+            .map(|frame| {
+                let mut opaque = frame.as_raw().clone();
+                for pixel in opaque.chunks_exact_mut(4) {
+                    pixel[3] = if is_transparent(pixel) { 0 } else { 255 };
+                }
+                opaque
+            })
+            .collect();
+        let buffers: Vec<&[u8]> = opaque.iter().map(Vec::as_slice).collect();
+        let palette =
+            generate_palette_from_pixels(&buffers, max_colors, QuantizeMethod::MedianCut)?;
+        colors = palette.get_colors().to_vec();
+        Some(palette)
+    };
+
+    // Make room for the transparent index among the colors
+    let transparent = if reserve {
+        let index = options
+            .transparent_index
+            .map(|index| index as usize)
+            .unwrap_or(colors.len());
+        if index > colors.len() {
+            colors.resize(index, [0, 0, 0]);
+        }
+        colors.insert(index.min(colors.len()), [0, 0, 0]);
+        if colors.len() > 256 {
+            return Err(ImageProcessingError::failed(
+                "encode",
+                "The GIF transparent index leaves no room for the image colors",
+            ));
+        }
+        Some(index as u8)
+    } else {
+        None
+    };
+
+    // Indices past the transparent one shift up by one
+    let shift = |index: usize| match transparent {
+        Some(t) if index >= t as usize => (index + 1) as u8,
+        _ => index as u8,
+    };
+
+    let mut cache: HashMap<[u8; 3], u8> = HashMap::new();
+    let indices = frames
+        .iter()
+        .map(|frame| {
+            frame
+                .as_raw()
+                .chunks_exact(4)
+                .map(|pixel| {
+                    if is_transparent(pixel) {
+                        return transparent.unwrap_or(0);
+                    }
+
+                    let color = [pixel[0], pixel[1], pixel[2]];
+                    *cache.entry(color).or_insert_with(|| match &palette {
+                        Some(palette) => shift(palette.nearest_index(color)),
+                        None => shift(lookup[&color] as usize),
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(IndexedFrames {
+        palette: colors,
+        frames: indices,
+        transparent,
+    })
+}
+
+fn encode_gif(rgba: &RgbaImage, options: &GifOptions) -> Result<Vec<u8>, ImageProcessingError> {
+    write_gif(&[(rgba, 0)], None, options)
+}
+
+/// Writes frames of the same size, each with its delay in hundredths of a second, as a GIF with one global palette.
+fn write_gif(
+    frames: &[(&RgbaImage, u16)],
+    repeat: Option<gif::Repeat>,
+    options: &GifOptions,
+) -> Result<Vec<u8>, ImageProcessingError> {
+    let (width, height) = gif_dimensions(frames[0].0)?;
+    let images: Vec<&RgbaImage> = frames.iter().map(|(image, _)| *image).collect();
+    let indexed = index_for_gif(&images, options)?;
+
+    let mut bytes = Vec::new();
+    let mut encoder = gif::Encoder::new(&mut bytes, width, height, indexed.palette.as_flattened())
+        .map_err(gif_error)?;
+    if let Some(repeat) = repeat {
+        encoder.set_repeat(repeat).map_err(gif_error)?;
+    }
+
+    for (indices, (_, delay)) in indexed.frames.into_iter().zip(frames) {
+        let frame = gif::Frame {
+            width,
+            height,
+            delay: *delay,
+            transparent: indexed.transparent,
+            // Every frame covers the whole canvas, so clear it rather than show the last frame
+            // through transparent pixels
+            dispose: gif::DisposalMethod::Background,
+            buffer: indices.into(),
+            ..gif::Frame::default()
+        };
+        encoder.write_frame(&frame).map_err(gif_error)?;
+    }
+    drop(encoder);
+
+    Ok(bytes)
+}
+
+/// Encodes every frame of `animation` into the file bytes of `format`.
+///
+/// GIF frames share one palette, chosen like for a still GIF from the colors of all frames.
+/// PNG output is an APNG. Delays and the loop count are kept, GIF rounds delays to hundredths of a second.
+/// Formats that cannot hold an animation get the first frame, encoded like `encode` does.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if:
+/// - the animation has no frames, or frames of different sizes,
+/// - indexed PNG output is requested, which only supports still images,
+/// - any error of `encode` for the chosen format.
+pub fn encode_animation(
+    animation: &Animation,
+    format: OutputFormat,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, ImageProcessingError> {
+    let Some(first) = animation.frames.first() else {
+        return Err(ImageProcessingError::failed(
+            "encode",
+            "An animation needs at least one frame",
+        ));
+    };
+    if animation
+        .frames
+        .iter()
+        .any(|frame| frame.image.dimensions() != first.image.dimensions())
+    {
+        return Err(ImageProcessingError::failed(
+            "encode",
+            "Every frame of an animation must have the same size",
+        ));
+    }
+
+    match format {
+        OutputFormat::Gif => {
+            let frames: Vec<(&RgbaImage, u16)> = animation
+                .frames
+                .iter()
+                .map(|frame| {
+                    let (numerator, denominator) = frame.delay.numer_denom_ms();
+                    let centiseconds =
+                        (numerator as f64 / denominator.max(1) as f64 / 10.0).round();
+                    (&frame.image, centiseconds.min(u16::MAX as f64) as u16)
+                })
+                .collect();
+            let repeat = match animation.loop_count {
+                LoopCount::Infinite => gif::Repeat::Infinite,
+                // GIFs count the repeats after the first play
+                LoopCount::Times(plays) => {
+                    gif::Repeat::Finite(plays.saturating_sub(1).min(u16::MAX as u32) as u16)
+                }
+            };
+            write_gif(&frames, Some(repeat), &options.gif)
+        }
+        OutputFormat::Png if options.png.indexed => Err(png_error(
+            "Indexed PNGs can only hold still images, animations are written as RGBA",
+        )),
+        OutputFormat::Png => encode_apng(animation),
+        _ => encode(&PixelifyImage::from(first.image.clone()), format, options),
+    }
+}
+
+fn encode_apng(animation: &Animation) -> Result<Vec<u8>, ImageProcessingError> {
+    let (width, height) = animation.frames[0].image.dimensions();
+    let plays = match animation.loop_count {
+        LoopCount::Infinite => 0,
+        LoopCount::Times(plays) => plays.max(1),
+    };
+    let apng_error = |e: png::EncodingError| png_error(format!("Failed to encode png: {e}"));
+
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(animation.frames.len() as u32, plays)
+        .map_err(apng_error)?;
+
+    let mut writer = encoder.write_header().map_err(apng_error)?;
+    for frame in &animation.frames {
+        let (numerator, denominator) = apng_delay(frame.delay);
+        writer
+            .set_frame_delay(numerator, denominator)
+            .map_err(apng_error)?;
+        writer
+            .write_image_data(frame.image.as_raw())
+            .map_err(apng_error)?;
+    }
+    writer.finish().map_err(apng_error)?;
+
+    Ok(bytes)
+}
+
+/// An APNG frame delay, as a fraction of a second with 16 bit parts.
+fn apng_delay(delay: image::Delay) -> (u16, u16) {
+    let (numerator, denominator) = delay.numer_denom_ms();
+    let (numerator, denominator) = (numerator as u64, denominator as u64 * 1000);
+
+    let (mut a, mut b) = (numerator, denominator);
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    let divisor = a.max(1);
+    let (numerator, denominator) = (numerator / divisor, denominator / divisor);
+
+    match (u16::try_from(numerator), u16::try_from(denominator)) {
+        (Ok(numerator), Ok(denominator)) => (numerator, denominator),
+        // Fall back to whole milliseconds
+        _ => {
+            let milliseconds = (numerator as f64 * 1000.0 / denominator as f64).round();
+            (milliseconds.min(u16::MAX as f64) as u16, 1000)
+        }
+    }
+}
+
+fn gif_dimensions(rgba: &RgbaImage) -> Result<(u16, u16), ImageProcessingError> {
+    let (width, height) = rgba.dimensions();
+    u16::try_from(width)
+        .ok()
+        .zip(u16::try_from(height).ok())
+        .ok_or_else(|| {
+            ImageProcessingError::failed(
+                "encode",
+                format!("A {width}x{height} image is too large for GIF, the limit is 65535x65535"),
+            )
+        })
+}
+
+fn gif_error(e: gif::EncodingError) -> ImageProcessingError {
+    ImageProcessingError::failed("encode", format!("Failed to encode gif: {e}"))
+}
+
+fn ico_frames(
+    rgba: &RgbaImage,
+    options: &IcoOptions,
+) -> Result<Vec<IcoFrame<'static>>, ImageProcessingError> {
+    let (width, height) = rgba.dimensions();
+
+    let images = if options.sizes.is_empty() {
+        if width > 256 || height > 256 {
+            return Err(ImageProcessingError::failed(
+                "encode",
+                format!("A {width}x{height} image is too large for ICO, set sizes of at most 256"),
+            ));
+        }
+        vec![rgba.clone()]
+    } else {
+        options
+            .sizes
+            .iter()
+            .map(|&size| {
+                if !(1..=256).contains(&size) {
+                    return Err(ImageProcessingError::failed(
+                        "encode",
+                        format!("ICO sizes must be between 1 and 256, got {size}"),
+                    ));
+                }
+                Ok(fit_square(rgba, size))
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    images
+        .iter()
+        .map(|image| {
+            IcoFrame::as_png(
+                image.as_raw(),
+                image.width(),
+                image.height(),
+                ExtendedColorType::Rgba8,
+            )
+            .map_err(|e| {
+                ImageProcessingError::failed("encode", format!("Failed to encode ico: {e}"))
+            })
+        })
+        .collect()
+}
+
+/// Scales `rgba` with nearest-neighbor sampling to fit a `size` x `size` square, centered on transparency.
+fn fit_square(rgba: &RgbaImage, size: u32) -> RgbaImage {
+    let (width, height) = rgba.dimensions();
+    let longest = width.max(height) as u64;
+    let scaled_width = ((width as u64 * size as u64 / longest) as u32).max(1);
+    let scaled_height = ((height as u64 * size as u64 / longest) as u32).max(1);
+
+    let scaled = imageops::resize(rgba, scaled_width, scaled_height, FilterType::Nearest);
+
+    let mut square = RgbaImage::new(size, size);
+    imageops::overlay(
+        &mut square,
+        &scaled,
+        ((size - scaled_width) / 2) as i64,
+        ((size - scaled_height) / 2) as i64,
+    );
+    square
+}
+
+impl PixelifyImage {
+    /// Encodes the image into the file bytes of `format`, see `encode::encode`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `ImageProcessingError` of `encode::encode`.
+    pub fn encode(
+        &self,
+        format: OutputFormat,
+        options: &EncodeOptions,
+    ) -> Result<Vec<u8>, ImageProcessingError> {
+        encode(self, format, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{AnimationFrame, decode_animation};
+    use image::{Delay, Rgba};
+    use std::io::Cursor;
+
+    /// A `colors` x 2 image with one opaque color per column and a transparent bottom right pixel.
+    fn palette_image(colors: u32) -> RgbaImage {
+        RgbaImage::from_fn(colors, 2, |x, y| {
+            if x == colors - 1 && y == 1 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                Rgba([x as u8, 255 - x as u8, 7, 255])
+            }
+        })
+    }
+
+    fn indexed_png(
+        rgba: &RgbaImage,
+        bit_depth: Option<u8>,
+    ) -> Result<Vec<u8>, ImageProcessingError> {
+        let options = EncodeOptions {
+            png: PngOptions {
+                indexed: true,
+                bit_depth,
+                ..PngOptions::default()
+            },
+            ..EncodeOptions::default()
+        };
+        encode(
+            &PixelifyImage::from(rgba.clone()),
+            OutputFormat::Png,
+            &options,
+        )
+    }
+
+    fn animation(loop_count: LoopCount) -> Animation {
+        let frame = |color: [u8; 4]| AnimationFrame {
+            image: RgbaImage::from_pixel(4, 4, Rgba(color)),
+            delay: Delay::from_numer_denom_ms(100, 1),
+        };
+
+        Animation {
+            frames: vec![frame([255, 0, 0, 255]), frame([0, 0, 255, 255])],
+            loop_count,
+        }
+    }
+
+    #[test]
+    fn indexed_png_round_trips_at_every_bit_depth() {
+        // Colors including the transparent entry, and the smallest depth that holds them
+        for (colors, bits, depth) in [
+            (2, 1, png::BitDepth::One),
+            (4, 2, png::BitDepth::Two),
+            (16, 4, png::BitDepth::Four),
+            (200, 8, png::BitDepth::Eight),
+        ] {
+            // One column per opaque color, the transparent pixel adds the last entry
+            let rgba = palette_image(colors - 1);
+
+            for bit_depth in [None, Some(bits)] {
+                let bytes = indexed_png(&rgba, bit_depth).unwrap();
+
+                let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+                let info = reader.info();
+                assert_eq!(info.color_type, png::ColorType::Indexed);
+                assert_eq!(info.bit_depth, depth);
+                assert_eq!(info.palette.as_ref().unwrap().len(), 3 * colors as usize);
+                // Opaque entries come first, so tRNS ends at the transparent one
+                let trns = info.trns.as_ref().unwrap();
+                assert_eq!(trns.len(), colors as usize);
+                assert_eq!(trns.last(), Some(&0));
+                assert!(trns[..trns.len() - 1].iter().all(|&alpha| alpha == 255));
+
+                let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+                assert_eq!(decoded, rgba, "{colors} colors at {bit_depth:?} bits");
+            }
+        }
+    }
+
+    #[test]
+    fn indexed_png_rejects_a_bit_depth_too_small_for_the_palette() {
+        assert!(indexed_png(&palette_image(4), Some(2)).is_err());
+        assert!(indexed_png(&palette_image(4), Some(3)).is_err());
+        assert!(indexed_png(&palette_image(4), Some(4)).is_ok());
+    }
+
+    #[test]
+    fn indexed_png_with_a_palette_uses_its_transparent_index() {
+        let palette = Palette::new("test", vec![[9, 9, 9], [255, 0, 0], [0, 0, 255]]).unwrap();
+        let rgba = RgbaImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([250, 10, 10, 255]),
+            1 => Rgba([5, 5, 5, 40]),
+            _ => Rgba([10, 10, 240, 255]),
+        });
+        let options = EncodeOptions {
+            png: PngOptions {
+                indexed: true,
+                palette: Some(palette),
+                transparent_index: Some(0),
+                ..PngOptions::default()
+            },
+            ..EncodeOptions::default()
+        };
+        let bytes = encode(&PixelifyImage::from(rgba), OutputFormat::Png, &options).unwrap();
+
+        let mut reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+        assert_eq!(reader.info().bit_depth, png::BitDepth::Two);
+        assert_eq!(reader.info().trns.as_deref(), Some(&[0][..]));
+        let mut data = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut data).unwrap();
+        // Indices 1, 0 and 2 packed two bits each, most significant first
+        assert_eq!(data[0], 0b01_00_10_00);
+
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+        assert_eq!(decoded.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(decoded.get_pixel(1, 0)[3], 0);
+        assert_eq!(decoded.get_pixel(2, 0), &Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn indexed_png_without_transparency_has_no_trns() {
+        let rgba = RgbaImage::from_fn(3, 1, |x, _| Rgba([x as u8 * 80, 0, 0, 255]));
+        let bytes = indexed_png(&rgba, None).unwrap();
+
+        let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+        assert_eq!(reader.info().bit_depth, png::BitDepth::Two);
+        assert!(reader.info().trns.is_none());
+        assert_eq!(image::load_from_memory(&bytes).unwrap().to_rgba8(), rgba);
+    }
+
+    #[test]
+    fn gif_keeps_the_requested_transparent_index() {
+        let rgba = palette_image(3);
+        let options = GifOptions {
+            transparent_index: Some(5),
+            ..GifOptions::default()
+        };
+        let bytes = encode_gif(&rgba, &options).unwrap();
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(Cursor::new(&bytes))
+            .unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(frame.transparent, Some(5));
+        // The transparent pixel is the last one, colors keep their order of first appearance
+        assert_eq!(frame.buffer.last(), Some(&5));
+        assert_eq!(&frame.buffer[..3], &[0, 1, 2]);
+
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+        assert_eq!(decoded, rgba);
+    }
+
+    #[test]
+    fn gif_reserves_a_transparent_index_only_when_needed() {
+        let opaque = RgbaImage::from_pixel(2, 2, Rgba([10, 20, 30, 255]));
+        let bytes = encode_gif(&opaque, &GifOptions::default()).unwrap();
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(Cursor::new(&bytes))
+            .unwrap();
+        assert_eq!(
+            decoder.read_next_frame().unwrap().unwrap().transparent,
+            None
+        );
+
+        // The first index after the image colors
+        let bytes = encode_gif(&palette_image(3), &GifOptions::default()).unwrap();
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(Cursor::new(&bytes))
+            .unwrap();
+        assert_eq!(
+            decoder.read_next_frame().unwrap().unwrap().transparent,
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn animations_keep_their_loop_count() {
+        for format in [OutputFormat::Gif, OutputFormat::Png] {
+            for loop_count in [
+                LoopCount::Infinite,
+                LoopCount::Times(1),
+                LoopCount::Times(3),
+            ] {
+                let bytes =
+                    encode_animation(&animation(loop_count), format, &EncodeOptions::default())
+                        .unwrap();
+
+                let decoded = decode_animation(&bytes).unwrap().unwrap();
+                assert_eq!(decoded.loop_count, loop_count, "{format}");
+                assert_eq!(decoded.frames.len(), 2);
+                assert_eq!(
+                    decoded.frames[1].image.get_pixel(0, 0),
+                    &Rgba([0, 0, 255, 255])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn animated_gif_round_trips_frames_delays_and_one_palette() {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let delays_ms = [100, 50, 250];
+        let animation = Animation {
+            frames: colors
+                .iter()
+                .zip(delays_ms)
+                .map(|(&color, ms)| AnimationFrame {
+                    image: RgbaImage::from_pixel(4, 4, Rgba(color)),
+                    delay: Delay::from_numer_denom_ms(ms, 1),
+                })
+                .collect(),
+            loop_count: LoopCount::Infinite,
+        };
+        let bytes =
+            encode_animation(&animation, OutputFormat::Gif, &EncodeOptions::default()).unwrap();
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(Cursor::new(&bytes))
+            .unwrap();
+        let palette = decoder.global_palette().unwrap().to_vec();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            // Frames use the global palette instead of their own
+            assert!(frame.palette.is_none());
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, vec![10, 5, 25]);
+        for color in colors {
+            assert!(
+                palette.chunks(3).any(|entry| entry == &color[..3]),
+                "{color:?}"
+            );
+        }
+
+        let decoded = decode_animation(&bytes).unwrap().unwrap();
+        assert_eq!(decoded.frames.len(), 3);
+        for ((frame, color), ms) in decoded.frames.iter().zip(colors).zip(delays_ms) {
+            assert_eq!(frame.image.get_pixel(3, 3), &Rgba(color));
+            assert_eq!(frame.delay, Delay::from_numer_denom_ms(ms, 1));
+        }
+    }
+
+    #[test]
+    fn transparent_pixels_do_not_show_the_previous_frame() {
+        let mut second = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]));
+        second.put_pixel(1, 2, Rgba([0, 0, 0, 0]));
+        let animation = Animation {
+            frames: vec![
+                AnimationFrame {
+                    image: RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])),
+                    delay: Delay::from_numer_denom_ms(100, 1),
+                },
+                AnimationFrame {
+                    image: second,
+                    delay: Delay::from_numer_denom_ms(100, 1),
+                },
+            ],
+            loop_count: LoopCount::Infinite,
+        };
+
+        for format in [OutputFormat::Gif, OutputFormat::Png] {
+            let bytes = encode_animation(&animation, format, &EncodeOptions::default()).unwrap();
+            let decoded = decode_animation(&bytes).unwrap().unwrap();
+            let frame = &decoded.frames[1].image;
+            assert_eq!(frame.get_pixel(1, 2)[3], 0, "{format}");
+            assert_eq!(frame.get_pixel(0, 0), &Rgba([0, 0, 255, 255]), "{format}");
+        }
+    }
+
+    #[test]
+    fn gif_and_apng_loop_fields() {
+        let options = EncodeOptions::default();
+        let gif_repeat = |loop_count| {
+            let bytes =
+                encode_animation(&animation(loop_count), OutputFormat::Gif, &options).unwrap();
+            let mut decoder = gif::DecodeOptions::new()
+                .read_info(Cursor::new(bytes))
+                .unwrap();
+            while decoder.read_next_frame().unwrap().is_some() {}
+            decoder.repeat()
+        };
+        let apng_plays = |loop_count| {
+            let bytes =
+                encode_animation(&animation(loop_count), OutputFormat::Png, &options).unwrap();
+            let reader = png::Decoder::new(Cursor::new(bytes)).read_info().unwrap();
+            reader.info().animation_control.unwrap().num_plays
+        };
+
+        // GIFs count the repeats after the first play, APNGs the plays with 0 for forever
+        assert_eq!(gif_repeat(LoopCount::Infinite), gif::Repeat::Infinite);
+        assert_eq!(gif_repeat(LoopCount::Times(1)), gif::Repeat::Finite(0));
+        assert_eq!(gif_repeat(LoopCount::Times(4)), gif::Repeat::Finite(3));
+        assert_eq!(apng_plays(LoopCount::Infinite), 0);
+        assert_eq!(apng_plays(LoopCount::Times(4)), 4);
+    }
+
+    /// The width byte of each ICO directory entry, where 0 means 256.
+    fn ico_widths(bytes: &[u8]) -> Vec<u8> {
+        let count = u16::from_le_bytes([bytes[4], bytes[5]]) as usize;
+        (0..count).map(|i| bytes[6 + 16 * i]).collect()
+    }
+
+    fn ico(rgba: RgbaImage, sizes: Vec<u32>) -> Result<Vec<u8>, ImageProcessingError> {
+        let options = EncodeOptions {
+            ico: IcoOptions { sizes },
+            ..EncodeOptions::default()
+        };
+        encode(&PixelifyImage::from(rgba), OutputFormat::Ico, &options)
+    }
+
+    #[test]
+    fn ico_sizes_are_limited_to_256() {
+        let sprite = RgbaImage::from_pixel(20, 10, Rgba([1, 2, 3, 255]));
+
+        let bytes = ico(sprite.clone(), vec![1, 16, 256]).unwrap();
+        assert_eq!(ico_widths(&bytes), vec![1, 16, 0]);
+
+        assert!(ico(sprite.clone(), vec![0]).is_err());
+        assert!(ico(sprite.clone(), vec![16, 257]).is_err());
+
+        // Without sizes the image is written at its own size, which must fit
+        assert_eq!(ico_widths(&ico(sprite, vec![]).unwrap()), vec![20]);
+        assert!(ico(RgbaImage::new(256, 256), vec![]).is_ok());
+        assert!(ico(RgbaImage::new(257, 16), vec![]).is_err());
+    }
+
+    #[test]
+    fn ico_sizes_center_the_scaled_image() {
+        let sprite = RgbaImage::from_pixel(20, 10, Rgba([1, 2, 3, 255]));
+        let bytes = ico(sprite, vec![16]).unwrap();
+
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), (16, 16));
+        assert_eq!(decoded.get_pixel(0, 3)[3], 0);
+        assert_eq!(decoded.get_pixel(0, 4), &Rgba([1, 2, 3, 255]));
+        assert_eq!(decoded.get_pixel(15, 11), &Rgba([1, 2, 3, 255]));
+        assert_eq!(decoded.get_pixel(15, 12)[3], 0);
+    }
+}
@@ -1,18 +1,15 @@
-use crate::PixelifyImage;
 use crate::pixelify_errors::ImageProcessingError;
-use image::GenericImageView;
+use crate::{PixelFormat, PixelifyImage};
+use image::{DynamicImage, GenericImageView, Pixel, Rgba, RgbaImage};
 
 /// Converts image into a grayscale format.
 ///
 /// Take in image bytes, loads them into memory, then transforms it into a luma8 image.
-/// Writes those into a resultant vector.
+/// The result holds raw luma pixels, one byte per pixel.
 ///
 /// # Errors
 ///
-/// Returns an error if:
-/// - loading the bytes from memory fails,
-/// - writing the bytes into the output fails.
-/// Both failures result in an `ImageProcessingError` with a relevant message.
+/// Returns an `ImageProcessingError` if loading the bytes from memory fails.
 pub fn grayscale_png(bytes: &[u8]) -> Result<PixelifyImage, ImageProcessingError> {
     let image = image::load_from_memory(bytes)
         .map_err(|_| ImageProcessingError::failed("grayscale", "Failed to decode input image"))?;
@@ -21,9 +18,44 @@ pub fn grayscale_png(bytes: &[u8]) -> Result<PixelifyImage, ImageProcessingError
 
     let luma = image.to_luma8();
 
-    let mut out = Vec::new();
-    luma.write_to(&mut std::io::Cursor::new(&mut out), image::ImageFormat::Png)
-        .map_err(|_| ImageProcessingError::failed("grayscale", "Failed to encode PNG"))?;
+    Ok(PixelifyImage::new(
+        luma.into_raw(),
+        width,
+        height,
+        PixelFormat::Luma8,
+    ))
+}
+
+/// Converts an image that is already in memory into a grayscale format, like `grayscale_png`.
+///
+/// The result holds raw luma pixels, one byte per pixel.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if `image` cannot be converted to RGBA.
+pub fn grayscale(image: &PixelifyImage) -> Result<PixelifyImage, ImageProcessingError> {
+    let rgba = image.to_rgba_image()?;
+    let (width, height) = rgba.dimensions();
+
+    let luma = DynamicImage::ImageRgba8(rgba).to_luma8();
 
-    Ok(PixelifyImage::new(out, width, height))
+    Ok(PixelifyImage::new(
+        luma.into_raw(),
+        width,
+        height,
+        PixelFormat::Luma8,
+    ))
+}
+
+/// Converts an in-memory RGBA image to grayscale while keeping it RGBA.
+///
+/// Uses the same luma weights as `grayscale_png`, but the alpha channel is preserved
+/// so the result can keep going through RGBA operations.
+pub(crate) fn grayscale_image(image: &RgbaImage) -> RgbaImage {
+    let mut gray = image.clone();
+    for pixel in gray.pixels_mut() {
+        let [luma, alpha] = pixel.to_luma_alpha().0;
+        *pixel = Rgba([luma, luma, luma, alpha]);
+    }
+    gray
 }
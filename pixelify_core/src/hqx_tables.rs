@@ -0,0 +1,248 @@
+//! The lookup tables of hq2x, hq3x and hq4x.
+//!
+//! hqx fills every output pixel with a blend of the 3x3 window around the source pixel, picked by
+//! the pattern of neighbors that differ from it. The reference tables are the same in every corner
+//! up to mirroring and transposing, so only the pixels of the top left quarter of a block are kept
+//! here. `scalers` turns the window to read them for the other pixels.
+//!
+//! Window positions are numbered 0 1 2 / 3 4 5 / 6 7 8, with 4 the source pixel. Bits 0 to 7 of a
+//! pattern are set when the neighbor at 0, 1, 2, 3, 5, 6, 7 and 8 differs from the source pixel.
+
+/// `(position, weight)` pairs of the window, with weights in sixteenths adding up to 16.
+pub(crate) type Blend = &'static [(usize, u32)];
+
+/// How one output pixel is filled.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum HqxRule {
+    Blend(Blend),
+    /// The first blend when the two neighbors differ, the second when they are similar.
+    Edge((usize, usize), Blend, Blend),
+}
+
+/// The rules for one output pixel, and the index of the rule for every pattern.
+pub(crate) struct HqxTable {
+    pub(crate) rules: &'static [HqxRule],
+    pub(crate) patterns: [u8; 256],
+}
+
+/// hq2x, the top left pixel of the 2x2 block.
+#[rustfmt::skip]
+pub(crate) const HQ2X_CORNER: HqxTable = HqxTable {
+    rules: &[
+        HqxRule::Blend(&[(4, 8), (1, 4), (3, 4)]),
+        HqxRule::Blend(&[(4, 8), (0, 4), (1, 4)]),
+        HqxRule::Blend(&[(4, 8), (0, 4), (3, 4)]),
+        HqxRule::Blend(&[(4, 12), (1, 4)]),
+        HqxRule::Blend(&[(4, 12), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 8), (1, 4), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 12), (0, 4)], &[(4, 12), (1, 2), (3, 2)]),
+        HqxRule::Blend(&[(4, 12), (0, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 14), (1, 1), (3, 1)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 4), (1, 6), (3, 6)]),
+        HqxRule::Edge((1, 5), &[(4, 12), (3, 4)], &[(4, 10), (1, 4), (3, 2)]),
+        HqxRule::Edge((3, 7), &[(4, 12), (1, 4)], &[(4, 10), (1, 2), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 12), (0, 4)], &[(4, 4), (1, 6), (3, 6)]),
+        HqxRule::Edge((1, 3), &[(4, 12), (0, 4)], &[(4, 8), (1, 4), (3, 4)]),
+    ],
+    patterns: [
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 13, 5, 1, 3, 12, 9,
+        0, 0, 2, 10, 0, 0, 2, 10, 1, 3, 5, 5, 1, 3, 7, 5,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 12, 9, 1, 3, 6, 8,
+        0, 0, 2, 10, 0, 0, 2, 10, 1, 3, 6, 5, 1, 3, 7, 8,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 11, 5, 5, 1, 11, 6, 5,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 6, 5, 1, 3, 6, 5,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 11, 7, 5, 1, 11, 7, 8,
+        0, 0, 2, 4, 0, 0, 2, 10, 1, 3, 6, 5, 1, 11, 7, 8,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 13, 5, 1, 3, 12, 9,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 6, 5, 1, 3, 6, 5,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 12, 9, 1, 3, 6, 8,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 6, 9, 1, 3, 7, 8,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 6, 5, 1, 3, 6, 9,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 6, 5, 1, 3, 7, 5,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 6, 5, 1, 3, 7, 8,
+        0, 0, 2, 4, 0, 0, 2, 4, 1, 3, 7, 5, 1, 3, 7, 8,
+    ],
+};
+
+/// hq3x, the top left pixel of the 3x3 block.
+#[rustfmt::skip]
+pub(crate) const HQ3X_CORNER: HqxTable = HqxTable {
+    rules: &[
+        HqxRule::Blend(&[(4, 12), (0, 4)]),
+        HqxRule::Blend(&[(4, 8), (1, 4), (3, 4)]),
+        HqxRule::Blend(&[(4, 12), (1, 4)]),
+        HqxRule::Blend(&[(4, 12), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 2), (1, 7), (3, 7)]),
+        HqxRule::Edge((1, 3), &[(4, 12), (0, 4)], &[(4, 8), (1, 4), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 8), (1, 4), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(1, 8), (3, 8)]),
+        HqxRule::Edge((1, 5), &[(4, 12), (3, 4)], &[(4, 8), (1, 4), (3, 4)]),
+        HqxRule::Edge((3, 7), &[(4, 12), (1, 4)], &[(4, 8), (1, 4), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 12), (0, 4)], &[(1, 8), (3, 8)]),
+        HqxRule::Edge((1, 3), &[(4, 12), (0, 4)], &[(4, 2), (1, 7), (3, 7)]),
+    ],
+    patterns: [
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 11, 4, 0, 2, 10, 7,
+        1, 1, 0, 8, 1, 1, 0, 8, 0, 2, 4, 4, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 10, 7, 0, 2, 5, 6,
+        1, 1, 0, 8, 1, 1, 0, 8, 0, 2, 5, 4, 0, 2, 0, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 9, 4, 4, 0, 9, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 9, 0, 4, 0, 9, 0, 6,
+        1, 1, 0, 3, 1, 1, 0, 8, 0, 2, 5, 4, 0, 9, 0, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 11, 4, 0, 2, 10, 7,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 10, 7, 0, 2, 5, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 7, 0, 2, 0, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 5, 7,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 0, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 0, 4, 0, 2, 0, 6,
+    ],
+};
+
+/// hq3x, the top middle pixel of the 3x3 block.
+#[rustfmt::skip]
+pub(crate) const HQ3X_EDGE: HqxTable = HqxTable {
+    rules: &[
+        HqxRule::Blend(&[(4, 12), (1, 4)]),
+        HqxRule::Blend(&[(4, 16)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 14), (1, 2)]),
+        HqxRule::Edge((1, 5), &[(4, 16)], &[(4, 14), (1, 2)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 4), (1, 12)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 12), (1, 4)]),
+        HqxRule::Edge((1, 5), &[(4, 16)], &[(4, 4), (1, 12)]),
+        HqxRule::Edge((1, 5), &[(4, 16)], &[(4, 12), (1, 4)]),
+    ],
+    patterns: [
+        0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 4, 4,
+        0, 0, 3, 6, 0, 0, 3, 6, 0, 0, 1, 2, 0, 0, 3, 1,
+        0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 5, 5, 0, 0, 1, 1,
+        0, 0, 3, 6, 0, 0, 3, 6, 0, 0, 1, 2, 0, 0, 3, 1,
+        0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 2,
+        0, 0, 3, 1, 0, 0, 3, 3, 0, 0, 1, 2, 0, 0, 3, 1,
+        0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 1, 1,
+        0, 0, 1, 1, 0, 0, 3, 6, 0, 0, 1, 2, 0, 0, 3, 2,
+        0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 4, 4,
+        0, 0, 7, 1, 0, 0, 7, 1, 0, 0, 1, 2, 0, 0, 3, 1,
+        0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 5, 5, 0, 0, 1, 1,
+        0, 0, 7, 1, 0, 0, 7, 1, 0, 0, 1, 5, 0, 0, 7, 1,
+        0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 1, 4,
+        0, 0, 1, 1, 0, 0, 3, 1, 0, 0, 1, 2, 0, 0, 3, 3,
+        0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 1, 1,
+        0, 0, 1, 1, 0, 0, 3, 1, 0, 0, 1, 2, 0, 0, 3, 1,
+    ],
+};
+
+/// hq3x, the center pixel of the 3x3 block, which always keeps the source pixel.
+pub(crate) const HQ3X_CENTER: HqxTable = HqxTable {
+    rules: &[HqxRule::Blend(&[(4, 16)])],
+    patterns: [0; 256],
+};
+
+/// hq4x, the top left pixel of the 4x4 block.
+#[rustfmt::skip]
+pub(crate) const HQ4X_CORNER: HqxTable = HqxTable {
+    rules: &[
+        HqxRule::Blend(&[(4, 10), (0, 6)]),
+        HqxRule::Blend(&[(4, 8), (1, 4), (3, 4)]),
+        HqxRule::Blend(&[(4, 10), (1, 6)]),
+        HqxRule::Blend(&[(4, 10), (3, 6)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(1, 8), (3, 8)]),
+        HqxRule::Edge((1, 3), &[(4, 10), (0, 6)], &[(4, 8), (1, 4), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 8), (1, 4), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 10), (0, 6)], &[(1, 8), (3, 8)]),
+        HqxRule::Edge((1, 5), &[(4, 10), (3, 6)], &[(4, 12), (1, 4)]),
+        HqxRule::Edge((3, 7), &[(4, 10), (1, 6)], &[(4, 12), (3, 4)]),
+    ],
+    patterns: [
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 7, 4, 0, 2, 7, 4,
+        1, 1, 0, 8, 1, 1, 0, 8, 0, 2, 4, 4, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 7, 4, 0, 2, 5, 6,
+        1, 1, 0, 8, 1, 1, 0, 8, 0, 2, 5, 4, 0, 2, 0, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 9, 4, 4, 0, 9, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 9, 0, 4, 0, 9, 0, 6,
+        1, 1, 0, 3, 1, 1, 0, 8, 0, 2, 5, 4, 0, 9, 0, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 7, 4, 0, 2, 7, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 7, 4, 0, 2, 5, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 0, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 0, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 0, 4, 0, 2, 0, 6,
+    ],
+};
+
+/// hq4x, the second pixel of the top row of the 4x4 block.
+#[rustfmt::skip]
+pub(crate) const HQ4X_EDGE: HqxTable = HqxTable {
+    rules: &[
+        HqxRule::Blend(&[(4, 10), (1, 4), (3, 2)]),
+        HqxRule::Blend(&[(4, 12), (0, 4)]),
+        HqxRule::Blend(&[(4, 10), (0, 2), (1, 4)]),
+        HqxRule::Blend(&[(4, 10), (1, 6)]),
+        HqxRule::Blend(&[(4, 14), (3, 2)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 8), (1, 8)]),
+        HqxRule::Edge((1, 3), &[(4, 12), (0, 4)], &[(4, 12), (1, 4)]),
+        HqxRule::Blend(&[(4, 16)]),
+        HqxRule::Edge((1, 5), &[(4, 14), (3, 2)], &[(4, 4), (1, 12)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(1, 10), (3, 6)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 4), (1, 8), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 12), (0, 4)], &[(1, 10), (3, 6)]),
+        HqxRule::Edge((1, 3), &[(4, 12), (0, 4)], &[(4, 4), (1, 8), (3, 4)]),
+        HqxRule::Edge((1, 3), &[(4, 12), (0, 4)], &[(4, 8), (1, 8)]),
+    ],
+    patterns: [
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 13, 5, 2, 3, 11, 9,
+        0, 0, 1, 8, 0, 0, 1, 8, 2, 3, 5, 5, 2, 3, 1, 5,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 12, 10, 2, 3, 6, 7,
+        0, 0, 1, 8, 0, 0, 1, 8, 2, 3, 6, 5, 2, 3, 1, 7,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 5, 5, 2, 3, 6, 5,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 6, 5, 2, 3, 6, 5,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 1, 5, 2, 3, 1, 7,
+        0, 0, 1, 4, 0, 0, 1, 8, 2, 3, 6, 5, 2, 3, 1, 7,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 13, 5, 2, 3, 11, 9,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 6, 5, 2, 3, 6, 5,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 12, 10, 2, 3, 6, 7,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 6, 10, 2, 3, 1, 7,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 6, 5, 2, 3, 6, 9,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 6, 5, 2, 3, 1, 5,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 6, 5, 2, 3, 1, 7,
+        0, 0, 1, 4, 0, 0, 1, 4, 2, 3, 1, 5, 2, 3, 1, 7,
+    ],
+};
+
+/// hq4x, the second pixel of the second row of the 4x4 block.
+#[rustfmt::skip]
+pub(crate) const HQ4X_INNER: HqxTable = HqxTable {
+    rules: &[
+        HqxRule::Blend(&[(4, 14), (0, 2)]),
+        HqxRule::Blend(&[(4, 12), (1, 2), (3, 2)]),
+        HqxRule::Blend(&[(4, 14), (1, 2)]),
+        HqxRule::Blend(&[(4, 14), (3, 2)]),
+        HqxRule::Blend(&[(4, 16)]),
+        HqxRule::Edge((1, 3), &[(4, 14), (0, 2)], &[(4, 16)]),
+        HqxRule::Edge((1, 3), &[(4, 16)], &[(4, 12), (1, 2), (3, 2)]),
+        HqxRule::Edge((1, 3), &[(4, 14), (0, 2)], &[(4, 12), (1, 2), (3, 2)]),
+    ],
+    patterns: [
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 7, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 4, 4, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 7, 6, 0, 2, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 4, 4, 0, 2, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 0, 4, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 7, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 7, 6, 0, 2, 5, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 6, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 5, 6,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 5, 4, 0, 2, 0, 4,
+        1, 1, 0, 3, 1, 1, 0, 3, 0, 2, 0, 4, 0, 2, 0, 4,
+    ],
+};
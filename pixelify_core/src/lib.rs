@@ -1,6 +1,21 @@
+pub mod animation;
+pub mod atlas;
+pub mod background;
+pub mod color;
 pub mod crop;
+pub mod dither;
+pub mod encode;
 pub mod grayscale;
+mod hqx_tables;
+pub mod metadata;
+pub mod outline;
+pub mod palette;
+pub mod pipeline;
 pub mod pixelify;
 pub mod pixelify_errors;
 pub mod pixelify_image;
-pub use pixelify_image::PixelifyImage;
+pub mod quantize;
+mod scalers;
+pub mod tilemap;
+pub mod upscale;
+pub use pixelify_image::{PixelFormat, PixelifyImage};
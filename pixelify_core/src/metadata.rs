@@ -0,0 +1,414 @@
+//! Processing metadata sidecars.
+//!
+//! A `Metadata` records how an output was produced: a SHA-256 hash and the dimensions of the source file,
+//! every operation that ran with its parameters, the colors of the result with how many pixels use each one,
+//! and the output dimensions. Written next to an asset as JSON, it lets an asset pipeline trace a sprite
+//! back to its source and reproduce it later.
+//!
+//! Operations are written with the same fields as the steps of a CLI recipe,
+//! so the `operations` list can be pasted into a recipe as its `steps`.
+
+use crate::animation::decode_animation;
+use crate::background::BackgroundKey;
+use crate::outline::Connectivity;
+use crate::palette::{BuiltinPalette, Palette};
+use crate::pipeline::{Operation, PaletteSource};
+use crate::pixelify::BlockSize;
+use crate::pixelify_errors::ImageProcessingError;
+use crate::quantize::QuantizeMethod;
+use crate::upscale::UpscaleSize;
+use image::{ImageReader, RgbaImage};
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Cursor;
+
+/// The metadata version written by this build.
+pub const METADATA_VERSION: u32 = 1;
+
+/// The file an output was produced from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SourceInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Lowercase hex SHA-256 of the file bytes.
+    pub sha256: String,
+    pub width: u32,
+    pub height: u32,
+    /// The number of frames, only set for animations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frames: Option<usize>,
+}
+
+impl SourceInfo {
+    /// Hashes the bytes of a source file and reads its dimensions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if the bytes are not an image in a supported format.
+    pub fn new(bytes: &[u8]) -> Result<SourceInfo, ImageProcessingError> {
+        let (width, height, frames) = match decode_animation(bytes)? {
+            Some(animation) => {
+                let (width, height) = animation.frames[0].image.dimensions();
+                (width, height, Some(animation.frames.len()))
+            }
+            None => {
+                let (width, height) = ImageReader::new(Cursor::new(bytes))
+                    .with_guessed_format()
+                    .ok()
+                    .and_then(|reader| reader.into_dimensions().ok())
+                    .ok_or_else(|| metadata_error("Failed to read the source dimensions"))?;
+                (width, height, None)
+            }
+        };
+
+        Ok(SourceInfo {
+            file: None,
+            sha256: to_hex(&Sha256::digest(bytes)),
+            width,
+            height,
+            frames,
+        })
+    }
+}
+
+/// One color of the output and the number of pixels that use it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColorCount {
+    /// `#rrggbb`, or `#rrggbbaa` for translucent pixels.
+    pub color: String,
+    pub count: u64,
+}
+
+/// The result that was written.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutputInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    /// The number of frames, only set for animations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frames: Option<usize>,
+}
+
+/// How an output was produced from its source.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Metadata {
+    pub version: u32,
+    pub source: SourceInfo,
+    /// The operations in the order they ran, with the fields of a recipe step.
+    pub operations: Vec<Value>,
+    /// Every color of the output, most used first. Fully transparent pixels are left out.
+    pub palette: Vec<ColorCount>,
+    pub output: OutputInfo,
+}
+
+impl Metadata {
+    /// Records that `operations` turned `source` into `output`, the frames of the result.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if `output` has no frames.
+    pub fn new(
+        source: SourceInfo,
+        operations: &[Operation],
+        output: &[RgbaImage],
+    ) -> Result<Metadata, ImageProcessingError> {
+        let Some(first) = output.first() else {
+            return Err(metadata_error("The output has no frames"));
+        };
+
+        Ok(Metadata {
+            version: METADATA_VERSION,
+            source,
+            operations: operations.iter().map(describe_operation).collect(),
+            palette: count_colors(output),
+            output: OutputInfo {
+                file: None,
+                format: None,
+                width: first.width(),
+                height: first.height(),
+                frames: (output.len() > 1).then_some(output.len()),
+            },
+        })
+    }
+
+    /// Records that `operations` turned `source` into `encoded`, the bytes of the written output.
+    ///
+    /// The output is decoded again, so the palette, counts and dimensions describe what was written,
+    /// after any color loss from the output format, such as GIF transparency thresholds.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if `encoded` is not an image in a supported format.
+    pub fn from_encoded(
+        source: SourceInfo,
+        operations: &[Operation],
+        encoded: &[u8],
+    ) -> Result<Metadata, ImageProcessingError> {
+        let frames = match decode_animation(encoded)? {
+            Some(animation) => animation.images(),
+            None => vec![
+                image::load_from_memory(encoded)
+                    .map_err(|_| metadata_error("Failed to decode the output"))?
+                    .to_rgba8(),
+            ],
+        };
+        Metadata::new(source, operations, &frames)
+    }
+
+    /// Serializes the metadata as pretty-printed JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if serialization fails.
+    pub fn to_json(&self) -> Result<String, ImageProcessingError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| metadata_error(format!("Failed to serialize metadata: {e}")))
+    }
+}
+
+fn describe_operation(operation: &Operation) -> Value {
+    match operation {
+        Operation::Crop {
+            x,
+            y,
+            width,
+            height,
+        } => json!({ "op": "crop", "x": x, "y": y, "width": width, "height": height }),
+        Operation::DownscaleByPixelSize {
+            pixel_size,
+            options,
+        } => json!({
+            "op": "downscale",
+            "pixel_size": describe_block_size(*pixel_size),
+            "linear": options.linear_light,
+            "reducer": options.reducer.to_string(),
+            "edges": options.edges.to_string(),
+            "alpha": options.alpha.to_string(),
+        }),
+        Operation::FalseDownscaleByPixelSize {
+            pixel_size,
+            options,
+        } => json!({
+            "op": "downscale",
+            "pixel_size": describe_block_size(*pixel_size),
+            "keep_size": true,
+            "linear": options.linear_light,
+            "reducer": options.reducer.to_string(),
+            "edges": options.edges.to_string(),
+            "alpha": options.alpha.to_string(),
+        }),
+        Operation::DownscaleByImageSize {
+            width,
+            height,
+            options,
+        } => {
+            let mut step = Map::new();
+            step.insert(String::from("op"), json!("downscale"));
+            if let Some(width) = width {
+                step.insert(String::from("width"), json!(width));
+            }
+            if let Some(height) = height {
+                step.insert(String::from("height"), json!(height));
+            }
+            step.insert(String::from("linear"), json!(options.linear_light));
+            step.insert(String::from("reducer"), json!(options.reducer.to_string()));
+            step.insert(String::from("resize"), json!(options.resize.to_string()));
+            step.insert(String::from("alpha"), json!(options.alpha.to_string()));
+            Value::Object(step)
+        }
+        Operation::Palette(source) => {
+            let mut step = Map::new();
+            step.insert(String::from("op"), json!("palette"));
+            step.extend(describe_palette(source));
+            Value::Object(step)
+        }
+        Operation::Dither {
+            palette,
+            method,
+            strength,
+        } => json!({
+            "op": "dither",
+            "method": method.to_string(),
+            "strength": strength,
+            "palette": describe_palette(palette),
+        }),
+        Operation::Grayscale => json!({ "op": "grayscale" }),
+        Operation::Upscale { size, options } => {
+            let mut step = Map::new();
+            step.insert(String::from("op"), json!("upscale"));
+            step.insert(String::from("method"), json!(options.method.to_string()));
+            match size {
+                UpscaleSize::Factor(factor) => {
+                    step.insert(String::from("factor"), json!(factor));
+                }
+                UpscaleSize::Fit { width, height } => {
+                    step.insert(String::from("width"), json!(width));
+                    step.insert(String::from("height"), json!(height));
+                }
+            }
+            if let Some(color) = options.pixel_grid {
+                step.insert(String::from("grid_color"), json!(hex_color(color)));
+            }
+            Value::Object(step)
+        }
+        Operation::Outline(options) => json!({
+            "op": "outline",
+            "placement": options.placement.to_string(),
+            "connectivity": match options.connectivity {
+                Connectivity::Four => 4,
+                Connectivity::Eight => 8,
+            },
+            "thickness": options.thickness,
+            "color": options.color.to_string(),
+        }),
+        Operation::RemoveBackground(options) => {
+            let mut step = Map::new();
+            step.insert(String::from("op"), json!("remove_background"));
+            match &options.key {
+                BackgroundKey::Corners => {}
+                BackgroundKey::Seeds(seeds) => {
+                    let seeds: Vec<[u32; 2]> = seeds.iter().map(|&(x, y)| [x, y]).collect();
+                    step.insert(String::from("seeds"), json!(seeds));
+                }
+                BackgroundKey::Color([r, g, b]) => {
+                    step.insert(String::from("color"), json!(hex_color([*r, *g, *b, 255])));
+                }
+            }
+            step.insert(String::from("tolerance"), json!(options.tolerance));
+            step.insert(String::from("feather"), json!(options.feather));
+            Value::Object(step)
+        }
+    }
+}
+
+/// A square block size as a number, like recipes write it, other sizes as `WxH`.
+fn describe_block_size(size: BlockSize) -> Value {
+    if size.is_square() {
+        json!(size.width)
+    } else {
+        json!(size.to_string())
+    }
+}
+
+/// The fields of a recipe palette: a built-in name, the colors of any other fixed palette,
+/// or the settings of an adaptive one.
+fn describe_palette(source: &PaletteSource) -> Map<String, Value> {
+    let mut fields = Map::new();
+    let metric = match source {
+        PaletteSource::Fixed(palette) => {
+            match builtin_of(palette) {
+                Some(builtin) => fields.insert(String::from("builtin"), json!(builtin.to_string())),
+                None => fields.insert(
+                    String::from("hex"),
+                    json!(
+                        palette
+                            .get_colors()
+                            .iter()
+                            .map(|&[r, g, b]| hex_color([r, g, b, 255]))
+                            .collect::<Vec<_>>()
+                    ),
+                ),
+            };
+            palette.get_metric()
+        }
+        PaletteSource::Adaptive {
+            colors,
+            method,
+            metric,
+        } => {
+            fields.insert(String::from("colors"), json!(colors));
+            fields.insert(String::from("quantize"), json!(method.to_string()));
+            if let QuantizeMethod::KMeans { seed } = method {
+                fields.insert(String::from("seed"), json!(seed));
+            }
+            *metric
+        }
+    };
+    fields.insert(String::from("metric"), json!(metric.to_string()));
+    fields
+}
+
+/// The built-in palette `palette` was created from, if any.
+fn builtin_of(palette: &Palette) -> Option<BuiltinPalette> {
+    let builtin = palette.get_name().parse::<BuiltinPalette>().ok()?;
+    (Palette::builtin(builtin).get_colors() == palette.get_colors()).then_some(builtin)
+}
+
+fn count_colors(frames: &[RgbaImage]) -> Vec<ColorCount> {
+    let mut counts: HashMap<[u8; 4], u64> = HashMap::new();
+    for pixel in frames.iter().flat_map(|frame| frame.pixels()) {
+        if pixel[3] > 0 {
+            *counts.entry(pixel.0).or_default() += 1;
+        }
+    }
+
+    let mut counts: Vec<([u8; 4], u64)> = counts.into_iter().collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+    counts
+        .into_iter()
+        .map(|(color, count)| ColorCount {
+            color: hex_color(color),
+            count,
+        })
+        .collect()
+}
+
+/// `#rrggbb` for opaque colors, `#rrggbbaa` otherwise.
+fn hex_color([r, g, b, a]: [u8; 4]) -> String {
+    if a == 255 {
+        format!("#{r:02x}{g:02x}{b:02x}")
+    } else {
+        format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn metadata_error(message: impl Into<String>) -> ImageProcessingError {
+    ImageProcessingError::failed("metadata", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorCount, Metadata, SourceInfo};
+    use crate::encode::{EncodeOptions, OutputFormat};
+    use crate::pixelify_image::PixelifyImage;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn palette_is_counted_from_the_encoded_output() {
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, Rgba([0, 0, 255, 100]));
+        let source = SourceInfo {
+            file: None,
+            sha256: String::new(),
+            width: 2,
+            height: 1,
+            frames: None,
+        };
+
+        // GIF has no partial transparency, so the translucent pixel is written fully transparent
+        let encoded = PixelifyImage::from(image)
+            .encode(OutputFormat::Gif, &EncodeOptions::default())
+            .unwrap();
+        let metadata = Metadata::from_encoded(source, &[], &encoded).unwrap();
+
+        assert_eq!(
+            metadata.palette,
+            vec![ColorCount {
+                color: String::from("#ff0000"),
+                count: 1,
+            }]
+        );
+        assert_eq!((metadata.output.width, metadata.output.height), (2, 1));
+    }
+}
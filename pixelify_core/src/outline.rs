@@ -0,0 +1,292 @@
+//! Outlines around the opaque shapes of a sprite.
+//!
+//! Any pixel with some alpha is part of the sprite. An outer outline colors the transparent pixels
+//! around the sprite, an inner outline recolors the edge pixels of the sprite itself, so the image
+//! keeps its size either way. Pixels outside the image count as transparent.
+//!
+//! The outline is grown one pixel at a time from the edge, through 4 neighbors for rounded,
+//! diamond-like corners or 8 neighbors for square ones.
+
+use crate::PixelifyImage;
+use crate::color::{linear_to_srgb, parse_hex_color, srgb_to_linear};
+use crate::pixelify_errors::ImageProcessingError;
+use image::{Rgba, RgbaImage};
+use std::fmt;
+use std::str::FromStr;
+
+/// How much darker a shaded outline is when no amount is given.
+pub const DEFAULT_SHADE: f32 = 0.5;
+
+/// Which side of the sprite edge the outline is drawn on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutlinePlacement {
+    /// Around the sprite, on the transparent pixels next to it.
+    #[default]
+    Outer,
+    /// On the edge pixels of the sprite itself.
+    Inner,
+}
+
+impl fmt::Display for OutlinePlacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutlinePlacement::Outer => write!(f, "outer"),
+            OutlinePlacement::Inner => write!(f, "inner"),
+        }
+    }
+}
+
+impl FromStr for OutlinePlacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "outer" | "outside" => Ok(OutlinePlacement::Outer),
+            "inner" | "inside" => Ok(OutlinePlacement::Inner),
+            _ => Err(format!(
+                "unknown outline placement '{s}', expected one of: outer, inner"
+            )),
+        }
+    }
+}
+
+/// Which neighbors of a pixel the outline grows through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Left, right, up and down. Leaves the corners of the sprite open.
+    #[default]
+    Four,
+    /// The diagonals too. Closes the corners.
+    Eight,
+}
+
+impl Connectivity {
+    fn offsets(self) -> &'static [(i32, i32)] {
+        match self {
+            Connectivity::Four => &[(-1, 0), (1, 0), (0, -1), (0, 1)],
+            Connectivity::Eight => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Connectivity::Four => write!(f, "4"),
+            Connectivity::Eight => write!(f, "8"),
+        }
+    }
+}
+
+impl FromStr for Connectivity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "4" | "four" => Ok(Connectivity::Four),
+            "8" | "eight" => Ok(Connectivity::Eight),
+            _ => Err(format!("unknown connectivity '{s}', expected 4 or 8")),
+        }
+    }
+}
+
+/// The color of the outline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutlineColor {
+    /// One RGBA color for the whole outline.
+    Color([u8; 4]),
+    /// A darker shade of the sprite pixel next to the outline, also known as a selective outline.
+    /// The amount goes from `0.0`, the same color, to `1.0`, black.
+    Shade(f32),
+}
+
+impl Default for OutlineColor {
+    fn default() -> Self {
+        OutlineColor::Color([0, 0, 0, 255])
+    }
+}
+
+impl fmt::Display for OutlineColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutlineColor::Color([r, g, b, a]) => write!(f, "#{r:02x}{g:02x}{b:02x}{a:02x}"),
+            OutlineColor::Shade(amount) => write!(f, "shade:{amount}"),
+        }
+    }
+}
+
+impl FromStr for OutlineColor {
+    type Err = String;
+
+    /// Parses an `RRGGBB` or `RRGGBBAA` color, `shade` for the default shade, or `shade:AMOUNT`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, amount) = match s.split_once(':') {
+            Some((name, amount)) => (name, Some(amount.trim())),
+            None => (s, None),
+        };
+
+        match (name.trim().to_ascii_lowercase().as_str(), amount) {
+            ("shade" | "selective", None) => Ok(OutlineColor::Shade(DEFAULT_SHADE)),
+            ("shade" | "selective", Some(amount)) => amount
+                .parse::<f32>()
+                .map(OutlineColor::Shade)
+                .map_err(|_| format!("invalid shade amount '{amount}', expected 0.0 to 1.0")),
+            _ => parse_hex_color(s).map(OutlineColor::Color),
+        }
+    }
+}
+
+/// Options for `outline`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutlineOptions {
+    pub placement: OutlinePlacement,
+    pub connectivity: Connectivity,
+    /// The width of the outline in pixels, at least 1.
+    pub thickness: u32,
+    pub color: OutlineColor,
+}
+
+impl Default for OutlineOptions {
+    fn default() -> Self {
+        Self {
+            placement: OutlinePlacement::default(),
+            connectivity: Connectivity::default(),
+            thickness: 1,
+            color: OutlineColor::default(),
+        }
+    }
+}
+
+/// Draws an outline around, or along the inside of, the opaque shapes of an image.
+///
+/// The result holds raw RGBA pixels of the same size as the input.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if:
+/// - `image` cannot be converted to RGBA,
+/// - the thickness is 0,
+/// - a shade amount is not within `0.0..=1.0`.
+pub fn outline(
+    image: &PixelifyImage,
+    options: &OutlineOptions,
+) -> Result<PixelifyImage, ImageProcessingError> {
+    let rgba = image.to_rgba_image()?;
+    outline_image(&rgba, options).map(PixelifyImage::from)
+}
+
+/// Outlines an in-memory RGBA image, see `outline`.
+pub(crate) fn outline_image(
+    image: &RgbaImage,
+    options: &OutlineOptions,
+) -> Result<RgbaImage, ImageProcessingError> {
+    if options.thickness == 0 {
+        return Err(ImageProcessingError::failed(
+            "outline",
+            "Outline thickness must be at least 1",
+        ));
+    }
+    if let OutlineColor::Shade(amount) = options.color
+        && !(0.0..=1.0).contains(&amount)
+    {
+        return Err(ImageProcessingError::failed(
+            "outline",
+            "Shade amount must be between 0.0 and 1.0",
+        ));
+    }
+
+    let (width, height) = image.dimensions();
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+    let is_solid = |x: u32, y: u32| image.get_pixel(x, y).0[3] > 0;
+
+    // An outer outline grows from the sprite into the transparent pixels, an inner one the other way around.
+    // Every frontier pixel carries the pixel it was grown from, for an outer outline that is the sprite pixel to shade.
+    let grows_into = |x: u32, y: u32| match options.placement {
+        OutlinePlacement::Outer => !is_solid(x, y),
+        OutlinePlacement::Inner => is_solid(x, y),
+    };
+    let mut reached = vec![false; (width * height) as usize];
+    let mut frontier: Vec<(u32, u32, usize)> = Vec::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            if !grows_into(x, y) {
+                // Every pixel the outline cannot grow into is a starting point
+                reached[index(x, y)] = true;
+                frontier.push((x, y, index(x, y)));
+            }
+        }
+    }
+
+    let mut outline_pixels: Vec<(u32, u32, usize)> = Vec::new();
+
+    for step in 0..options.thickness {
+        let mut next = Vec::new();
+
+        for &(x, y, source) in &frontier {
+            for &(dx, dy) in options.connectivity.offsets() {
+                let (nx, ny) = (x as i64 + dx as i64, y as i64 + dy as i64);
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                let (nx, ny) = (nx as u32, ny as u32);
+                if !reached[index(nx, ny)] {
+                    reached[index(nx, ny)] = true;
+                    next.push((nx, ny, source));
+                }
+            }
+        }
+
+        // The outside of the image is transparent, so an inner outline also grows in from the border
+        if options.placement == OutlinePlacement::Inner {
+            for y in 0..height {
+                for x in 0..width {
+                    let depth = x.min(y).min(width - 1 - x).min(height - 1 - y);
+                    if depth == step && !reached[index(x, y)] {
+                        reached[index(x, y)] = true;
+                        next.push((x, y, index(x, y)));
+                    }
+                }
+            }
+        }
+
+        outline_pixels.extend_from_slice(&next);
+        frontier = next;
+    }
+
+    let mut outlined = image.clone();
+    for (x, y, source) in outline_pixels {
+        // The sprite pixel under an inner outline is its own neighbor
+        let neighbor = match options.placement {
+            OutlinePlacement::Outer => {
+                let source = source as u32;
+                image.get_pixel(source % width, source / width).0
+            }
+            OutlinePlacement::Inner => image.get_pixel(x, y).0,
+        };
+
+        let color = match options.color {
+            OutlineColor::Color(color) => color,
+            OutlineColor::Shade(amount) => shade(neighbor, amount),
+        };
+        outlined.put_pixel(x, y, Rgba(color));
+    }
+
+    Ok(outlined)
+}
+
+/// Darkens a color by `amount` in linear light, keeping its alpha.
+fn shade([r, g, b, a]: [u8; 4], amount: f32) -> [u8; 4] {
+    let [r, g, b] =
+        [r, g, b].map(|channel| linear_to_srgb(srgb_to_linear(channel) * (1.0 - amount)));
+    [r, g, b, a]
+}
@@ -0,0 +1,349 @@
+//! Palette reduction for Pixelify.
+//!
+//! A `Palette` is an ordered list of RGB colors that an image can be limited to.
+//! Every pixel of an RGBA image gets mapped to its nearest palette color, while the alpha channel is left untouched.
+//!
+//! A handful of retro console palettes are built in (NES, Game Boy, Pico-8 and CGA),
+//! and custom palettes can be created from any list of colors.
+//!
+//! How "nearest" is measured is set per palette with a `ColorMetric`, which defaults to OKLab.
+
+use crate::color::ColorMetric;
+use crate::pixelify_errors::ImageProcessingError;
+use crate::{PixelFormat, PixelifyImage};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// An ordered list of RGB colors.
+///
+/// The order of the colors is preserved, so index `i` always refers to the same color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    name: String,
+    colors: Vec<[u8; 3]>,
+    metric: ColorMetric,
+    // The colors converted into the space of `metric`, so matching doesn't convert them per pixel
+    matching: Vec<[f32; 3]>,
+}
+
+impl Palette {
+    /// Creates a palette from a list of RGB colors.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if `colors` is empty.
+    pub fn new(
+        name: impl Into<String>,
+        colors: Vec<[u8; 3]>,
+    ) -> Result<Palette, ImageProcessingError> {
+        if colors.is_empty() {
+            return Err(ImageProcessingError::failed(
+                "palette",
+                "A palette needs at least one color",
+            ));
+        }
+
+        Ok(Self::with_colors(name.into(), colors))
+    }
+
+    fn with_colors(name: String, colors: Vec<[u8; 3]>) -> Palette {
+        let metric = ColorMetric::default();
+        Self {
+            matching: colors.iter().map(|&c| metric.to_space(c)).collect(),
+            name,
+            colors,
+            metric,
+        }
+    }
+
+    /// Returns the palette with nearest color matching done in `metric`.
+    pub fn with_metric(mut self, metric: ColorMetric) -> Palette {
+        self.matching = self.colors.iter().map(|&c| metric.to_space(c)).collect();
+        self.metric = metric;
+        self
+    }
+
+    /// Returns one of the built-in retro palettes.
+    pub fn builtin(builtin: BuiltinPalette) -> Palette {
+        Self::with_colors(
+            builtin.to_string(),
+            builtin
+                .colors()
+                .iter()
+                .map(|&hex| hex_to_rgb(hex))
+                .collect(),
+        )
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    pub fn get_metric(&self) -> ColorMetric {
+        self.metric
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Returns the index of the palette color closest to `color`.
+    ///
+    /// Distance is measured with the palette's `ColorMetric`.
+    /// Ties are resolved in favour of the color that comes first in the palette.
+    pub fn nearest_index(&self, color: [u8; 3]) -> usize {
+        let target = self.metric.to_space(color);
+        let mut best_index = 0;
+        let mut best_distance = f32::MAX;
+
+        for (i, candidate) in self.matching.iter().enumerate() {
+            let distance = self.metric.distance(target, *candidate);
+            if distance < best_distance {
+                best_distance = distance;
+                best_index = i;
+            }
+        }
+
+        best_index
+    }
+
+    /// Returns the palette color closest to `color`.
+    pub fn nearest(&self, color: [u8; 3]) -> [u8; 3] {
+        self.colors[self.nearest_index(color)]
+    }
+
+    /// Parses a palette in the `.hex` format used by Lospec, one `RRGGBB` color per line.
+    ///
+    /// Blank lines are skipped and a leading `#` on a color is allowed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if a line is not a 6 digit hex color or no colors were found.
+    pub fn from_hex(name: impl Into<String>, text: &str) -> Result<Palette, ImageProcessingError> {
+        let mut colors = Vec::new();
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let digits = line.strip_prefix('#').unwrap_or(line);
+            let hex = u32::from_str_radix(digits, 16)
+                .ok()
+                .filter(|_| digits.len() == 6)
+                .ok_or_else(|| {
+                    ImageProcessingError::failed("palette", format!("Invalid hex color '{line}'"))
+                })?;
+            colors.push(hex_to_rgb(hex));
+        }
+
+        Palette::new(name, colors)
+    }
+
+    /// Parses a GIMP `.gpl` palette.
+    ///
+    /// The palette takes the name from the `Name:` header when there is one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if the `GIMP Palette` header is missing,
+    /// a color line is malformed, or no colors were found.
+    pub fn from_gpl(text: &str) -> Result<Palette, ImageProcessingError> {
+        let mut lines = text.lines().map(str::trim);
+
+        if lines.next() != Some("GIMP Palette") {
+            return Err(ImageProcessingError::failed(
+                "palette",
+                "Missing 'GIMP Palette' header",
+            ));
+        }
+
+        let mut name = String::from("gpl");
+        let mut colors = Vec::new();
+
+        for line in lines {
+            if let Some(value) = line.strip_prefix("Name:") {
+                name = value.trim().to_string();
+                continue;
+            }
+
+            // Anything that doesn't start with a channel value is a header or a comment
+            if !line.starts_with(|c: char| c.is_ascii_digit()) {
+                continue;
+            }
+
+            let channels: Vec<u8> = line
+                .split_whitespace()
+                .take(3)
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| {
+                    ImageProcessingError::failed("palette", format!("Invalid color line '{line}'"))
+                })?;
+
+            let [r, g, b] = channels[..] else {
+                return Err(ImageProcessingError::failed(
+                    "palette",
+                    format!("Invalid color line '{line}'"),
+                ));
+            };
+            colors.push([r, g, b]);
+        }
+
+        Palette::new(name, colors)
+    }
+
+    /// Exports the palette in the `.hex` format used by Lospec, one lowercase `rrggbb` color per line.
+    pub fn to_hex(&self) -> String {
+        self.colors
+            .iter()
+            .map(|[r, g, b]| format!("{r:02x}{g:02x}{b:02x}\n"))
+            .collect()
+    }
+
+    /// Exports the palette as a GIMP `.gpl` palette, which Aseprite and most paint programs can load.
+    pub fn to_gpl(&self) -> String {
+        let mut out = format!("GIMP Palette\nName: {}\nColumns: 0\n#\n", self.name);
+        for [r, g, b] in &self.colors {
+            out.push_str(&format!("{r:>3} {g:>3} {b:>3}\t#{r:02x}{g:02x}{b:02x}\n"));
+        }
+        out
+    }
+}
+
+/// The retro palettes that ship with Pixelify.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinPalette {
+    /// The NES (2C02) palette, without the duplicated blacks.
+    Nes,
+    /// The four shades of green of the original Game Boy (DMG).
+    GameBoy,
+    /// The 16 colors of the Pico-8 fantasy console.
+    Pico8,
+    /// The full 16 color RGBI palette of the IBM CGA.
+    Cga,
+}
+
+impl BuiltinPalette {
+    pub const ALL: [BuiltinPalette; 4] = [
+        BuiltinPalette::Nes,
+        BuiltinPalette::GameBoy,
+        BuiltinPalette::Pico8,
+        BuiltinPalette::Cga,
+    ];
+
+    fn colors(self) -> &'static [u32] {
+        match self {
+            BuiltinPalette::Nes => &NES,
+            BuiltinPalette::GameBoy => &GAME_BOY,
+            BuiltinPalette::Pico8 => &PICO_8,
+            BuiltinPalette::Cga => &CGA,
+        }
+    }
+}
+
+impl fmt::Display for BuiltinPalette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BuiltinPalette::Nes => "nes",
+            BuiltinPalette::GameBoy => "gameboy",
+            BuiltinPalette::Pico8 => "pico8",
+            BuiltinPalette::Cga => "cga",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for BuiltinPalette {
+    type Err = String;
+
+    /// Parses a palette name, ignoring case, `-` and `_`.
+    /// So `game-boy`, `GameBoy` and `game_boy` all resolve to `BuiltinPalette::GameBoy`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match normalized.as_str() {
+            "nes" => Ok(BuiltinPalette::Nes),
+            "gameboy" | "gb" | "dmg" => Ok(BuiltinPalette::GameBoy),
+            "pico8" => Ok(BuiltinPalette::Pico8),
+            "cga" => Ok(BuiltinPalette::Cga),
+            _ => Err(format!(
+                "unknown palette '{s}', expected one of: nes, gameboy, pico8, cga"
+            )),
+        }
+    }
+}
+
+/// Maps every pixel of an image to its nearest palette color.
+///
+/// The image is converted to RGBA first, and the result holds raw RGBA pixels.
+/// The alpha channel of each pixel is kept as is, only the RGB channels are replaced.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if `image` cannot be converted to RGBA.
+pub fn apply_palette(
+    image: &PixelifyImage,
+    palette: &Palette,
+) -> Result<PixelifyImage, ImageProcessingError> {
+    let rgba = image.to_rgba8()?;
+    let (width, height) = (rgba.get_width(), rgba.get_height());
+    let mut mapped = rgba.into_bytes();
+
+    map_to_palette(&mut mapped, palette);
+
+    Ok(PixelifyImage::new(
+        mapped,
+        width,
+        height,
+        PixelFormat::Rgba8,
+    ))
+}
+
+/// Maps a raw RGBA buffer onto `palette` in place.
+pub(crate) fn map_to_palette(pixels: &mut [u8], palette: &Palette) {
+    // Sprites tend to reuse a small set of colors, so remember the ones already matched
+    let mut cache: HashMap<[u8; 3], [u8; 3]> = HashMap::new();
+
+    for pixel in pixels.chunks_exact_mut(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let nearest = *cache.entry(color).or_insert_with(|| palette.nearest(color));
+
+        pixel[..3].copy_from_slice(&nearest);
+    }
+}
+
+fn hex_to_rgb(hex: u32) -> [u8; 3] {
+    [(hex >> 16) as u8, (hex >> 8) as u8, hex as u8]
+}
+
+const NES: [u32; 55] = [
+    0x7C7C7C, 0x0000FC, 0x0000BC, 0x4428BC, 0x940084, 0xA80020, 0xA81000, 0x881400, 0x503000,
+    0x007800, 0x006800, 0x005800, 0x004058, 0x000000, 0xBCBCBC, 0x0078F8, 0x0058F8, 0x6844FC,
+    0xD800CC, 0xE40058, 0xF83800, 0xE45C10, 0xAC7C00, 0x00B800, 0x00A800, 0x00A844, 0x008888,
+    0xF8F8F8, 0x3CBCFC, 0x6888FC, 0x9878F8, 0xF878F8, 0xF85898, 0xF87858, 0xFCA044, 0xF8B800,
+    0xB8F818, 0x58D854, 0x58F898, 0x00E8D8, 0x787878, 0xFCFCFC, 0xA4E4FC, 0xB8B8F8, 0xD8B8F8,
+    0xF8B8F8, 0xF8A4C0, 0xF0D0B0, 0xFCE0A8, 0xF8D878, 0xD8F878, 0xB8F8B8, 0xB8F8D8, 0x00FCFC,
+    0xF8D8F8,
+];
+
+const GAME_BOY: [u32; 4] = [0x0F380F, 0x306230, 0x8BAC0F, 0x9BBC0F];
+
+const PICO_8: [u32; 16] = [
+    0x000000, 0x1D2B53, 0x7E2553, 0x008751, 0xAB5236, 0x5F574F, 0xC2C3C7, 0xFFF1E8, 0xFF004D,
+    0xFFA300, 0xFFEC27, 0x00E436, 0x29ADFF, 0x83769C, 0xFF77A8, 0xFFCCAA,
+];
+
+const CGA: [u32; 16] = [
+    0x000000, 0x0000AA, 0x00AA00, 0x00AAAA, 0xAA0000, 0xAA00AA, 0xAA5500, 0xAAAAAA, 0x555555,
+    0x5555FF, 0x55FF55, 0x55FFFF, 0xFF5555, 0xFF55FF, 0xFFFF55, 0xFFFFFF,
+];
@@ -0,0 +1,406 @@
+//! Chaining operations without repeated decoding.
+//!
+//! Each standalone function in this crate decodes its input bytes, so running crop, then pixelify,
+//! then a palette means decoding and encoding the image at every step.
+//! A `Pipeline` decodes the input once, runs an ordered list of `Operation`s on the in-memory
+//! RGBA image, and only encodes when asked to at the end.
+//!
+//! The frames of an animation can run through a pipeline together with `run_frames`,
+//! which gives every frame the same pixel grid and the same adaptive palette.
+
+use crate::PixelifyImage;
+use crate::animation::Animation;
+use crate::background::{BackgroundOptions, remove_background_image};
+use crate::color::ColorMetric;
+use crate::crop::crop_image;
+use crate::dither::{DitherMethod, dither_pixels};
+use crate::grayscale::grayscale_image;
+use crate::outline::{OutlineOptions, outline_image};
+use crate::palette::{Palette, map_to_palette};
+use crate::pixelify::{
+    BlockSize, PixelifyOptions, downscale_by_image_size, downscale_by_pixel_size,
+    false_downscale_by_pixel_size,
+};
+use crate::pixelify_errors::ImageProcessingError;
+use crate::quantize::{QuantizeMethod, generate_palette_from_pixels};
+use crate::upscale::{UpscaleOptions, UpscaleSize, upscale_image};
+use image::RgbaImage;
+
+/// Where a palette step gets its colors from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaletteSource {
+    /// A palette known ahead of time, such as a built-in one or one loaded from a file.
+    Fixed(Palette),
+    /// A palette derived from the image as it arrives at this step.
+    Adaptive {
+        colors: usize,
+        method: QuantizeMethod,
+        metric: ColorMetric,
+    },
+}
+
+impl PaletteSource {
+    /// The palette for `images`, an adaptive palette is derived from all of them together.
+    fn resolve(&self, images: &[&RgbaImage]) -> Result<Palette, ImageProcessingError> {
+        match self {
+            PaletteSource::Fixed(palette) => Ok(palette.clone()),
+            PaletteSource::Adaptive {
+                colors,
+                method,
+                metric,
+            } => Ok(generate_palette_from_pixels(
+                &images
+                    .iter()
+                    .map(|image| image.as_raw().as_slice())
+                    .collect::<Vec<_>>(),
+                *colors,
+                *method,
+            )?
+            .with_metric(*metric)),
+        }
+    }
+}
+
+/// A single step of a `Pipeline`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    /// See `crop::crop_png`.
+    Crop {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    /// See `pixelify::pixelify_downscale_by_pixel_size`.
+    DownscaleByPixelSize {
+        pixel_size: BlockSize,
+        options: PixelifyOptions,
+    },
+    /// See `pixelify::pixelify_false_downscale_by_pixel_size`.
+    FalseDownscaleByPixelSize {
+        pixel_size: BlockSize,
+        options: PixelifyOptions,
+    },
+    /// See `pixelify::pixelify_by_image_size`.
+    /// A missing width or height is derived from the aspect ratio of the image,
+    /// like `pixelify::pixelify_by_image_width` and `pixelify::pixelify_by_image_height`.
+    DownscaleByImageSize {
+        width: Option<u32>,
+        height: Option<u32>,
+        options: PixelifyOptions,
+    },
+    /// See `palette::apply_palette`.
+    Palette(PaletteSource),
+    /// See `dither::dither`.
+    Dither {
+        palette: PaletteSource,
+        method: DitherMethod,
+        strength: f32,
+    },
+    /// Converts to grayscale like `grayscale::grayscale_png`, but keeps the alpha channel.
+    Grayscale,
+    /// See `upscale::upscale`.
+    Upscale {
+        size: UpscaleSize,
+        options: UpscaleOptions,
+    },
+    /// See `outline::outline`.
+    Outline(OutlineOptions),
+    /// See `background::remove_background`.
+    RemoveBackground(BackgroundOptions),
+}
+
+impl Operation {
+    /// Applies this operation to an in-memory RGBA image.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `ImageProcessingError` of the underlying operation.
+    pub fn apply(&self, image: RgbaImage) -> Result<RgbaImage, ImageProcessingError> {
+        match self {
+            Operation::Crop {
+                x,
+                y,
+                width,
+                height,
+            } => crop_image(&image, *x, *y, *width, *height),
+            Operation::DownscaleByPixelSize {
+                pixel_size,
+                options,
+            } => downscale_by_pixel_size(&image, *pixel_size, options),
+            Operation::FalseDownscaleByPixelSize {
+                pixel_size,
+                options,
+            } => false_downscale_by_pixel_size(&image, *pixel_size, options),
+            Operation::DownscaleByImageSize {
+                width,
+                height,
+                options,
+            } => downscale_by_image_size(&image, *width, *height, options),
+            Operation::Palette(source) => {
+                let palette = source.resolve(&[&image])?;
+                let mut image = image;
+                map_to_palette(&mut image, &palette);
+                Ok(image)
+            }
+            Operation::Dither {
+                palette,
+                method,
+                strength,
+            } => {
+                let palette = palette.resolve(&[&image])?;
+                let (width, height) = image.dimensions();
+                let mut image = image;
+                dither_pixels(
+                    &mut image,
+                    width as usize,
+                    height as usize,
+                    &palette,
+                    *method,
+                    *strength,
+                )?;
+                Ok(image)
+            }
+            Operation::Grayscale => Ok(grayscale_image(&image)),
+            Operation::Upscale { size, options } => upscale_image(&image, *size, options),
+            Operation::Outline(options) => outline_image(&image, options),
+            Operation::RemoveBackground(options) => remove_background_image(&image, options),
+        }
+    }
+
+    /// Applies this operation to every frame of an animation.
+    ///
+    /// An adaptive palette is derived once from all frames, so every frame is mapped to the same colors.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `ImageProcessingError` of the underlying operation.
+    pub fn apply_frames(
+        &self,
+        frames: Vec<RgbaImage>,
+    ) -> Result<Vec<RgbaImage>, ImageProcessingError> {
+        let shared = |source: &PaletteSource| -> Result<PaletteSource, ImageProcessingError> {
+            let images: Vec<&RgbaImage> = frames.iter().collect();
+            Ok(PaletteSource::Fixed(source.resolve(&images)?))
+        };
+
+        let fixed = match self {
+            Operation::Palette(source @ PaletteSource::Adaptive { .. }) => {
+                Some(Operation::Palette(shared(source)?))
+            }
+            Operation::Dither {
+                palette: source @ PaletteSource::Adaptive { .. },
+                method,
+                strength,
+            } => Some(Operation::Dither {
+                palette: shared(source)?,
+                method: *method,
+                strength: *strength,
+            }),
+            _ => None,
+        };
+
+        let operation = fixed.as_ref().unwrap_or(self);
+        frames
+            .into_iter()
+            .map(|frame| operation.apply(frame))
+            .collect()
+    }
+}
+
+/// An ordered list of operations that runs on a single decoded image.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pipeline {
+    operations: Vec<Operation>,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Self::default()
+    }
+
+    /// Appends an operation to the end of the pipeline.
+    pub fn then(mut self, operation: Operation) -> Pipeline {
+        self.operations.push(operation);
+        self
+    }
+
+    pub fn crop(self, x: u32, y: u32, width: u32, height: u32) -> Pipeline {
+        self.then(Operation::Crop {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    pub fn downscale_by_pixel_size(
+        self,
+        pixel_size: impl Into<BlockSize>,
+        options: PixelifyOptions,
+    ) -> Pipeline {
+        self.then(Operation::DownscaleByPixelSize {
+            pixel_size: pixel_size.into(),
+            options,
+        })
+    }
+
+    pub fn false_downscale_by_pixel_size(
+        self,
+        pixel_size: impl Into<BlockSize>,
+        options: PixelifyOptions,
+    ) -> Pipeline {
+        self.then(Operation::FalseDownscaleByPixelSize {
+            pixel_size: pixel_size.into(),
+            options,
+        })
+    }
+
+    pub fn downscale_by_image_size(
+        self,
+        width: u32,
+        height: u32,
+        options: PixelifyOptions,
+    ) -> Pipeline {
+        self.then(Operation::DownscaleByImageSize {
+            width: Some(width),
+            height: Some(height),
+            options,
+        })
+    }
+
+    /// Downscales to `width`, with the height that keeps the aspect ratio.
+    pub fn downscale_by_image_width(self, width: u32, options: PixelifyOptions) -> Pipeline {
+        self.then(Operation::DownscaleByImageSize {
+            width: Some(width),
+            height: None,
+            options,
+        })
+    }
+
+    /// Downscales to `height`, with the width that keeps the aspect ratio.
+    pub fn downscale_by_image_height(self, height: u32, options: PixelifyOptions) -> Pipeline {
+        self.then(Operation::DownscaleByImageSize {
+            width: None,
+            height: Some(height),
+            options,
+        })
+    }
+
+    pub fn palette(self, palette: Palette) -> Pipeline {
+        self.then(Operation::Palette(PaletteSource::Fixed(palette)))
+    }
+
+    pub fn adaptive_palette(
+        self,
+        colors: usize,
+        method: QuantizeMethod,
+        metric: ColorMetric,
+    ) -> Pipeline {
+        self.then(Operation::Palette(PaletteSource::Adaptive {
+            colors,
+            method,
+            metric,
+        }))
+    }
+
+    pub fn dither(self, palette: PaletteSource, method: DitherMethod, strength: f32) -> Pipeline {
+        self.then(Operation::Dither {
+            palette,
+            method,
+            strength,
+        })
+    }
+
+    pub fn grayscale(self) -> Pipeline {
+        self.then(Operation::Grayscale)
+    }
+
+    pub fn upscale(self, size: UpscaleSize, options: UpscaleOptions) -> Pipeline {
+        self.then(Operation::Upscale { size, options })
+    }
+
+    pub fn outline(self, options: OutlineOptions) -> Pipeline {
+        self.then(Operation::Outline(options))
+    }
+
+    pub fn remove_background(self, options: BackgroundOptions) -> Pipeline {
+        self.then(Operation::RemoveBackground(options))
+    }
+
+    pub fn get_operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Decodes `bytes` once and runs every operation on the result, in order.
+    ///
+    /// The result holds raw RGBA pixels.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if decoding fails or any operation fails.
+    /// Operations after the failing one are not run.
+    pub fn run(&self, bytes: &[u8]) -> Result<PixelifyImage, ImageProcessingError> {
+        let image = image::load_from_memory(bytes)
+            .map_err(|_| ImageProcessingError::failed("pipeline", "Failed to decode input image"))?
+            .to_rgba8();
+
+        self.run_rgba(image).map(PixelifyImage::from)
+    }
+
+    /// Runs every operation on an image that is already in memory, in any `PixelFormat`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if `image` cannot be converted to RGBA or any operation fails.
+    pub fn run_image(&self, image: &PixelifyImage) -> Result<PixelifyImage, ImageProcessingError> {
+        self.run_rgba(image.to_rgba_image()?)
+            .map(PixelifyImage::from)
+    }
+
+    /// Same as `run`, but encodes the result as PNG once every operation is done.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if decoding, any operation, or encoding fails.
+    pub fn run_to_png(&self, bytes: &[u8]) -> Result<PixelifyImage, ImageProcessingError> {
+        self.run(bytes)?.to_png()
+    }
+
+    /// Runs every operation on an RGBA image, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `ImageProcessingError` of the first operation that fails.
+    pub fn run_rgba(&self, image: RgbaImage) -> Result<RgbaImage, ImageProcessingError> {
+        self.operations
+            .iter()
+            .try_fold(image, |image, operation| operation.apply(image))
+    }
+
+    /// Runs every operation on all frames of an animation, see `Operation::apply_frames`.
+    ///
+    /// Frames of the same size are cut into the same pixel grid, and adaptive palettes are shared by all frames.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `ImageProcessingError` of the first operation that fails on any frame.
+    pub fn run_frames(
+        &self,
+        frames: Vec<RgbaImage>,
+    ) -> Result<Vec<RgbaImage>, ImageProcessingError> {
+        self.operations
+            .iter()
+            .try_fold(frames, |frames, operation| operation.apply_frames(frames))
+    }
+
+    /// Runs every operation on the frames of `animation`, keeping its delays and loop count.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `ImageProcessingError` of `run_frames`.
+    pub fn run_animation(&self, animation: Animation) -> Result<Animation, ImageProcessingError> {
+        let frames = self.run_frames(animation.images())?;
+        animation.with_images(frames)
+    }
+}
@@ -10,52 +10,505 @@
 //! Or they should be able to enter in their desired image size, ex, w = 128, h = 72, and then the backed determine pixel size from that.
 
 use crate::PixelifyImage;
+use crate::color::{linear_to_srgb, parse_hex_color, srgb_to_linear};
 use crate::pixelify_errors::ImageProcessingError;
 use image::RgbaImage;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 
+/// How the pixels of a block are reduced to the one color of the block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlockReducer {
+    /// The arithmetic mean of every channel. Smooth, but blends edges into in-between colors.
+    #[default]
+    Mean,
+    /// The median of every channel on its own.
+    Median,
+    /// The most frequent color of the block.
+    Mode,
+    /// The mean of the largest group of similar colors, which ignores outliers without needing exact repeats.
+    Dominant,
+    /// The pixel in the center of the block.
+    Center,
+    /// The visible pixel with the lowest luminance, which keeps thin dark lines.
+    Darkest,
+    /// The visible pixel with the highest luminance.
+    Brightest,
+}
+
+impl BlockReducer {
+    pub const ALL: [BlockReducer; 7] = [
+        BlockReducer::Mean,
+        BlockReducer::Median,
+        BlockReducer::Mode,
+        BlockReducer::Dominant,
+        BlockReducer::Center,
+        BlockReducer::Darkest,
+        BlockReducer::Brightest,
+    ];
+}
+
+impl fmt::Display for BlockReducer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BlockReducer::Mean => "mean",
+            BlockReducer::Median => "median",
+            BlockReducer::Mode => "mode",
+            BlockReducer::Dominant => "dominant",
+            BlockReducer::Center => "center",
+            BlockReducer::Darkest => "darkest",
+            BlockReducer::Brightest => "brightest",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for BlockReducer {
+    type Err = String;
+
+    /// Parses a block reducer name, ignoring case, `-` and `_`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match normalized.as_str() {
+            "mean" | "average" => Ok(BlockReducer::Mean),
+            "median" => Ok(BlockReducer::Median),
+            "mode" | "mostfrequent" => Ok(BlockReducer::Mode),
+            "dominant" => Ok(BlockReducer::Dominant),
+            "center" | "centre" => Ok(BlockReducer::Center),
+            "darkest" | "min" => Ok(BlockReducer::Darkest),
+            "brightest" | "max" => Ok(BlockReducer::Brightest),
+            _ => Err(format!(
+                "unknown block reducer '{s}', expected one of: mean, median, mode, dominant, \
+                 center, darkest, brightest"
+            )),
+        }
+    }
+}
+
+/// The size of the blocks an image is cut into, in source pixels.
+///
+/// Blocks do not have to be square, a 2 x 1 block emulates the wide pixels of C64 multicolor modes.
+/// A single number converts into a square block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl BlockSize {
+    pub fn new(width: u32, height: u32) -> BlockSize {
+        BlockSize { width, height }
+    }
+
+    pub fn is_square(self) -> bool {
+        self.width == self.height
+    }
+}
+
+impl From<u32> for BlockSize {
+    fn from(size: u32) -> Self {
+        BlockSize::new(size, size)
+    }
+}
+
+impl From<(u32, u32)> for BlockSize {
+    fn from((width, height): (u32, u32)) -> Self {
+        BlockSize::new(width, height)
+    }
+}
+
+impl fmt::Display for BlockSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_square() {
+            write!(f, "{}", self.width)
+        } else {
+            write!(f, "{}x{}", self.width, self.height)
+        }
+    }
+}
+
+impl FromStr for BlockSize {
+    type Err = String;
+
+    /// Parses `N` for a square block, or `WxH`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid block size '{s}', expected N or WxH, such as 8 or 4x2");
+        let parse = |part: &str| part.trim().parse::<u32>().map_err(|_| error());
+
+        match s.to_ascii_lowercase().split_once('x') {
+            Some((width, height)) => Ok(BlockSize::new(parse(width)?, parse(height)?)),
+            None => parse(s).map(BlockSize::from),
+        }
+    }
+}
+
+/// What fills the missing pixels of edge blocks when padding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    /// Repeats the nearest edge pixel.
+    Replicate,
+    Transparent,
+    /// An RGBA color.
+    Color([u8; 4]),
+}
+
+/// What happens to the remainder columns and rows when the image size is not a multiple of the pixel size.
+///
+/// A false downscale always keeps the image size, the remainder a policy drops is left as it was.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EdgePolicy {
+    /// Drops the remainder on the right and bottom.
+    #[default]
+    Crop,
+    /// Drops the remainder, split between both sides so the grid sits in the center.
+    Center,
+    /// Keeps the remainder as smaller blocks, reduced from the pixels they have.
+    Partial,
+    /// Pads the image up to whole blocks.
+    Pad(Padding),
+}
+
+impl fmt::Display for EdgePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdgePolicy::Crop => write!(f, "crop"),
+            EdgePolicy::Center => write!(f, "center"),
+            EdgePolicy::Partial => write!(f, "partial"),
+            EdgePolicy::Pad(Padding::Replicate) => write!(f, "pad-replicate"),
+            EdgePolicy::Pad(Padding::Transparent) => write!(f, "pad-transparent"),
+            EdgePolicy::Pad(Padding::Color([r, g, b, a])) => {
+                write!(f, "pad-color:#{r:02x}{g:02x}{b:02x}{a:02x}")
+            }
+        }
+    }
+}
+
+impl FromStr for EdgePolicy {
+    type Err = String;
+
+    /// Parses an edge policy name, ignoring case, `-` and `_`.
+    /// A padding color is written as `pad-color:RRGGBB` or `pad-color:RRGGBBAA`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, color) = match s.split_once(':') {
+            Some((name, color)) => (name, Some(color)),
+            None => (s, None),
+        };
+        let normalized: String = name
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match (normalized.as_str(), color) {
+            ("crop", None) => Ok(EdgePolicy::Crop),
+            ("center" | "centre", None) => Ok(EdgePolicy::Center),
+            ("partial", None) => Ok(EdgePolicy::Partial),
+            ("padreplicate" | "replicate", None) => Ok(EdgePolicy::Pad(Padding::Replicate)),
+            ("padtransparent" | "transparent", None) => Ok(EdgePolicy::Pad(Padding::Transparent)),
+            ("padcolor" | "color", Some(color)) => {
+                Ok(EdgePolicy::Pad(Padding::Color(parse_hex_color(color)?)))
+            }
+            _ => Err(format!(
+                "unknown edge policy '{s}', expected one of: crop, center, partial, pad-replicate, \
+                 pad-transparent, pad-color:RRGGBB"
+            )),
+        }
+    }
+}
+
+/// Where the kept part of the image sits when cropping to fill a size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    pub const ALL: [Anchor; 9] = [
+        Anchor::TopLeft,
+        Anchor::Top,
+        Anchor::TopRight,
+        Anchor::Left,
+        Anchor::Center,
+        Anchor::Right,
+        Anchor::BottomLeft,
+        Anchor::Bottom,
+        Anchor::BottomRight,
+    ];
+
+    /// The offset of a span of `size` within `length`, along the x axis.
+    fn offset_x(self, length: u32, size: u32) -> u32 {
+        match self {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => 0,
+            Anchor::Top | Anchor::Center | Anchor::Bottom => (length - size) / 2,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => length - size,
+        }
+    }
+
+    /// The offset of a span of `size` within `length`, along the y axis.
+    fn offset_y(self, length: u32, size: u32) -> u32 {
+        match self {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => 0,
+            Anchor::Left | Anchor::Center | Anchor::Right => (length - size) / 2,
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => length - size,
+        }
+    }
+}
+
+impl fmt::Display for Anchor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Anchor::TopLeft => "top-left",
+            Anchor::Top => "top",
+            Anchor::TopRight => "top-right",
+            Anchor::Left => "left",
+            Anchor::Center => "center",
+            Anchor::Right => "right",
+            Anchor::BottomLeft => "bottom-left",
+            Anchor::Bottom => "bottom",
+            Anchor::BottomRight => "bottom-right",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Anchor {
+    type Err = String;
+
+    /// Parses an anchor name, ignoring case, `-` and `_`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match normalized.as_str() {
+            "topleft" => Ok(Anchor::TopLeft),
+            "top" => Ok(Anchor::Top),
+            "topright" => Ok(Anchor::TopRight),
+            "left" => Ok(Anchor::Left),
+            "center" | "centre" => Ok(Anchor::Center),
+            "right" => Ok(Anchor::Right),
+            "bottomleft" => Ok(Anchor::BottomLeft),
+            "bottom" => Ok(Anchor::Bottom),
+            "bottomright" => Ok(Anchor::BottomRight),
+            _ => Err(format!(
+                "unknown anchor '{s}', expected one of: top-left, top, top-right, left, center, \
+                 right, bottom-left, bottom, bottom-right"
+            )),
+        }
+    }
+}
+
+/// How `pixelify_by_image_size` fits an image into a size of another aspect ratio.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Uses the whole image at the exact size, distorting it if the aspect ratios differ.
+    #[default]
+    Stretch,
+    /// Keeps the aspect ratio and the whole image, the bars around it are filled with this RGBA color.
+    Fit([u8; 4]),
+    /// Keeps the aspect ratio and fills the whole size, cropping the image around the anchor.
+    Fill(Anchor),
+}
+
+impl fmt::Display for ResizeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResizeMode::Stretch => write!(f, "stretch"),
+            ResizeMode::Fit([0, 0, 0, 0]) => write!(f, "fit"),
+            ResizeMode::Fit([r, g, b, a]) => write!(f, "fit:#{r:02x}{g:02x}{b:02x}{a:02x}"),
+            ResizeMode::Fill(Anchor::Center) => write!(f, "fill"),
+            ResizeMode::Fill(anchor) => write!(f, "fill:{anchor}"),
+        }
+    }
+}
+
+impl FromStr for ResizeMode {
+    type Err = String;
+
+    /// Parses `stretch`, `fit` with transparent bars, `fit:RRGGBB[AA]` with colored bars,
+    /// `fill` centered, or `fill:ANCHOR` such as `fill:top-left`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, argument) = match s.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (s, None),
+        };
+
+        match (name.trim().to_ascii_lowercase().as_str(), argument) {
+            ("stretch", None) => Ok(ResizeMode::Stretch),
+            ("fit", None) => Ok(ResizeMode::Fit([0, 0, 0, 0])),
+            ("fit", Some(color)) => Ok(ResizeMode::Fit(parse_hex_color(color)?)),
+            ("fill", None) => Ok(ResizeMode::Fill(Anchor::Center)),
+            ("fill", Some(anchor)) => Ok(ResizeMode::Fill(anchor.parse()?)),
+            _ => Err(format!(
+                "unknown resize mode '{s}', expected one of: stretch, fit, fit:RRGGBB, fill, \
+                 fill:ANCHOR"
+            )),
+        }
+    }
+}
+
+/// What happens to the alpha of every block after it is reduced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Keeps the reduced alpha, soft edges stay semi-transparent.
+    #[default]
+    Keep,
+    /// Blocks with at least this alpha become opaque, the rest fully transparent.
+    Threshold(u8),
+    /// Snaps alpha to this many evenly spaced levels, from transparent to opaque. At least 2.
+    Levels(u8),
+}
+
+impl AlphaMode {
+    /// The alpha threshold used when none is given.
+    pub const DEFAULT_THRESHOLD: u8 = 128;
+
+    /// Applies the mode to a reduced color, fully transparent colors become `[0, 0, 0, 0]`.
+    fn apply(self, [r, g, b, a]: [u8; 4]) -> [u8; 4] {
+        let alpha = match self {
+            AlphaMode::Keep => a,
+            AlphaMode::Threshold(threshold) => {
+                if a >= threshold {
+                    255
+                } else {
+                    0
+                }
+            }
+            AlphaMode::Levels(levels) => {
+                let steps = levels.max(2) as u32 - 1;
+                let level = (a as u32 * steps + 127) / 255;
+                (level * 255 / steps) as u8
+            }
+        };
+
+        if alpha == 0 { [0; 4] } else { [r, g, b, alpha] }
+    }
+}
+
+impl fmt::Display for AlphaMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlphaMode::Keep => write!(f, "keep"),
+            AlphaMode::Threshold(threshold) => write!(f, "threshold:{threshold}"),
+            AlphaMode::Levels(levels) => write!(f, "levels:{levels}"),
+        }
+    }
+}
+
+impl FromStr for AlphaMode {
+    type Err = String;
+
+    /// Parses `keep`, `threshold` with the default threshold, `threshold:N` with N from 1 to 255,
+    /// or `levels:N` with N from 2 to 255.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => (name, Some(value.trim())),
+            None => (s, None),
+        };
+        let number = |value: &str, min: u8| match value.parse::<u8>() {
+            Ok(number) if number >= min => Ok(number),
+            _ => Err(format!(
+                "invalid alpha mode '{s}', expected a number from {min} to 255"
+            )),
+        };
+
+        match (name.trim().to_ascii_lowercase().as_str(), value) {
+            ("keep", None) => Ok(AlphaMode::Keep),
+            ("threshold", None) => Ok(AlphaMode::Threshold(AlphaMode::DEFAULT_THRESHOLD)),
+            ("threshold", Some(value)) => Ok(AlphaMode::Threshold(number(value, 1)?)),
+            ("levels", Some(value)) => Ok(AlphaMode::Levels(number(value, 2)?)),
+            _ => Err(format!(
+                "unknown alpha mode '{s}', expected one of: keep, threshold, threshold:N, levels:N"
+            )),
+        }
+    }
+}
+
+/// Options that tune how the pixelify functions compute each block.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PixelifyOptions {
+    /// Average block colors in linear light instead of gamma-encoded sRGB.
+    ///
+    /// Averaging sRGB values directly darkens blocks that mix light and dark pixels,
+    /// linear light keeps their perceived brightness.
+    /// Applies to the reducers that average colors, `Mean` and `Dominant`.
+    pub linear_light: bool,
+    /// How each block is reduced to one color.
+    pub reducer: BlockReducer,
+    /// What happens to the pixels that do not fill a whole block along the right and bottom edges.
+    /// `pixelify_by_image_size` always covers the whole image, so it does not use this.
+    pub edges: EdgePolicy,
+    /// How `pixelify_by_image_size` handles a size of another aspect ratio than the image.
+    pub resize: ResizeMode,
+    /// Snaps the alpha of every block, for engines that cannot draw semi-transparent pixels.
+    pub alpha: AlphaMode,
+}
+
+/// Downscales an image so every `pixel_size` block becomes one pixel.
+///
+/// `pixel_size` is a `BlockSize`, or a single number for square blocks.
 pub fn pixelify_downscale_by_pixel_size(
     bytes: &[u8],
-    pixel_size: u32,
+    pixel_size: impl Into<BlockSize>,
+    options: &PixelifyOptions,
 ) -> Result<PixelifyImage, ImageProcessingError> {
-    if pixel_size == 0 {
+    let image = decode_rgba(bytes, "pixelify_downscale_by_pixel_size")?;
+    downscale_by_pixel_size(&image, pixel_size.into(), options).map(PixelifyImage::from)
+}
+
+pub(crate) fn downscale_by_pixel_size(
+    image: &RgbaImage,
+    pixel_size: BlockSize,
+    options: &PixelifyOptions,
+) -> Result<RgbaImage, ImageProcessingError> {
+    if pixel_size.width == 0 || pixel_size.height == 0 {
         return Err(ImageProcessingError::failed(
             "pixelify_downscale_by_pixel_size",
             "Pixel size must be a positive number",
         ));
     }
 
-    let image = image::load_from_memory(&bytes).map_err(|_| {
-        ImageProcessingError::failed("pixelify_downscale_by_pixel_size", "Failed to decode PNG")
-    })?;
-
-    let image = image.to_rgba8();
-
     let (width, height) = image.dimensions();
 
-    // New number of pixels by width with truncation
-    let new_width = width / pixel_size;
+    // The blocks along each axis, the remainder is handled by `options.edges`
+    let columns = axis_blocks(width, pixel_size.width, options.edges);
+    let rows = axis_blocks(height, pixel_size.height, options.edges);
 
-    // New number of pixels by height with truncation
-    let new_height = height / pixel_size;
-
-    if new_width == 0 || new_height == 0 {
+    if columns.is_empty() || rows.is_empty() {
         return Err(ImageProcessingError::failed(
             "pixelify_downscale_by_pixel_size",
             "Pixel size is larger than the image dimensions",
         ));
     }
 
-    let mut downscaled = vec![0u8; (new_width * new_height * 4) as usize];
+    let new_width = columns.len() as u32;
+    let new_height = rows.len() as u32;
 
-    // Take the average color and map that to the downscaled image
-    for by in 0..new_height {
-        for bx in 0..new_width {
-            let x = bx * pixel_size;
-            let y = by * pixel_size;
+    let mut downscaled = vec![0u8; (new_width * new_height * 4) as usize];
 
-            let (r, g, b, a) = get_average_rgba(&image, x, y, pixel_size)?;
+    // Reduce every block to one color and map that to the downscaled image
+    for (by, &(y, block_height)) in rows.iter().enumerate() {
+        for (bx, &(x, block_width)) in columns.iter().enumerate() {
+            let (r, g, b, a) = reduce_block(image, x, y, block_width, block_height, options)?;
 
-            let out_i = ((by * new_width + bx) * 4) as usize;
+            let out_i = (by * new_width as usize + bx) * 4;
             downscaled[out_i] = r;
             downscaled[out_i + 1] = g;
             downscaled[out_i + 2] = b;
@@ -63,45 +516,57 @@ pub fn pixelify_downscale_by_pixel_size(
         }
     }
 
-    Ok(PixelifyImage::new(downscaled, new_width, new_height))
+    RgbaImage::from_raw(new_width, new_height, downscaled).ok_or_else(|| {
+        ImageProcessingError::failed("pixelify_downscale_by_pixel_size", "Bad buffer length")
+    })
 }
 
+/// Fills every `pixel_size` block with one color, keeping the original dimensions.
+///
+/// `pixel_size` is a `BlockSize`, or a single number for square blocks.
 pub fn pixelify_false_downscale_by_pixel_size(
     bytes: &[u8],
-    pixel_size: u32,
+    pixel_size: impl Into<BlockSize>,
+    options: &PixelifyOptions,
 ) -> Result<PixelifyImage, ImageProcessingError> {
-    if pixel_size == 0 {
+    let image = decode_rgba(bytes, "pixelify_downscale_by_pixel_size")?;
+    false_downscale_by_pixel_size(&image, pixel_size.into(), options).map(PixelifyImage::from)
+}
+
+pub(crate) fn false_downscale_by_pixel_size(
+    image: &RgbaImage,
+    pixel_size: BlockSize,
+    options: &PixelifyOptions,
+) -> Result<RgbaImage, ImageProcessingError> {
+    if pixel_size.width == 0 || pixel_size.height == 0 {
         return Err(ImageProcessingError::failed(
             "pixelify_downscale_by_pixel_size",
             "Pixel size must be a positive number",
         ));
     }
 
-    let image = image::load_from_memory(&bytes).map_err(|_| {
-        ImageProcessingError::failed("pixelify_downscale_by_pixel_size", "Failed to decode PNG")
-    })?;
-
-    let image = image.to_rgba8();
-
     let (width, height) = image.dimensions();
 
-    let mut false_downscaled = image.as_raw().to_vec();
-
-    let blocks_x = width / pixel_size;
-    let blocks_y = height / pixel_size;
+    let columns = axis_blocks(width, pixel_size.width, options.edges);
+    let rows = axis_blocks(height, pixel_size.height, options.edges);
 
-    for by in 0..blocks_y {
-        for bx in 0..blocks_x {
-            let x0 = bx * pixel_size;
-            let y0 = by * pixel_size;
+    if columns.is_empty() || rows.is_empty() {
+        return Err(ImageProcessingError::failed(
+            "pixelify_false_downscale_by_pixel_size",
+            "Pixel size is larger than the image dimensions",
+        ));
+    }
 
-            let (r, g, b, a) = get_average_rgba(&image, x0, y0, pixel_size)?;
+    // Cropped and centered grids leave the remainder pixels as they were
+    let mut false_downscaled = image.as_raw().to_vec();
 
-            for dy in 0..pixel_size {
-                for dx in 0..pixel_size {
-                    let x = x0 + dx;
-                    let y = y0 + dy;
+    for &(y0, block_height) in &rows {
+        for &(x0, block_width) in &columns {
+            let (r, g, b, a) = reduce_block(image, x0, y0, block_width, block_height, options)?;
 
+            // Padded blocks reach past the image, only their visible part is written
+            for y in y0..(y0 + block_height).min(height) {
+                for x in x0..(x0 + block_width).min(width) {
                     let out_i = ((y * width + x) * 4) as usize;
                     false_downscaled[out_i] = r;
                     false_downscaled[out_i + 1] = g;
@@ -112,53 +577,286 @@ pub fn pixelify_false_downscale_by_pixel_size(
         }
     }
 
-    Ok(PixelifyImage::new(false_downscaled, width, height))
+    RgbaImage::from_raw(width, height, false_downscaled).ok_or_else(|| {
+        ImageProcessingError::failed(
+            "pixelify_false_downscale_by_pixel_size",
+            "Bad buffer length",
+        )
+    })
+}
+
+/// The blocks along an axis of `length` pixels as `(start, size)` pairs, following `edges`.
+///
+/// Padded blocks can reach past `length`.
+fn axis_blocks(length: u32, pixel_size: u32, edges: EdgePolicy) -> Vec<(u32, u32)> {
+    let whole = length / pixel_size;
+    let remainder = length % pixel_size;
+
+    match edges {
+        EdgePolicy::Crop => (0..whole).map(|i| (i * pixel_size, pixel_size)).collect(),
+        EdgePolicy::Center => (0..whole)
+            .map(|i| (remainder / 2 + i * pixel_size, pixel_size))
+            .collect(),
+        EdgePolicy::Partial => (0..length.div_ceil(pixel_size))
+            .map(|i| {
+                let start = i * pixel_size;
+                (start, pixel_size.min(length - start))
+            })
+            .collect(),
+        EdgePolicy::Pad(_) => (0..length.div_ceil(pixel_size))
+            .map(|i| (i * pixel_size, pixel_size))
+            .collect(),
+    }
 }
 
-fn get_average_rgba(
+/// Reduces the `block_width` x `block_height` block at `x`, `y` to one color with `options.reducer`.
+///
+/// Only padded blocks may reach past the image, their missing pixels come from the padding.
+fn reduce_block(
     image: &RgbaImage,
     x: u32,
     y: u32,
-    pixel_size: u32,
+    block_width: u32,
+    block_height: u32,
+    options: &PixelifyOptions,
 ) -> Result<(u8, u8, u8, u8), ImageProcessingError> {
-    if x + pixel_size > image.width() || y + pixel_size > image.height() {
+    let padding = match options.edges {
+        EdgePolicy::Pad(padding) => Some(padding),
+        _ => None,
+    };
+
+    if padding.is_none() && (x + block_width > image.width() || y + block_height > image.height()) {
         return Err(ImageProcessingError::failed(
             "Rgba Average",
             "Indexing would cause out of bounds error logic",
         ));
     }
 
-    let mut red_sum: u32 = 0;
-    let mut green_sum: u32 = 0;
-    let mut blue_sum: u32 = 0;
-    let mut alpha_sum: u32 = 0;
-    let mut pixel_count: u32 = 0;
+    let sample = |px: u32, py: u32| -> [u8; 4] {
+        if px < image.width() && py < image.height() {
+            return image.get_pixel(px, py).0;
+        }
+        match padding {
+            Some(Padding::Color(color)) => color,
+            Some(Padding::Transparent) => [0; 4],
+            _ => {
+                image
+                    .get_pixel(px.min(image.width() - 1), py.min(image.height() - 1))
+                    .0
+            }
+        }
+    };
 
-    for local_x in 0..pixel_size {
-        for local_y in 0..pixel_size {
-            let pixel = image.get_pixel(local_x + x, local_y + y);
-            let [r, g, b, a] = pixel.0;
-            red_sum += r as u32;
-            green_sum += g as u32;
-            blue_sum += b as u32;
-            alpha_sum += a as u32;
-            pixel_count += 1;
+    let mut pixels: Vec<[u8; 4]> = Vec::with_capacity((block_width * block_height) as usize);
+    for local_x in 0..block_width {
+        for local_y in 0..block_height {
+            pixels.push(sample(local_x + x, local_y + y));
         }
     }
 
-    Ok((
-        (red_sum / pixel_count) as u8,
-        (green_sum / pixel_count) as u8,
-        (blue_sum / pixel_count) as u8,
+    let [r, g, b, a] = match options.reducer {
+        BlockReducer::Mean => get_average_rgba(&pixels, options),
+        BlockReducer::Median => get_median_rgba(&mut pixels),
+        BlockReducer::Mode => get_mode_rgba(&pixels),
+        BlockReducer::Dominant => get_dominant_rgba(&pixels, options),
+        BlockReducer::Center => sample(x + block_width / 2, y + block_height / 2),
+        BlockReducer::Darkest => get_extreme_rgba(&pixels, |a, b| a < b),
+        BlockReducer::Brightest => get_extreme_rgba(&pixels, |a, b| a > b),
+    };
+
+    let [r, g, b, a] = options.alpha.apply([r, g, b, a]);
+    Ok((r, g, b, a))
+}
+
+/// The mean color of the pixels, weighted by alpha so transparent pixels do not bleed their color.
+fn get_average_rgba(pixels: &[[u8; 4]], options: &PixelifyOptions) -> [u8; 4] {
+    if options.linear_light {
+        return get_average_rgba_linear(pixels);
+    }
+
+    let mut red_sum: u64 = 0;
+    let mut green_sum: u64 = 0;
+    let mut blue_sum: u64 = 0;
+    let mut alpha_sum: u64 = 0;
+    let pixel_count = pixels.len() as u64;
+
+    for [r, g, b, a] in pixels {
+        red_sum += *r as u64 * *a as u64;
+        green_sum += *g as u64 * *a as u64;
+        blue_sum += *b as u64 * *a as u64;
+        alpha_sum += *a as u64;
+    }
+
+    if alpha_sum == 0 {
+        return [0; 4];
+    }
+
+    [
+        (red_sum / alpha_sum) as u8,
+        (green_sum / alpha_sum) as u8,
+        (blue_sum / alpha_sum) as u8,
         (alpha_sum / pixel_count) as u8,
-    ))
+    ]
+}
+
+/// Same as `get_average_rgba`, but the color channels are averaged in linear light.
+/// Alpha is already linear, so it is averaged as is.
+fn get_average_rgba_linear(pixels: &[[u8; 4]]) -> [u8; 4] {
+    let mut sums = [0f32; 3];
+    let mut alpha_sum: u64 = 0;
+    let pixel_count = pixels.len() as u64;
+
+    for [r, g, b, a] in pixels {
+        let weight = *a as f32;
+        sums[0] += srgb_to_linear(*r) * weight;
+        sums[1] += srgb_to_linear(*g) * weight;
+        sums[2] += srgb_to_linear(*b) * weight;
+        alpha_sum += *a as u64;
+    }
+
+    if alpha_sum == 0 {
+        return [0; 4];
+    }
+
+    let [r, g, b] = sums.map(|sum| linear_to_srgb(sum / alpha_sum as f32));
+    [r, g, b, (alpha_sum / pixel_count) as u8]
+}
+
+/// The median of every channel on its own, the lower one for an even number of pixels.
+fn get_median_rgba(pixels: &mut [[u8; 4]]) -> [u8; 4] {
+    let middle = (pixels.len() - 1) / 2;
+    std::array::from_fn(|channel| {
+        pixels.select_nth_unstable_by_key(middle, |pixel| pixel[channel]);
+        pixels[middle][channel]
+    })
+}
+
+/// The most frequent color, ties go to the color that appears first.
+/// Fully transparent pixels all count as the same color.
+fn get_mode_rgba(pixels: &[[u8; 4]]) -> [u8; 4] {
+    let mut counts: HashMap<[u8; 4], (usize, usize)> = HashMap::new();
+    for (i, &pixel) in pixels.iter().enumerate() {
+        let key = if pixel[3] == 0 { [0; 4] } else { pixel };
+        counts.entry(key).or_insert((0, i)).0 += 1;
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|&(_, (count, first))| (count, std::cmp::Reverse(first)))
+        .map(|(color, _)| color)
+        .unwrap_or_default()
 }
 
+/// The mean color of the largest group of similar pixels.
+///
+/// Pixels are grouped by the top 3 bits of every channel, ties go to the group that appears first.
+fn get_dominant_rgba(pixels: &[[u8; 4]], options: &PixelifyOptions) -> [u8; 4] {
+    let group = |pixel: &[u8; 4]| {
+        if pixel[3] == 0 {
+            [0; 4]
+        } else {
+            pixel.map(|channel| channel >> 5)
+        }
+    };
+
+    let mut counts: HashMap<[u8; 4], (usize, usize)> = HashMap::new();
+    for (i, pixel) in pixels.iter().enumerate() {
+        counts.entry(group(pixel)).or_insert((0, i)).0 += 1;
+    }
+
+    let Some(dominant) = counts
+        .into_iter()
+        .max_by_key(|&(_, (count, first))| (count, std::cmp::Reverse(first)))
+        .map(|(key, _)| key)
+    else {
+        return [0; 4];
+    };
+
+    let members: Vec<[u8; 4]> = pixels
+        .iter()
+        .filter(|pixel| group(pixel) == dominant)
+        .copied()
+        .collect();
+    get_average_rgba(&members, options)
+}
+
+/// The visible pixel whose luminance wins `is_better`, ties go to the pixel that appears first.
+/// A fully transparent block stays transparent.
+fn get_extreme_rgba(pixels: &[[u8; 4]], is_better: impl Fn(f32, f32) -> bool) -> [u8; 4] {
+    let mut best: Option<([u8; 4], f32)> = None;
+    for &pixel in pixels.iter().filter(|pixel| pixel[3] > 0) {
+        let luminance = luminance(pixel);
+        if best.is_none_or(|(_, best_luminance)| is_better(luminance, best_luminance)) {
+            best = Some((pixel, luminance));
+        }
+    }
+
+    best.map(|(pixel, _)| pixel).unwrap_or(pixels[0])
+}
+
+/// Relative luminance with the Rec. 709 weights, computed in linear light.
+fn luminance([r, g, b, _]: [u8; 4]) -> f32 {
+    0.2126 * srgb_to_linear(r) + 0.7152 * srgb_to_linear(g) + 0.0722 * srgb_to_linear(b)
+}
+
+/// Downscales an image to exactly `new_width` x `new_height`, following `options.resize`.
 pub fn pixelify_by_image_size(
     bytes: &[u8],
     new_width: u32,
     new_height: u32,
+    options: &PixelifyOptions,
+) -> Result<PixelifyImage, ImageProcessingError> {
+    let image = decode_rgba(bytes, "pixelify_by_image_size")?;
+    downscale_by_image_size(&image, Some(new_width), Some(new_height), options)
+        .map(PixelifyImage::from)
+}
+
+/// Downscales an image to `new_width`, with the height that keeps its aspect ratio.
+pub fn pixelify_by_image_width(
+    bytes: &[u8],
+    new_width: u32,
+    options: &PixelifyOptions,
+) -> Result<PixelifyImage, ImageProcessingError> {
+    let image = decode_rgba(bytes, "pixelify_by_image_size")?;
+    downscale_by_image_size(&image, Some(new_width), None, options).map(PixelifyImage::from)
+}
+
+/// Downscales an image to `new_height`, with the width that keeps its aspect ratio.
+pub fn pixelify_by_image_height(
+    bytes: &[u8],
+    new_height: u32,
+    options: &PixelifyOptions,
 ) -> Result<PixelifyImage, ImageProcessingError> {
+    let image = decode_rgba(bytes, "pixelify_by_image_size")?;
+    downscale_by_image_size(&image, None, Some(new_height), options).map(PixelifyImage::from)
+}
+
+/// Downscales to the given size, a missing width or height is derived from the aspect ratio of the image.
+pub(crate) fn downscale_by_image_size(
+    image: &RgbaImage,
+    new_width: Option<u32>,
+    new_height: Option<u32>,
+    options: &PixelifyOptions,
+) -> Result<RgbaImage, ImageProcessingError> {
+    let (original_width, original_height) = image.dimensions();
+
+    // Scales `length` by `to / from`, rounded and at least 1
+    let derive = |length: u32, to: u32, from: u32| {
+        ((length as u64 * to as u64 + from as u64 / 2) / from.max(1) as u64).max(1) as u32
+    };
+
+    let (new_width, new_height) = match (new_width, new_height) {
+        (Some(width), Some(height)) => (width, height),
+        (Some(width), None) => (width, derive(original_height, width, original_width)),
+        (None, Some(height)) => (derive(original_width, height, original_height), height),
+        (None, None) => {
+            return Err(ImageProcessingError::failed(
+                "pixelify_by_image_size",
+                "Set a width, a height, or both",
+            ));
+        }
+    };
+
     if new_width == 0 || new_height == 0 {
         return Err(ImageProcessingError::failed(
             "pixelify_by_image_size",
@@ -166,36 +864,112 @@ pub fn pixelify_by_image_size(
         ));
     }
 
-    let image = image::load_from_memory(&bytes).map_err(|_| {
-        ImageProcessingError::failed("pixelify_by_image_size", "Failed to decode PNG")
-    })?;
+    let too_large = || {
+        ImageProcessingError::failed(
+            "pixelify_by_image_size",
+            "desired width and/or height is greater than original_width and original_height",
+        )
+    };
 
-    let image = image.to_rgba8();
+    match options.resize {
+        ResizeMode::Stretch => {
+            if new_width > original_width || new_height > original_height {
+                return Err(too_large());
+            }
 
-    let (original_width, original_height) = image.dimensions();
+            reduce_region(
+                image,
+                (0, 0, original_width, original_height),
+                new_width,
+                new_height,
+                options,
+            )
+        }
+        ResizeMode::Fit(color) => {
+            // The largest size with the aspect ratio of the image that fits, never upscaled
+            let scale = (new_width as f64 / original_width as f64)
+                .min(new_height as f64 / original_height as f64)
+                .min(1.0);
+            let content_width =
+                ((original_width as f64 * scale).round() as u32).clamp(1, new_width);
+            let content_height =
+                ((original_height as f64 * scale).round() as u32).clamp(1, new_height);
 
-    if new_width > original_width || new_height > original_height {
-        return Err(ImageProcessingError::failed(
-            "pixelify_by_image_size",
-            "desired width and/or height is greater than original_width and original_height",
-        ));
+            let content = reduce_region(
+                image,
+                (0, 0, original_width, original_height),
+                content_width,
+                content_height,
+                options,
+            )?;
+
+            let mut letterboxed = RgbaImage::from_pixel(new_width, new_height, image::Rgba(color));
+            image::imageops::replace(
+                &mut letterboxed,
+                &content,
+                ((new_width - content_width) / 2) as i64,
+                ((new_height - content_height) / 2) as i64,
+            );
+            Ok(letterboxed)
+        }
+        ResizeMode::Fill(anchor) => {
+            // The largest part of the image with the aspect ratio of the new size
+            let scale = (new_width as f64 / original_width as f64)
+                .max(new_height as f64 / original_height as f64);
+            let region_width = ((new_width as f64 / scale).round() as u32).min(original_width);
+            let region_height = ((new_height as f64 / scale).round() as u32).min(original_height);
+
+            if new_width > region_width || new_height > region_height {
+                return Err(too_large());
+            }
+
+            let left = anchor.offset_x(original_width, region_width);
+            let top = anchor.offset_y(original_height, region_height);
+            reduce_region(
+                image,
+                (left, top, region_width, region_height),
+                new_width,
+                new_height,
+                options,
+            )
+        }
+    }
+}
+
+/// Reduces the `(left, top, width, height)` region of `image` to `new_width` x `new_height` blocks.
+///
+/// The mean reducer is an area-weighted box filter, every output pixel averages the fractional source
+/// rectangle it covers. The other reducers pick from whole pixels, so their blocks are spread over the
+/// whole region and some are one pixel larger when it does not divide evenly.
+fn reduce_region(
+    image: &RgbaImage,
+    region: (u32, u32, u32, u32),
+    new_width: u32,
+    new_height: u32,
+    options: &PixelifyOptions,
+) -> Result<RgbaImage, ImageProcessingError> {
+    if options.reducer == BlockReducer::Mean {
+        return area_average_region(image, region, new_width, new_height, options);
     }
 
-    // This would give a rectangular box
-    let pixel_size_x = original_width / new_width;
-    let pixel_size_y = original_height / new_height;
+    let (left, top, width, height) = region;
 
-    let pixel_size = pixel_size_x.min(pixel_size_y);
+    // Where block `i` of `count` starts along a span of `length` pixels
+    let boundary =
+        |i: u32, length: u32, count: u32| (i as u64 * length as u64 / count as u64) as u32;
 
     let mut downscaled = vec![0u8; (new_width * new_height * 4) as usize];
 
-    // Take the average color and map that to the downscaled image
+    // Reduce every block to one color and map that to the downscaled image
     for by in 0..new_height {
+        let y = top + boundary(by, height, new_height);
+        let block_height = top + boundary(by + 1, height, new_height) - y;
+
         for bx in 0..new_width {
-            let x = bx * pixel_size;
-            let y = by * pixel_size;
+            let x = left + boundary(bx, width, new_width);
+            let block_width = left + boundary(bx + 1, width, new_width) - x;
 
-            let (r, g, b, a) = get_average_rgba(&image, x, y, pixel_size)?;
+            let (r, g, b, a) = reduce_block(image, x, y, block_width, block_height, options)?;
 
             let out_i = ((by * new_width + bx) * 4) as usize;
             downscaled[out_i] = r;
@@ -205,5 +979,195 @@ pub fn pixelify_by_image_size(
         }
     }
 
-    Ok(PixelifyImage::new(downscaled, new_width, new_height))
+    RgbaImage::from_raw(new_width, new_height, downscaled)
+        .ok_or_else(|| ImageProcessingError::failed("pixelify_by_image_size", "Bad buffer length"))
+}
+
+/// Area-weighted box filter from the `(left, top, width, height)` region to `new_width` x `new_height`.
+///
+/// Colors are weighted by alpha like `get_average_rgba`. Weights are exact integers, so whole-number ratios
+/// give the same colors as averaging whole blocks.
+fn area_average_region(
+    image: &RgbaImage,
+    (left, top, width, height): (u32, u32, u32, u32),
+    new_width: u32,
+    new_height: u32,
+    options: &PixelifyOptions,
+) -> Result<RgbaImage, ImageProcessingError> {
+    let columns = box_weights(left, width, new_width);
+    let rows = box_weights(top, height, new_height);
+    let area = width as u64 * height as u64;
+
+    let mut downscaled = vec![0u8; (new_width * new_height * 4) as usize];
+
+    for (by, row) in rows.iter().enumerate() {
+        for (bx, column) in columns.iter().enumerate() {
+            let mut sums = [0u64; 3];
+            let mut linear_sums = [0f64; 3];
+            let mut alpha_sum: u64 = 0;
+
+            for &(y, weight_y) in row {
+                for &(x, weight_x) in column {
+                    let pixel = image.get_pixel(x, y).0;
+                    let weight = weight_x * weight_y * pixel[3] as u64;
+                    for channel in 0..3 {
+                        sums[channel] += pixel[channel] as u64 * weight;
+                    }
+                    if options.linear_light {
+                        for channel in 0..3 {
+                            linear_sums[channel] +=
+                                srgb_to_linear(pixel[channel]) as f64 * weight as f64;
+                        }
+                    }
+                    alpha_sum += weight;
+                }
+            }
+
+            let color = if alpha_sum == 0 {
+                [0; 4]
+            } else if options.linear_light {
+                let [r, g, b] =
+                    linear_sums.map(|sum| linear_to_srgb((sum / alpha_sum as f64) as f32));
+                [r, g, b, (alpha_sum / area) as u8]
+            } else {
+                let [r, g, b] = sums.map(|sum| (sum / alpha_sum) as u8);
+                [r, g, b, (alpha_sum / area) as u8]
+            };
+            let color = options.alpha.apply(color);
+
+            let out_i = (by * new_width as usize + bx) * 4;
+            downscaled[out_i..out_i + 4].copy_from_slice(&color);
+        }
+    }
+
+    RgbaImage::from_raw(new_width, new_height, downscaled)
+        .ok_or_else(|| ImageProcessingError::failed("pixelify_by_image_size", "Bad buffer length"))
+}
+
+/// The source pixels every one of `count` output pixels covers along a span of `length` pixels from `start`,
+/// with how much of each is covered.
+///
+/// Coordinates are scaled by `count` so the weights stay integers: output pixel `i` covers
+/// `[i * length, (i + 1) * length)` and source pixel `s` covers `[s * count, (s + 1) * count)`.
+fn box_weights(start: u32, length: u32, count: u32) -> Vec<Vec<(u32, u64)>> {
+    let (length, count) = (length as u64, count as u64);
+
+    (0..count)
+        .map(|i| {
+            let from = i * length;
+            let to = (i + 1) * length;
+            (from / count..to.div_ceil(count))
+                .map(|s| {
+                    let covered = to.min((s + 1) * count) - from.max(s * count);
+                    (start + s as u32, covered)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Decodes image bytes of any supported format into RGBA pixels.
+fn decode_rgba(bytes: &[u8], op: &'static str) -> Result<RgbaImage, ImageProcessingError> {
+    image::load_from_memory(bytes)
+        .map(|image| image.to_rgba8())
+        .map_err(|_| ImageProcessingError::failed(op, "Failed to decode PNG"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_weights_cover_every_source_pixel_once() {
+        for (length, count) in [(1, 1), (3, 2), (7, 3), (10, 10), (100, 7), (64, 16), (5, 8)] {
+            let weights = box_weights(0, length, count);
+            assert_eq!(weights.len(), count as usize);
+
+            // Every output pixel covers `length` scaled units
+            for output in &weights {
+                assert_eq!(output.iter().map(|&(_, w)| w).sum::<u64>(), length as u64);
+                assert!(output.iter().all(|&(_, w)| w > 0));
+            }
+
+            // Every source pixel is split over the outputs without loss
+            let mut covered = vec![0u64; length as usize];
+            for &(s, w) in weights.iter().flatten() {
+                covered[s as usize] += w;
+            }
+            assert!(
+                covered.iter().all(|&w| w == count as u64),
+                "{length} to {count}"
+            );
+        }
+    }
+
+    #[test]
+    fn box_weights_split_shared_pixels() {
+        // Three pixels into two, the middle one is shared equally
+        assert_eq!(
+            box_weights(0, 3, 2),
+            vec![vec![(0, 2), (1, 1)], vec![(1, 1), (2, 2)]]
+        );
+        // Two pixels into three, the middle output straddles both
+        assert_eq!(
+            box_weights(0, 2, 3),
+            vec![vec![(0, 2)], vec![(0, 1), (1, 1)], vec![(1, 2)]]
+        );
+    }
+
+    #[test]
+    fn box_weights_with_integer_ratios_are_equal_blocks() {
+        let weights = box_weights(5, 12, 3);
+        assert_eq!(
+            weights,
+            vec![
+                vec![(5, 3), (6, 3), (7, 3), (8, 3)],
+                vec![(9, 3), (10, 3), (11, 3), (12, 3)],
+                vec![(13, 3), (14, 3), (15, 3), (16, 3)],
+            ]
+        );
+    }
+
+    #[test]
+    fn false_downscale_keeps_the_image_size() {
+        let image = RgbaImage::from_fn(100, 100, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+
+        for edges in [
+            EdgePolicy::Crop,
+            EdgePolicy::Center,
+            EdgePolicy::Partial,
+            EdgePolicy::Pad(Padding::Transparent),
+        ] {
+            let options = PixelifyOptions {
+                edges,
+                ..PixelifyOptions::default()
+            };
+            let result =
+                false_downscale_by_pixel_size(&image, BlockSize::from(3), &options).unwrap();
+            assert_eq!(result.dimensions(), (100, 100), "{edges}");
+        }
+
+        // Cropped blocks leave the last column and row as they were
+        let result =
+            false_downscale_by_pixel_size(&image, BlockSize::from(3), &PixelifyOptions::default())
+                .unwrap();
+        assert_eq!(result.get_pixel(0, 0), &image::Rgba([1, 1, 0, 255]));
+        assert_eq!(result.get_pixel(99, 42), image.get_pixel(99, 42));
+        assert_eq!(result.get_pixel(42, 99), image.get_pixel(42, 99));
+    }
+
+    #[test]
+    fn mean_of_large_blocks_does_not_overflow() {
+        // 300x300 blocks sum more than u32::MAX for a white channel
+        let image = RgbaImage::from_fn(300, 300, |x, _| {
+            let green = if x % 2 == 0 { 255 } else { 0 };
+            image::Rgba([255, green, 10, 255])
+        });
+
+        let result =
+            downscale_by_pixel_size(&image, BlockSize::from(300), &PixelifyOptions::default())
+                .unwrap();
+        assert_eq!(result.dimensions(), (1, 1));
+        assert_eq!(result.get_pixel(0, 0), &image::Rgba([255, 127, 10, 255]));
+    }
 }
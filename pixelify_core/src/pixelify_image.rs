@@ -1,18 +1,41 @@
+use crate::pixelify_errors::ImageProcessingError;
+use image::{DynamicImage, GrayImage, ImageFormat, RgbaImage};
+
+/// How the bytes of a `PixelifyImage` are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Raw pixels, 4 bytes per pixel (red, green, blue, alpha) in row-major order.
+    Rgba8,
+    /// Raw pixels, 1 byte per pixel (luminance) in row-major order.
+    Luma8,
+    /// A complete PNG file, headers and compressed data included.
+    Png,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ImageDimensions {
     width: u32,
     height: u32,
 }
 
+/// An image produced by Pixelify, along with the format its bytes are in.
+///
+/// Core operations return raw pixels (`Rgba8` or `Luma8`), and encoding only happens
+/// at the output boundary through `to_png`. Operations that take a `PixelifyImage`
+/// accept any format and convert it with `to_rgba8` first, so results can be chained.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PixelifyImage {
     pixels: Vec<u8>,
     dimensions: ImageDimensions,
+    format: PixelFormat,
 }
 
 impl PixelifyImage {
-    pub fn new(bytes: Vec<u8>, width: u32, height: u32) -> PixelifyImage {
+    pub fn new(bytes: Vec<u8>, width: u32, height: u32, format: PixelFormat) -> PixelifyImage {
         Self {
             pixels: bytes,
             dimensions: ImageDimensions { width, height },
+            format,
         }
     }
 
@@ -28,7 +51,96 @@ impl PixelifyImage {
         self.dimensions.height
     }
 
+    pub fn get_format(&self) -> PixelFormat {
+        self.format
+    }
+
     pub fn into_bytes(self) -> Vec<u8> {
         self.pixels
     }
+
+    /// Converts the image into raw RGBA pixels.
+    ///
+    /// Luma pixels are expanded to opaque gray, and PNG bytes are decoded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if the raw buffer does not match the dimensions,
+    /// or the PNG bytes cannot be decoded.
+    pub fn to_rgba8(&self) -> Result<PixelifyImage, ImageProcessingError> {
+        if self.format == PixelFormat::Rgba8 {
+            let expected_len = self.get_width() as usize * self.get_height() as usize * 4;
+            if self.pixels.len() != expected_len {
+                return Err(ImageProcessingError::failed(
+                    "to_rgba8",
+                    "Bad buffer length",
+                ));
+            }
+            return Ok(self.clone());
+        }
+
+        Ok(PixelifyImage::from(self.to_rgba_image()?))
+    }
+
+    /// Converts the image into an `RgbaImage` from the `image` crate.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if the raw buffer does not match the dimensions,
+    /// or the PNG bytes cannot be decoded.
+    pub fn to_rgba_image(&self) -> Result<RgbaImage, ImageProcessingError> {
+        let (width, height) = (self.get_width(), self.get_height());
+
+        match self.format {
+            PixelFormat::Rgba8 => RgbaImage::from_raw(width, height, self.pixels.clone())
+                .ok_or_else(|| ImageProcessingError::failed("to_rgba8", "Bad buffer length")),
+            PixelFormat::Luma8 => GrayImage::from_raw(width, height, self.pixels.clone())
+                .map(|luma| DynamicImage::ImageLuma8(luma).to_rgba8())
+                .ok_or_else(|| ImageProcessingError::failed("to_rgba8", "Bad buffer length")),
+            PixelFormat::Png => image::load_from_memory_with_format(&self.pixels, ImageFormat::Png)
+                .map(|decoded| decoded.to_rgba8())
+                .map_err(|_| ImageProcessingError::failed("to_rgba8", "Failed to decode PNG")),
+        }
+    }
+
+    /// Encodes the image into PNG file bytes.
+    ///
+    /// Raw RGBA pixels become an RGBA PNG and raw luma pixels a grayscale PNG.
+    /// An image that already holds PNG bytes is returned as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if the raw buffer does not match the dimensions,
+    /// or PNG encoding fails.
+    pub fn to_png(&self) -> Result<PixelifyImage, ImageProcessingError> {
+        let (width, height) = (self.get_width(), self.get_height());
+
+        let image = match self.format {
+            PixelFormat::Png => return Ok(self.clone()),
+            PixelFormat::Rgba8 => RgbaImage::from_raw(width, height, self.pixels.clone())
+                .map(DynamicImage::ImageRgba8),
+            PixelFormat::Luma8 => GrayImage::from_raw(width, height, self.pixels.clone())
+                .map(DynamicImage::ImageLuma8),
+        }
+        .ok_or_else(|| ImageProcessingError::failed("to_png", "Bad buffer length"))?;
+
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        image
+            .write_to(&mut cursor, ImageFormat::Png)
+            .map_err(|_| ImageProcessingError::failed("to_png", "Failed to encode PNG"))?;
+
+        Ok(PixelifyImage::new(
+            cursor.into_inner(),
+            width,
+            height,
+            PixelFormat::Png,
+        ))
+    }
+}
+
+impl From<RgbaImage> for PixelifyImage {
+    fn from(image: RgbaImage) -> Self {
+        let (width, height) = image.dimensions();
+        PixelifyImage::new(image.into_raw(), width, height, PixelFormat::Rgba8)
+    }
 }
@@ -0,0 +1,535 @@
+//! Adaptive palette generation.
+//!
+//! Instead of limiting an image to one of the fixed palettes, these functions derive the `n` colors
+//! that best represent the image itself. The result is a regular `Palette`, so it can be applied with
+//! `apply_palette` or `dither`, exported, and reused across a whole set of sprites.
+//!
+//! Three algorithms are available:
+//! - median cut, which recursively splits the color space at the median of its widest channel,
+//! - octree, which buckets colors by their bits and merges the least used buckets,
+//! - k-means, which iteratively refines cluster centers, seeded for deterministic output.
+//!
+//! Fully transparent pixels are ignored, since their color is never seen.
+
+use crate::PixelifyImage;
+use crate::palette::Palette;
+use crate::pixelify_errors::ImageProcessingError;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Maximum number of refinement passes for k-means.
+const KMEANS_MAX_ITERATIONS: u32 = 32;
+
+/// The algorithms that can derive a palette from an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuantizeMethod {
+    MedianCut,
+    Octree,
+    /// K-means clustering, initialized with k-means++ from the given seed.
+    /// The same seed and input always produce the same palette.
+    KMeans {
+        seed: u64,
+    },
+}
+
+impl fmt::Display for QuantizeMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuantizeMethod::MedianCut => write!(f, "median-cut"),
+            QuantizeMethod::Octree => write!(f, "octree"),
+            QuantizeMethod::KMeans { .. } => write!(f, "kmeans"),
+        }
+    }
+}
+
+impl FromStr for QuantizeMethod {
+    type Err = String;
+
+    /// Parses a quantization method name, ignoring case, `-` and `_`.
+    /// K-means parses with a seed of `0`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match normalized.as_str() {
+            "mediancut" | "median" => Ok(QuantizeMethod::MedianCut),
+            "octree" => Ok(QuantizeMethod::Octree),
+            "kmeans" => Ok(QuantizeMethod::KMeans { seed: 0 }),
+            _ => Err(format!(
+                "unknown quantize method '{s}', expected one of: median-cut, octree, kmeans"
+            )),
+        }
+    }
+}
+
+/// Derives a palette of at most `colors` colors from an image.
+///
+/// If the image has no more than `colors` distinct colors, those exact colors are returned.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if:
+/// - `colors` is 0,
+/// - `image` cannot be converted to RGBA,
+/// - the image has no visible pixels.
+pub fn generate_palette(
+    image: &PixelifyImage,
+    colors: usize,
+    method: QuantizeMethod,
+) -> Result<Palette, ImageProcessingError> {
+    generate_shared_palette(&[image], colors, method)
+}
+
+/// Derives one palette of at most `colors` colors that is shared by all `images`.
+///
+/// Every visible pixel across all images weighs the same, so larger images have a larger say.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if:
+/// - `colors` is 0,
+/// - any image cannot be converted to RGBA,
+/// - none of the images have visible pixels.
+pub fn generate_shared_palette(
+    images: &[&PixelifyImage],
+    colors: usize,
+    method: QuantizeMethod,
+) -> Result<Palette, ImageProcessingError> {
+    let images = images
+        .iter()
+        .map(|image| image.to_rgba8())
+        .collect::<Result<Vec<_>, _>>()?;
+    let buffers: Vec<&[u8]> = images
+        .iter()
+        .map(|image| image.as_bytes().as_slice())
+        .collect();
+
+    generate_palette_from_pixels(&buffers, colors, method)
+}
+
+/// Derives one palette from raw RGBA buffers.
+pub(crate) fn generate_palette_from_pixels(
+    buffers: &[&[u8]],
+    colors: usize,
+    method: QuantizeMethod,
+) -> Result<Palette, ImageProcessingError> {
+    if colors == 0 {
+        return Err(ImageProcessingError::failed(
+            "generate_palette",
+            "Palette size must be a positive number",
+        ));
+    }
+
+    let histogram = build_histogram(buffers);
+
+    if histogram.is_empty() {
+        return Err(ImageProcessingError::failed(
+            "generate_palette",
+            "Image has no visible pixels to build a palette from",
+        ));
+    }
+
+    let generated = if histogram.len() <= colors {
+        histogram.iter().map(|entry| entry.color).collect()
+    } else {
+        match method {
+            QuantizeMethod::MedianCut => median_cut(histogram, colors),
+            QuantizeMethod::Octree => octree(&histogram, colors),
+            QuantizeMethod::KMeans { seed } => kmeans(&histogram, colors, seed),
+        }
+    };
+
+    Palette::new(format!("{method}-{colors}"), generated)
+}
+
+/// A distinct color and how many pixels use it.
+#[derive(Clone, Copy)]
+struct HistogramEntry {
+    color: [u8; 3],
+    count: u64,
+}
+
+/// Counts the distinct visible colors of all raw RGBA buffers.
+///
+/// The entries are sorted by descending count, then by color, so the output is deterministic.
+fn build_histogram(buffers: &[&[u8]]) -> Vec<HistogramEntry> {
+    let mut counts: HashMap<[u8; 3], u64> = HashMap::new();
+
+    for buffer in buffers {
+        for pixel in buffer.chunks_exact(4) {
+            if pixel[3] == 0 {
+                continue;
+            }
+            *counts.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0) += 1;
+        }
+    }
+
+    let mut histogram: Vec<HistogramEntry> = counts
+        .into_iter()
+        .map(|(color, count)| HistogramEntry { color, count })
+        .collect();
+    histogram.sort_by(|a, b| b.count.cmp(&a.count).then(a.color.cmp(&b.color)));
+
+    histogram
+}
+
+/// Returns the count weighted average color of `entries`.
+fn weighted_average(entries: &[HistogramEntry]) -> [u8; 3] {
+    let mut sums = [0u64; 3];
+    let mut total = 0u64;
+
+    for entry in entries {
+        for (sum, channel) in sums.iter_mut().zip(entry.color) {
+            *sum += channel as u64 * entry.count;
+        }
+        total += entry.count;
+    }
+
+    sums.map(|sum| ((sum + total / 2) / total) as u8)
+}
+
+fn median_cut(histogram: Vec<HistogramEntry>, colors: usize) -> Vec<[u8; 3]> {
+    let mut boxes: Vec<Vec<HistogramEntry>> = vec![histogram];
+
+    while boxes.len() < colors {
+        // Split the box that spans the widest range on any channel
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, entries)| entries.len() > 1)
+            .map(|(i, entries)| {
+                let (channel, range) = widest_channel(entries);
+                (i, channel, range)
+            })
+            .max_by_key(|(i, _, range)| (*range, std::cmp::Reverse(*i)))
+            .map(|(i, channel, _)| (i, channel))
+        else {
+            break;
+        };
+
+        let mut entries = boxes.swap_remove(index);
+        entries.sort_by_key(|entry| (entry.color[channel], entry.color));
+
+        // Split at the pixel weighted median, keeping at least one color on each side
+        let half = entries.iter().map(|entry| entry.count).sum::<u64>() / 2;
+        let mut running = 0;
+        let mut split = 1;
+        for (i, entry) in entries.iter().enumerate() {
+            running += entry.count;
+            if running >= half {
+                split = (i + 1).clamp(1, entries.len() - 1);
+                break;
+            }
+        }
+
+        let upper = entries.split_off(split);
+        boxes.push(entries);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|entries| weighted_average(entries))
+        .collect()
+}
+
+/// Returns the channel with the largest spread among `entries`, and that spread.
+fn widest_channel(entries: &[HistogramEntry]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let min = entries.iter().map(|e| e.color[channel]).min().unwrap_or(0);
+            let max = entries.iter().map(|e| e.color[channel]).max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|(channel, range)| (*range, std::cmp::Reverse(*channel)))
+        .unwrap_or((0, 0))
+}
+
+const OCTREE_DEPTH: usize = 8;
+
+#[derive(Default)]
+struct OctreeNode {
+    children: [Option<usize>; 8],
+    sums: [u64; 3],
+    count: u64,
+    is_leaf: bool,
+}
+
+fn octree(histogram: &[HistogramEntry], colors: usize) -> Vec<[u8; 3]> {
+    let mut nodes: Vec<OctreeNode> = vec![OctreeNode::default()];
+    // Inner nodes per depth, these are the candidates for merging
+    let mut levels: Vec<Vec<usize>> = vec![Vec::new(); OCTREE_DEPTH];
+    let mut leaf_count = 0;
+
+    for entry in histogram {
+        let mut node = 0;
+
+        for depth in 0..OCTREE_DEPTH {
+            let shift = 7 - depth;
+            let child = (((entry.color[0] >> shift) & 1) << 2
+                | ((entry.color[1] >> shift) & 1) << 1
+                | ((entry.color[2] >> shift) & 1)) as usize;
+
+            node = match nodes[node].children[child] {
+                Some(existing) => existing,
+                None => {
+                    nodes.push(OctreeNode::default());
+                    let created = nodes.len() - 1;
+                    nodes[node].children[child] = Some(created);
+
+                    if depth + 1 == OCTREE_DEPTH {
+                        nodes[created].is_leaf = true;
+                        leaf_count += 1;
+                    } else {
+                        levels[depth + 1].push(created);
+                    }
+                    created
+                }
+            };
+        }
+
+        let leaf = &mut nodes[node];
+        for c in 0..3 {
+            leaf.sums[c] += entry.color[c] as u64 * entry.count;
+        }
+        leaf.count += entry.count;
+    }
+    levels[0].push(0);
+
+    // Merge the deepest, least used nodes until the leaves fit the palette
+    let mut sorted_depth = None;
+    while leaf_count > colors {
+        let Some(depth) = levels.iter().rposition(|level| !level.is_empty()) else {
+            break;
+        };
+
+        // The children of the deepest level are all leaves by now, so their counts are final
+        if sorted_depth != Some(depth) {
+            let mut level = std::mem::take(&mut levels[depth]);
+            level.sort_by_key(|&node| std::cmp::Reverse(subtree_count(&nodes, node)));
+            levels[depth] = level;
+            sorted_depth = Some(depth);
+        }
+
+        let node = levels[depth].pop().expect("level is not empty");
+        let children: Vec<usize> = nodes[node].children.iter().flatten().copied().collect();
+
+        // Folding a whole subtree can overshoot, the remaining leaves are merged pairwise below
+        if leaf_count + 1 - children.len() < colors {
+            break;
+        }
+
+        for &child in &children {
+            let (sums, count) = (nodes[child].sums, nodes[child].count);
+            for (total, sum) in nodes[node].sums.iter_mut().zip(sums) {
+                *total += sum;
+            }
+            nodes[node].count += count;
+        }
+
+        nodes[node].children = [None; 8];
+        nodes[node].is_leaf = true;
+        leaf_count = leaf_count + 1 - children.len();
+    }
+
+    let mut leaves = Vec::with_capacity(leaf_count);
+    collect_leaves(&nodes, 0, &mut leaves);
+
+    // Fold the least used leaf into the leaf closest in color until the palette fits
+    while leaves.len() > colors {
+        let (smallest, _) = leaves
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (_, count))| *count)
+            .expect("more leaves than colors");
+        let (sums, count) = leaves.swap_remove(smallest);
+        let color = average(sums, count).map(|c| c as f32);
+
+        let closest = leaves
+            .iter()
+            .map(|(sums, count)| average(*sums, *count).map(|c| c as f32))
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                distance_squared(color, *a).total_cmp(&distance_squared(color, *b))
+            })
+            .map(|(i, _)| i)
+            .expect("at least one leaf remains");
+
+        for (total, sum) in leaves[closest].0.iter_mut().zip(sums) {
+            *total += sum;
+        }
+        leaves[closest].1 += count;
+    }
+
+    leaves
+        .into_iter()
+        .map(|(sums, count)| average(sums, count))
+        .collect()
+}
+
+fn average(sums: [u64; 3], count: u64) -> [u8; 3] {
+    sums.map(|sum| ((sum + count / 2) / count) as u8)
+}
+
+/// Counts the pixels below `node`. Only called on nodes whose children are all leaves.
+fn subtree_count(nodes: &[OctreeNode], node: usize) -> u64 {
+    nodes[node]
+        .children
+        .iter()
+        .flatten()
+        .map(|&child| nodes[child].count)
+        .sum()
+}
+
+/// Collects the color sums and pixel counts of every used leaf below `node`.
+fn collect_leaves(nodes: &[OctreeNode], node: usize, leaves: &mut Vec<([u64; 3], u64)>) {
+    let current = &nodes[node];
+
+    if current.is_leaf {
+        if current.count > 0 {
+            leaves.push((current.sums, current.count));
+        }
+        return;
+    }
+
+    for &child in current.children.iter().flatten() {
+        collect_leaves(nodes, child, leaves);
+    }
+}
+
+fn kmeans(histogram: &[HistogramEntry], colors: usize, seed: u64) -> Vec<[u8; 3]> {
+    let mut rng = SplitMix64(seed);
+    let points: Vec<[f32; 3]> = histogram
+        .iter()
+        .map(|entry| entry.color.map(|c| c as f32))
+        .collect();
+
+    // k-means++ initialization, picking far apart colors with a probability weighted by pixel count
+    let mut centers: Vec<[f32; 3]> = Vec::with_capacity(colors);
+    centers.push(points[weighted_pick(&mut rng, histogram, |i| histogram[i].count as f64)]);
+
+    let mut nearest_distance: Vec<f64> = points
+        .iter()
+        .map(|p| distance_squared(*p, centers[0]) as f64)
+        .collect();
+
+    while centers.len() < colors {
+        let next = weighted_pick(&mut rng, histogram, |i| {
+            nearest_distance[i] * histogram[i].count as f64
+        });
+        centers.push(points[next]);
+
+        for (i, point) in points.iter().enumerate() {
+            let distance = distance_squared(*point, points[next]) as f64;
+            nearest_distance[i] = nearest_distance[i].min(distance);
+        }
+    }
+
+    let mut assignments = vec![usize::MAX; points.len()];
+
+    for _ in 0..KMEANS_MAX_ITERATIONS {
+        let mut changed = false;
+
+        for (i, point) in points.iter().enumerate() {
+            let closest = closest_center(&centers, *point);
+            if assignments[i] != closest {
+                assignments[i] = closest;
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+
+        let mut sums = vec![[0f64; 3]; centers.len()];
+        let mut totals = vec![0f64; centers.len()];
+
+        for (i, point) in points.iter().enumerate() {
+            let weight = histogram[i].count as f64;
+            for c in 0..3 {
+                sums[assignments[i]][c] += point[c] as f64 * weight;
+            }
+            totals[assignments[i]] += weight;
+        }
+
+        for (k, center) in centers.iter_mut().enumerate() {
+            // Empty clusters keep their previous center
+            if totals[k] > 0.0 {
+                *center = sums[k].map(|sum| (sum / totals[k]) as f32);
+            }
+        }
+    }
+
+    let mut palette: Vec<[u8; 3]> = Vec::with_capacity(centers.len());
+    for center in centers {
+        let color = center.map(|c| c.round().clamp(0.0, 255.0) as u8);
+        if !palette.contains(&color) {
+            palette.push(color);
+        }
+    }
+    palette
+}
+
+fn closest_center(centers: &[[f32; 3]], point: [f32; 3]) -> usize {
+    let mut best = 0;
+    let mut best_distance = f32::MAX;
+
+    for (k, center) in centers.iter().enumerate() {
+        let distance = distance_squared(point, *center);
+        if distance < best_distance {
+            best_distance = distance;
+            best = k;
+        }
+    }
+
+    best
+}
+
+fn distance_squared(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+/// Picks an index into `histogram` with a probability proportional to `weight`.
+fn weighted_pick(
+    rng: &mut SplitMix64,
+    histogram: &[HistogramEntry],
+    weight: impl Fn(usize) -> f64,
+) -> usize {
+    let total: f64 = (0..histogram.len()).map(&weight).sum();
+    if total <= 0.0 {
+        return 0;
+    }
+
+    let mut target = rng.next_f64() * total;
+    for i in 0..histogram.len() {
+        target -= weight(i);
+        if target < 0.0 {
+            return i;
+        }
+    }
+
+    histogram.len() - 1
+}
+
+/// A tiny deterministic random number generator, so k-means is reproducible from a seed.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a float in `0.0..1.0`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
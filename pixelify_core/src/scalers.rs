@@ -0,0 +1,728 @@
+//! Pixel-art-aware upscaling filters: EPX/Scale2x, Scale3x, hqx and xBR.
+//!
+//! Nearest-neighbor scaling keeps every stair step of a diagonal edge. These filters look at the
+//! neighbors of each source pixel to find edges, and round off the steps instead:
+//! - EPX (also known as Scale2x) and Scale3x only copy neighbor colors, so no new colors appear,
+//! - hqx, Maxim Stepin's hq2x, hq3x and hq4x, sorts the 8 neighbors into similar and different ones,
+//!   and fills the block with the blends its lookup tables give for that pattern,
+//! - xBR, by Hyllian, weighs the edge direction over a 5x5 window, and blends each corner in
+//!   proportions that follow the slope of the edge.
+//!
+//! On opaque images hqx gives the same pixels as the reference implementation. Fully transparent
+//! pixels are treated as one color whatever their RGB values, a change in alpha counts as an edge,
+//! and blending is done on premultiplied colors, so the hidden RGB of transparent pixels never
+//! bleeds into an edge.
+
+use crate::hqx_tables::{
+    HQ2X_CORNER, HQ3X_CENTER, HQ3X_CORNER, HQ3X_EDGE, HQ4X_CORNER, HQ4X_EDGE, HQ4X_INNER, HqxRule,
+    HqxTable,
+};
+use image::RgbaImage;
+
+type Color = [u8; 4];
+
+/// The pixels of the source image, with reads outside the bounds clamped to the nearest edge.
+struct Source {
+    width: i64,
+    height: i64,
+    pixels: Vec<Color>,
+}
+
+impl Source {
+    fn new(image: &RgbaImage) -> Source {
+        let pixels = image
+            .pixels()
+            .map(|pixel| if pixel[3] == 0 { [0; 4] } else { pixel.0 })
+            .collect();
+
+        Source {
+            width: image.width() as i64,
+            height: image.height() as i64,
+            pixels,
+        }
+    }
+
+    fn index(&self, x: i64, y: i64) -> usize {
+        let x = x.clamp(0, self.width - 1);
+        let y = y.clamp(0, self.height - 1);
+        (y * self.width + x) as usize
+    }
+
+    fn get(&self, x: i64, y: i64) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+
+    /// The pixels with their YUV values, for the filters that compare colors.
+    fn samples(&self) -> Vec<Sample> {
+        self.pixels
+            .iter()
+            .map(|&color| Sample {
+                color,
+                yuv: yuv(color),
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    color: Color,
+    yuv: [i32; 3],
+}
+
+/// Scales by `factor` with repeated Scale2x passes followed by Scale3x passes.
+///
+/// `factor` must be a product of 2s and 3s, e.g. 4 is two Scale2x passes like AdvMAME4x.
+pub(crate) fn scale_epx(image: &RgbaImage, factor: u32) -> RgbaImage {
+    let mut scaled = image.clone();
+    let mut remaining = factor;
+
+    while remaining > 1 && remaining.is_multiple_of(2) {
+        scaled = scale2x(&scaled);
+        remaining /= 2;
+    }
+    while remaining > 1 && remaining.is_multiple_of(3) {
+        scaled = scale3x(&scaled);
+        remaining /= 3;
+    }
+
+    scaled
+}
+
+fn scale2x(image: &RgbaImage) -> RgbaImage {
+    let source = Source::new(image);
+    let mut scaled = RgbaImage::new(image.width() * 2, image.height() * 2);
+
+    for y in 0..source.height {
+        for x in 0..source.width {
+            let b = source.get(x, y - 1);
+            let d = source.get(x - 1, y);
+            let e = source.get(x, y);
+            let f = source.get(x + 1, y);
+            let h = source.get(x, y + 1);
+
+            let block = if b != h && d != f {
+                [
+                    if d == b { d } else { e },
+                    if b == f { f } else { e },
+                    if d == h { d } else { e },
+                    if h == f { f } else { e },
+                ]
+            } else {
+                [e; 4]
+            };
+
+            write_block(&mut scaled, x, y, 2, &block);
+        }
+    }
+
+    scaled
+}
+
+fn scale3x(image: &RgbaImage) -> RgbaImage {
+    let source = Source::new(image);
+    let mut scaled = RgbaImage::new(image.width() * 3, image.height() * 3);
+
+    for y in 0..source.height {
+        for x in 0..source.width {
+            let a = source.get(x - 1, y - 1);
+            let b = source.get(x, y - 1);
+            let c = source.get(x + 1, y - 1);
+            let d = source.get(x - 1, y);
+            let e = source.get(x, y);
+            let f = source.get(x + 1, y);
+            let g = source.get(x - 1, y + 1);
+            let h = source.get(x, y + 1);
+            let i = source.get(x + 1, y + 1);
+
+            let block = if b != h && d != f {
+                [
+                    if d == b { d } else { e },
+                    if (d == b && e != c) || (b == f && e != a) {
+                        b
+                    } else {
+                        e
+                    },
+                    if b == f { f } else { e },
+                    if (d == b && e != g) || (d == h && e != a) {
+                        d
+                    } else {
+                        e
+                    },
+                    e,
+                    if (b == f && e != i) || (h == f && e != c) {
+                        f
+                    } else {
+                        e
+                    },
+                    if d == h { d } else { e },
+                    if (d == h && e != i) || (h == f && e != g) {
+                        h
+                    } else {
+                        e
+                    },
+                    if h == f { f } else { e },
+                ]
+            } else {
+                [e; 9]
+            };
+
+            write_block(&mut scaled, x, y, 3, &block);
+        }
+    }
+
+    scaled
+}
+
+/// The window positions behind bits 0 to 7 of an hqx pattern.
+const PATTERN_NEIGHBORS: [usize; 8] = [0, 1, 2, 3, 5, 6, 7, 8];
+
+/// Scales by 2, 3 or 4 with hq2x, hq3x or hq4x.
+///
+/// Each neighbor in the 3x3 window either differs from the source pixel or not, and the lookup tables
+/// give every output pixel a blend of the window for that pattern. Some blends also depend on whether
+/// two neighbors differ from each other.
+pub(crate) fn scale_hqx(image: &RgbaImage, factor: u32) -> RgbaImage {
+    let source = Source::new(image);
+    let samples = source.samples();
+    let n = factor as usize;
+    let mut scaled = RgbaImage::new(image.width() * factor, image.height() * factor);
+
+    let cells: Vec<(&HqxTable, [usize; 9])> =
+        (0..n * n).map(|i| hqx_cell(n, i % n, i / n)).collect();
+
+    for y in 0..source.height {
+        for x in 0..source.width {
+            let window: [Sample; 9] = std::array::from_fn(|k| {
+                samples[source.index(x + (k % 3) as i64 - 1, y + (k / 3) as i64 - 1)]
+            });
+            let differs = window.map(|neighbor| hqx_differs(window[4], neighbor));
+
+            let block: Vec<Color> = cells
+                .iter()
+                .map(|(table, turn)| {
+                    let pattern = PATTERN_NEIGHBORS
+                        .iter()
+                        .enumerate()
+                        .filter(|&(_, &k)| differs[turn[k]])
+                        .fold(0, |pattern, (bit, _)| pattern | 1 << bit);
+
+                    let blend = match table.rules[table.patterns[pattern] as usize] {
+                        HqxRule::Blend(blend) => blend,
+                        HqxRule::Edge((a, b), edge, similar) => {
+                            if hqx_differs(window[turn[a]], window[turn[b]]) {
+                                edge
+                            } else {
+                                similar
+                            }
+                        }
+                    };
+                    blend_sixteenths(
+                        blend
+                            .iter()
+                            .map(|&(k, weight)| (window[turn[k]].color, weight)),
+                    )
+                })
+                .collect();
+
+            write_block(&mut scaled, x, y, factor, &block);
+        }
+    }
+
+    scaled
+}
+
+/// The table of the pixel at `column`, `row` of an `n` x `n` hqx block, and how to turn the window for it.
+///
+/// The tables hold the top left quarter of a block. Other pixels are mirrored into it, and across the
+/// diagonal into its upper half. Position `k` of the table's window is position `turn[k]` of the image.
+fn hqx_cell(n: usize, column: usize, row: usize) -> (&'static HqxTable, [usize; 9]) {
+    let mirror_x = 2 * column + 1 > n;
+    let mirror_y = 2 * row + 1 > n;
+    let column = if mirror_x { n - 1 - column } else { column };
+    let row = if mirror_y { n - 1 - row } else { row };
+    let transpose = column < row;
+    let (column, row) = if transpose {
+        (row, column)
+    } else {
+        (column, row)
+    };
+
+    let table = match (n, column, row) {
+        (2, 0, 0) => &HQ2X_CORNER,
+        (3, 0, 0) => &HQ3X_CORNER,
+        (3, 1, 0) => &HQ3X_EDGE,
+        (3, 1, 1) => &HQ3X_CENTER,
+        (4, 0, 0) => &HQ4X_CORNER,
+        (4, 1, 0) => &HQ4X_EDGE,
+        (4, 1, 1) => &HQ4X_INNER,
+        _ => unreachable!("hqx scales by 2, 3 or 4"),
+    };
+
+    let turn = std::array::from_fn(|k| {
+        let (dx, dy) = (k % 3, k / 3);
+        let (dx, dy) = if transpose { (dy, dx) } else { (dx, dy) };
+        let dx = if mirror_x { 2 - dx } else { dx };
+        let dy = if mirror_y { 2 - dy } else { dy };
+        dy * 3 + dx
+    });
+
+    (table, turn)
+}
+
+/// The hqx similarity test, with alpha held to the same threshold as luma.
+///
+/// The reference tables store negative chroma as 0, so it is compared that way.
+fn hqx_differs(a: Sample, b: Sample) -> bool {
+    let chroma = |value: i32| value.max(0);
+    a.yuv[0].abs_diff(b.yuv[0]) > 48
+        || chroma(a.yuv[1]).abs_diff(chroma(b.yuv[1])) > 7
+        || chroma(a.yuv[2]).abs_diff(chroma(b.yuv[2])) > 6
+        || a.color[3].abs_diff(b.color[3]) > 48
+}
+
+/// Blends colors weighted in sixteenths, on premultiplied colors.
+///
+/// Results are truncated like hqx does, so opaque colors come out exactly as in the reference.
+fn blend_sixteenths(colors: impl Iterator<Item = (Color, u32)>) -> Color {
+    let mut sums = [0u32; 3];
+    let mut alpha_sum = 0u32;
+    for (color, weight) in colors {
+        let weight = weight * color[3] as u32;
+        for (sum, channel) in sums.iter_mut().zip(color) {
+            *sum += channel as u32 * weight;
+        }
+        alpha_sum += weight;
+    }
+
+    if alpha_sum == 0 {
+        return [0; 4];
+    }
+    let [r, g, b] = sums.map(|sum| (sum / alpha_sum) as u8);
+    [r, g, b, (alpha_sum / 16) as u8]
+}
+
+/// How xBR blends the new color into the corner of a block, depending on the edge slope.
+#[derive(Clone, Copy)]
+enum XbrEdge {
+    /// Both shallow and steep, the edge runs along the whole corner.
+    LeftUp,
+    /// Shallow edge, mostly along the bottom row.
+    Left,
+    /// Steep edge, mostly along the right column.
+    Up,
+    /// A 45 degree edge.
+    Diagonal,
+    /// An edge that only nicks the corner pixel.
+    Weak,
+}
+
+/// One step of blending a corner, applied in order to the bottom right corner of a block.
+#[derive(Clone, Copy)]
+enum XbrStep {
+    /// Mixes the corner color into the cell at `(column, row)`, by this many eighths.
+    Mix(usize, usize, u8),
+    /// Copies the second cell into the first, after the steps before it.
+    Copy((usize, usize), (usize, usize)),
+}
+
+/// Scales by 2, 3 or 4 with xBR.
+///
+/// Each corner of a source pixel is checked on its own, in the order bottom right, top right,
+/// top left and bottom left. Later corners blend over the earlier ones, like the reference does.
+pub(crate) fn scale_xbr(image: &RgbaImage, factor: u32) -> RgbaImage {
+    let source = Source::new(image);
+    let samples = source.samples();
+    let n = factor as usize;
+    let mut scaled = RgbaImage::new(image.width() * factor, image.height() * factor);
+
+    for y in 0..source.height {
+        for x in 0..source.width {
+            let mut block = vec![source.get(x, y); n * n];
+
+            // Every corner is the bottom right one, seen through the window rotated a quarter turn at a time
+            for rotation in 0..4 {
+                let at = |dx: i64, dy: i64| {
+                    let (dx, dy) = rotate(dx, dy, rotation);
+                    samples[source.index(x + dx, y + dy)]
+                };
+
+                let Some((edge, color)) = xbr_corner(at) else {
+                    continue;
+                };
+
+                let cell = |(column, row): (usize, usize)| {
+                    let (column, row) = rotate_cell(column, row, n, rotation);
+                    row * n + column
+                };
+                for &step in xbr_steps(n, edge) {
+                    match step {
+                        XbrStep::Mix(column, row, eighths) => {
+                            let pixel = &mut block[cell((column, row))];
+                            *pixel = mix(*pixel, color, eighths as f32 / 8.0);
+                        }
+                        XbrStep::Copy(to, from) => block[cell(to)] = block[cell(from)],
+                    }
+                }
+            }
+
+            write_block(&mut scaled, x, y, n as u32, &block);
+        }
+    }
+
+    scaled
+}
+
+/// Detects an edge across the bottom right corner, returning its kind and the color to blend in.
+fn xbr_corner(at: impl Fn(i64, i64) -> Sample) -> Option<(XbrEdge, Color)> {
+    let (b, c, d, e, f) = (at(0, -1), at(1, -1), at(-1, 0), at(0, 0), at(1, 0));
+    let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+    let (f4, i4, h5, i5) = (at(2, 0), at(2, 1), at(0, 2), at(1, 2));
+
+    if e.color == h.color || e.color == f.color {
+        return None;
+    }
+
+    // Weighted edge strength along the corner's diagonal against across it
+    let along =
+        xbr_diff(e, c) + xbr_diff(e, g) + xbr_diff(i, h5) + xbr_diff(i, f4) + 4 * xbr_diff(h, f);
+    let across =
+        xbr_diff(h, d) + xbr_diff(h, i5) + xbr_diff(f, i4) + xbr_diff(f, b) + 4 * xbr_diff(e, i);
+
+    if along > across {
+        return None;
+    }
+
+    let color = if xbr_diff(e, f) <= xbr_diff(e, h) {
+        f.color
+    } else {
+        h.color
+    };
+
+    let confirmed = (!xbr_eq(f, b) && !xbr_eq(h, d))
+        || (xbr_eq(e, i) && !xbr_eq(f, i4) && !xbr_eq(h, i5))
+        || xbr_eq(e, g)
+        || xbr_eq(e, c);
+
+    if along < across && confirmed {
+        let ke = xbr_diff(f, g);
+        let ki = xbr_diff(h, c);
+        let left = 2 * ke <= ki && e.color != g.color && d.color != g.color;
+        let up = ke >= 2 * ki && e.color != c.color && b.color != c.color;
+
+        let edge = match (left, up) {
+            (true, true) => XbrEdge::LeftUp,
+            (true, false) => XbrEdge::Left,
+            (false, true) => XbrEdge::Up,
+            (false, false) => XbrEdge::Diagonal,
+        };
+        Some((edge, color))
+    } else {
+        Some((XbrEdge::Weak, color))
+    }
+}
+
+/// The xBR blend of the bottom right corner of an `n` x `n` block.
+fn xbr_steps(n: usize, edge: XbrEdge) -> &'static [XbrStep] {
+    use XbrStep::{Copy, Mix};
+
+    match (n, edge) {
+        (2, XbrEdge::LeftUp) => &[Mix(1, 1, 7), Mix(0, 1, 2), Copy((1, 0), (0, 1))],
+        (2, XbrEdge::Left) => &[Mix(1, 1, 6), Mix(0, 1, 2)],
+        (2, XbrEdge::Up) => &[Mix(1, 1, 6), Mix(1, 0, 2)],
+        (2, XbrEdge::Diagonal | XbrEdge::Weak) => &[Mix(1, 1, 4)],
+
+        (3, XbrEdge::LeftUp) => &[
+            Mix(1, 2, 6),
+            Mix(0, 2, 2),
+            Copy((2, 1), (1, 2)),
+            Copy((2, 0), (0, 2)),
+            Mix(2, 2, 8),
+        ],
+        (3, XbrEdge::Left) => &[Mix(1, 2, 6), Mix(2, 1, 2), Mix(0, 2, 2), Mix(2, 2, 8)],
+        (3, XbrEdge::Up) => &[Mix(2, 1, 6), Mix(1, 2, 2), Mix(2, 0, 2), Mix(2, 2, 8)],
+        (3, XbrEdge::Diagonal) => &[Mix(2, 2, 7), Mix(2, 1, 1), Mix(1, 2, 1)],
+        (3, XbrEdge::Weak) => &[Mix(2, 2, 4)],
+
+        (4, XbrEdge::LeftUp) => &[
+            Mix(1, 3, 6),
+            Mix(0, 3, 2),
+            Mix(3, 3, 8),
+            Mix(2, 3, 8),
+            Mix(3, 2, 8),
+            Copy((2, 2), (0, 3)),
+            Copy((3, 0), (0, 3)),
+            Copy((3, 1), (1, 3)),
+        ],
+        (4, XbrEdge::Left) => &[
+            Mix(3, 2, 6),
+            Mix(1, 3, 6),
+            Mix(2, 2, 2),
+            Mix(0, 3, 2),
+            Mix(2, 3, 8),
+            Mix(3, 3, 8),
+        ],
+        (4, XbrEdge::Up) => &[
+            Mix(2, 3, 6),
+            Mix(3, 1, 6),
+            Mix(2, 2, 2),
+            Mix(3, 0, 2),
+            Mix(3, 2, 8),
+            Mix(3, 3, 8),
+        ],
+        (4, XbrEdge::Diagonal) => &[Mix(3, 2, 4), Mix(2, 3, 4), Mix(3, 3, 8)],
+        (4, XbrEdge::Weak) => &[Mix(3, 3, 4)],
+
+        _ => &[],
+    }
+}
+
+/// The xBR distance, with luma, chroma and alpha differences added up.
+fn xbr_diff(a: Sample, b: Sample) -> u32 {
+    a.yuv[0].abs_diff(b.yuv[0])
+        + a.yuv[1].abs_diff(b.yuv[1])
+        + a.yuv[2].abs_diff(b.yuv[2])
+        + a.color[3].abs_diff(b.color[3]) as u32
+}
+
+fn xbr_eq(a: Sample, b: Sample) -> bool {
+    xbr_diff(a, b) < 155
+}
+
+/// Rotates a neighbor offset a quarter turn counterclockwise, `times` times.
+fn rotate(dx: i64, dy: i64, times: u32) -> (i64, i64) {
+    (0..times).fold((dx, dy), |(dx, dy), _| (dy, -dx))
+}
+
+/// Rotates a cell of an `n` x `n` block around its center, like `rotate`.
+fn rotate_cell(column: usize, row: usize, n: usize, times: u32) -> (usize, usize) {
+    let last = n as i64 - 1;
+    let (u, v) = rotate(2 * column as i64 - last, 2 * row as i64 - last, times);
+    (((u + last) / 2) as usize, ((v + last) / 2) as usize)
+}
+
+fn write_block(scaled: &mut RgbaImage, x: i64, y: i64, n: u32, block: &[Color]) {
+    for (i, color) in block.iter().enumerate() {
+        let (column, row) = (i as u32 % n, i as u32 / n);
+        scaled.put_pixel(
+            x as u32 * n + column,
+            y as u32 * n + row,
+            image::Rgba(*color),
+        );
+    }
+}
+
+/// Mixes `to` into `from` by `t`, on premultiplied colors.
+fn mix(from: Color, to: Color, t: f32) -> Color {
+    let (from_alpha, to_alpha) = (from[3] as f32, to[3] as f32);
+    let alpha = from_alpha + (to_alpha - from_alpha) * t;
+    if alpha <= 0.0 {
+        return [0; 4];
+    }
+
+    let channel = |i: usize| {
+        let a = from[i] as f32 * from_alpha;
+        let b = to[i] as f32 * to_alpha;
+        ((a + (b - a) * t) / alpha).round().clamp(0.0, 255.0) as u8
+    };
+
+    [channel(0), channel(1), channel(2), alpha.round() as u8]
+}
+
+/// A color in YUV on a 0 to 255 scale, with chroma centered on 0 and every value truncated.
+///
+/// The fused multiply-adds, in this order, give the exact values of the hqx reference tables.
+fn yuv(color: Color) -> [i32; 3] {
+    let [r, g, b] = [color[0], color[1], color[2]].map(f64::from);
+    [
+        b.mul_add(0.114, r.mul_add(0.299, 0.587 * g)),
+        b.mul_add(0.5, r.mul_add(-0.169, -0.331 * g)),
+        b.mul_add(-0.081, r.mul_add(0.5, -0.419 * g)),
+    ]
+    .map(|value| value as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A staircase edge, black below and white above.
+    const STAIRS: [&str; 3] = ["...", "#..", "##."];
+
+    /// An opaque image from rows of `#` for black and `.` for white.
+    fn image(rows: &[&str]) -> RgbaImage {
+        RgbaImage::from_fn(rows[0].len() as u32, rows.len() as u32, |x, y| {
+            match rows[y as usize].as_bytes()[x as usize] {
+                b'#' => image::Rgba([0, 0, 0, 255]),
+                _ => image::Rgba([255, 255, 255, 255]),
+            }
+        })
+    }
+
+    /// The gray level of every pixel of a black and white result, row by row.
+    fn levels(image: &RgbaImage) -> Vec<Vec<u8>> {
+        image
+            .rows()
+            .map(|row| {
+                row.map(|pixel| {
+                    assert_eq!(pixel[3], 255);
+                    assert!(pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+                    pixel[0]
+                })
+                .collect()
+            })
+            .collect()
+    }
+
+    /// The rows of `#` and `.` of a result that only copies source colors.
+    fn art(image: &RgbaImage) -> Vec<String> {
+        levels(image)
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&level| match level {
+                        0 => '#',
+                        255 => '.',
+                        _ => panic!("blended level {level}"),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn epx_2x_rounds_a_diagonal_edge() {
+        assert_eq!(
+            art(&scale_epx(&image(&STAIRS), 2)),
+            ["......", "......", "#.....", "###...", "###...", "####.."]
+        );
+    }
+
+    #[test]
+    fn scale3x_rounds_a_diagonal_edge() {
+        assert_eq!(
+            art(&scale_epx(&image(&STAIRS), 3)),
+            [
+                ".........",
+                ".........",
+                ".........",
+                "#........",
+                "###......",
+                "####.....",
+                "#####....",
+                "#####....",
+                "######...",
+            ]
+        );
+    }
+
+    // The expected hqx levels come from the reference implementation
+
+    #[test]
+    fn hq2x_matches_the_reference_on_a_diagonal_edge() {
+        assert_eq!(
+            levels(&scale_hqx(&image(&STAIRS), 2)),
+            [
+                [255, 255, 255, 255, 255, 255],
+                [255, 255, 255, 255, 255, 255],
+                [63, 191, 255, 255, 255, 255],
+                [0, 0, 127, 255, 255, 255],
+                [0, 0, 0, 191, 255, 255],
+                [0, 0, 0, 63, 255, 255],
+            ]
+        );
+    }
+
+    #[test]
+    fn hq3x_matches_the_reference_on_a_diagonal_edge() {
+        assert_eq!(
+            levels(&scale_hqx(&image(&STAIRS), 3)),
+            [
+                [255, 255, 255, 255, 255, 255, 255, 255, 255],
+                [255, 255, 255, 255, 255, 255, 255, 255, 255],
+                [255, 255, 255, 255, 255, 255, 255, 255, 255],
+                [63, 191, 255, 255, 255, 255, 255, 255, 255],
+                [0, 0, 63, 223, 255, 255, 255, 255, 255],
+                [0, 0, 0, 31, 223, 255, 255, 255, 255],
+                [0, 0, 0, 0, 63, 255, 255, 255, 255],
+                [0, 0, 0, 0, 0, 191, 255, 255, 255],
+                [0, 0, 0, 0, 0, 63, 255, 255, 255],
+            ]
+        );
+    }
+
+    #[test]
+    fn hq4x_matches_the_reference_on_a_diagonal_edge() {
+        assert_eq!(
+            levels(&scale_hqx(&image(&STAIRS), 4))[4..8],
+            [
+                [63, 191, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+                [0, 0, 63, 191, 255, 255, 255, 255, 255, 255, 255, 255],
+                [0, 0, 0, 0, 127, 255, 255, 255, 255, 255, 255, 255],
+                [0, 0, 0, 0, 0, 127, 255, 255, 255, 255, 255, 255],
+            ]
+        );
+    }
+
+    #[test]
+    fn hqx_blends_transparent_pixels_without_their_rgb() {
+        let mut source = RgbaImage::from_pixel(2, 2, image::Rgba([0, 0, 0, 0]));
+        source.put_pixel(0, 1, image::Rgba([255, 0, 0, 255]));
+        source.put_pixel(1, 1, image::Rgba([255, 0, 0, 255]));
+        source.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+
+        let scaled = scale_hqx(&source, 2);
+        assert!(scaled.pixels().any(|pixel| pixel[3] > 0 && pixel[3] < 255));
+        for pixel in scaled.pixels().filter(|pixel| pixel[3] > 0) {
+            assert_eq!(pixel.0[..3], [255, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn xbr_softens_only_the_corners_of_a_lone_pixel() {
+        let levels = levels(&scale_xbr(&image(&["...", ".#.", "..."]), 4));
+        assert_eq!(
+            levels[4..8]
+                .iter()
+                .map(|row| &row[4..8])
+                .collect::<Vec<_>>(),
+            [
+                [128, 0, 0, 128],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0],
+                [128, 0, 0, 128]
+            ]
+        );
+        // Nothing spreads outside the pixel's own block
+        for (y, row) in levels.iter().enumerate() {
+            for (x, &level) in row.iter().enumerate() {
+                if !(4..8).contains(&x) || !(4..8).contains(&y) {
+                    assert_eq!(level, 255, "{x}, {y}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn xbr_blends_a_diagonal_edge() {
+        assert_eq!(
+            levels(&scale_xbr(&image(&STAIRS), 2)),
+            [
+                [255, 255, 255, 255, 255, 255],
+                [255, 255, 255, 255, 255, 255],
+                [64, 191, 255, 255, 255, 255],
+                [0, 0, 128, 255, 255, 255],
+                [0, 0, 0, 191, 255, 255],
+                [0, 0, 0, 64, 255, 255],
+            ]
+        );
+        assert_eq!(
+            levels(&scale_xbr(&image(&STAIRS), 3))[3..6],
+            [
+                [64, 191, 255, 255, 255, 255, 255, 255, 255],
+                [0, 0, 64, 223, 255, 255, 255, 255, 255],
+                [0, 0, 0, 32, 223, 255, 255, 255, 255],
+            ]
+        );
+    }
+}
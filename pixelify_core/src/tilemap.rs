@@ -0,0 +1,548 @@
+//! Tilemaps, cutting level art into a tileset and a map of tile indices.
+//!
+//! An image is sliced into fixed-size tiles and identical tiles are stored once.
+//! Optionally, a tile that is a flipped or rotated copy of another one is stored once as well,
+//! and the map records how to flip it back.
+//!
+//! Map cells use the global tile IDs (GIDs) of the Tiled editor: the tile index plus one,
+//! with 0 for an empty cell and the flip flags in the top three bits.
+
+use crate::PixelifyImage;
+use crate::pixelify_errors::ImageProcessingError;
+use image::{Rgba, RgbaImage};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Set in a GID when the tile is flipped horizontally.
+pub const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+/// Set in a GID when the tile is flipped vertically.
+pub const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+/// Set in a GID when the tile is flipped along its top left to bottom right diagonal.
+/// Combined with the other flips, this turns a tile by 90 degree steps.
+pub const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+
+/// Which copies of a tile count as the same tile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TileMatching {
+    /// Only identical tiles.
+    #[default]
+    Exact,
+    /// Identical tiles, and tiles flipped horizontally, vertically or both.
+    Flips,
+    /// All of the above, plus tiles rotated by 90 degree steps. Needs square tiles.
+    Rotations,
+}
+
+impl TileMatching {
+    pub const ALL: [TileMatching; 3] = [
+        TileMatching::Exact,
+        TileMatching::Flips,
+        TileMatching::Rotations,
+    ];
+
+    /// The flip flags a matching tile may have, in order of preference.
+    fn transforms(self) -> &'static [Transform] {
+        const ALL: [Transform; 8] = [
+            Transform::new(false, false, false),
+            Transform::new(true, false, false),
+            Transform::new(false, true, false),
+            Transform::new(true, true, false),
+            Transform::new(true, false, true),
+            Transform::new(false, true, true),
+            Transform::new(false, false, true),
+            Transform::new(true, true, true),
+        ];
+
+        match self {
+            TileMatching::Exact => &ALL[..1],
+            TileMatching::Flips => &ALL[..4],
+            TileMatching::Rotations => &ALL,
+        }
+    }
+}
+
+impl fmt::Display for TileMatching {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TileMatching::Exact => "exact",
+            TileMatching::Flips => "flips",
+            TileMatching::Rotations => "rotations",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for TileMatching {
+    type Err = String;
+
+    /// Parses a tile matching mode, ignoring case, `-` and `_`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match normalized.as_str() {
+            "exact" | "none" => Ok(TileMatching::Exact),
+            "flips" | "flip" => Ok(TileMatching::Flips),
+            "rotations" | "rotation" | "flipsandrotations" => Ok(TileMatching::Rotations),
+            _ => Err(format!(
+                "unknown tile matching '{s}', expected one of: exact, flips, rotations"
+            )),
+        }
+    }
+}
+
+/// Options for `slice_tilemap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TilemapOptions {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub matching: TileMatching,
+    /// Leaves fully transparent tiles out of the tileset, their cells become empty.
+    pub skip_empty: bool,
+    /// The number of tile columns in the tileset image, by default it is as square as possible.
+    pub columns: Option<u32>,
+}
+
+impl Default for TilemapOptions {
+    fn default() -> Self {
+        TilemapOptions {
+            tile_width: 16,
+            tile_height: 16,
+            matching: TileMatching::default(),
+            skip_empty: false,
+            columns: None,
+        }
+    }
+}
+
+/// How a tileset tile is flipped to draw a map cell, applied diagonally first, as in Tiled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Transform {
+    pub flip_horizontally: bool,
+    pub flip_vertically: bool,
+    pub flip_diagonally: bool,
+}
+
+impl Transform {
+    const fn new(flip_horizontally: bool, flip_vertically: bool, flip_diagonally: bool) -> Self {
+        Transform {
+            flip_horizontally,
+            flip_vertically,
+            flip_diagonally,
+        }
+    }
+
+    /// The tile pixels as they are drawn with this transform.
+    fn apply(self, tile: &RgbaImage) -> RgbaImage {
+        let mut result = if self.flip_diagonally {
+            RgbaImage::from_fn(tile.height(), tile.width(), |x, y| *tile.get_pixel(y, x))
+        } else {
+            tile.clone()
+        };
+        if self.flip_horizontally {
+            image::imageops::flip_horizontal_in_place(&mut result);
+        }
+        if self.flip_vertically {
+            image::imageops::flip_vertical_in_place(&mut result);
+        }
+        result
+    }
+}
+
+/// One cell of a tilemap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileRef {
+    /// The index of the tile in the tileset, counting from 0.
+    pub tile: u32,
+    pub transform: Transform,
+}
+
+impl TileRef {
+    /// The Tiled global tile ID of this cell, for a tileset whose first GID is 1.
+    pub fn gid(&self) -> u32 {
+        let mut gid = self.tile + 1;
+        if self.transform.flip_horizontally {
+            gid |= FLIPPED_HORIZONTALLY;
+        }
+        if self.transform.flip_vertically {
+            gid |= FLIPPED_VERTICALLY;
+        }
+        if self.transform.flip_diagonally {
+            gid |= FLIPPED_DIAGONALLY;
+        }
+        gid
+    }
+}
+
+/// A tileset image and the map that rebuilds the original image from it.
+#[derive(Debug, Clone)]
+pub struct Tilemap {
+    /// The unique tiles as raw RGBA pixels, left to right and top to bottom.
+    pub tileset: PixelifyImage,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tile_count: u32,
+    /// The number of tile columns in `tileset`.
+    pub tileset_columns: u32,
+    /// The map width in tiles.
+    pub width: u32,
+    /// The map height in tiles.
+    pub height: u32,
+    /// The map cells row by row, `None` for a skipped empty tile.
+    pub cells: Vec<Option<TileRef>>,
+}
+
+#[derive(Serialize)]
+struct TilesetJson<'a> {
+    image: &'a str,
+    image_width: u32,
+    image_height: u32,
+    columns: u32,
+    tile_count: u32,
+    first_gid: u32,
+}
+
+#[derive(Serialize)]
+struct FlipFlagsJson {
+    horizontal: u32,
+    vertical: u32,
+    diagonal: u32,
+}
+
+#[derive(Serialize)]
+struct TilemapJson<'a> {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    tileset: TilesetJson<'a>,
+    flip_flags: FlipFlagsJson,
+    data: Vec<u32>,
+}
+
+impl Tilemap {
+    /// The map as Tiled GIDs, row by row, with 0 for empty cells.
+    pub fn gids(&self) -> Vec<u32> {
+        self.cells
+            .iter()
+            .map(|cell| cell.map_or(0, |cell| cell.gid()))
+            .collect()
+    }
+
+    /// Describes the map as JSON, with the cells in `data` as Tiled GIDs.
+    ///
+    /// `tileset_image` is the file name the tileset is saved under.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if the JSON cannot be serialized.
+    pub fn to_json(&self, tileset_image: &str) -> Result<String, ImageProcessingError> {
+        let json = TilemapJson {
+            width: self.width,
+            height: self.height,
+            tile_width: self.tile_width,
+            tile_height: self.tile_height,
+            tileset: TilesetJson {
+                image: tileset_image,
+                image_width: self.tileset.get_width(),
+                image_height: self.tileset.get_height(),
+                columns: self.tileset_columns,
+                tile_count: self.tile_count,
+                first_gid: 1,
+            },
+            flip_flags: FlipFlagsJson {
+                horizontal: FLIPPED_HORIZONTALLY,
+                vertical: FLIPPED_VERTICALLY,
+                diagonal: FLIPPED_DIAGONALLY,
+            },
+            data: self.gids(),
+        };
+
+        serde_json::to_string_pretty(&json).map_err(|e| {
+            ImageProcessingError::failed("tilemap", format!("Failed to write tilemap: {e}"))
+        })
+    }
+
+    /// Describes the map as CSV, one row of Tiled GIDs per line, like Tiled's CSV layer format.
+    pub fn to_csv(&self) -> String {
+        let gids = self.gids();
+        let mut csv = String::new();
+
+        for row in gids.chunks(self.width.max(1) as usize) {
+            let line: Vec<String> = row.iter().map(u32::to_string).collect();
+            csv.push_str(&line.join(","));
+            csv.push('\n');
+        }
+
+        csv
+    }
+}
+
+/// Cuts an image into tiles, deduplicates them and builds the map that puts them back together.
+///
+/// Tiles are numbered in order of first appearance, reading the image row by row.
+/// When the image size is not a multiple of the tile size, the tiles along the right and bottom
+/// edges are padded with transparent pixels.
+///
+/// # Errors
+///
+/// Returns an `ImageProcessingError` if:
+/// - the image cannot be converted to RGBA,
+/// - the tile width or height is 0,
+/// - rotations are matched with tiles that are not square,
+/// - the tileset has 0 columns, or more tiles than GIDs can address.
+pub fn slice_tilemap(
+    image: &PixelifyImage,
+    options: &TilemapOptions,
+) -> Result<Tilemap, ImageProcessingError> {
+    let (tile_width, tile_height) = (options.tile_width, options.tile_height);
+
+    if tile_width == 0 || tile_height == 0 {
+        return Err(tilemap_error("Tile width and height must be at least 1"));
+    }
+    if options.matching == TileMatching::Rotations && tile_width != tile_height {
+        return Err(tilemap_error(format!(
+            "Matching rotated tiles needs square tiles, got {tile_width}x{tile_height}"
+        )));
+    }
+    if options.columns == Some(0) {
+        return Err(tilemap_error("A tileset needs at least one column"));
+    }
+
+    let rgba = image.to_rgba_image()?;
+    let width = rgba.width().div_ceil(tile_width);
+    let height = rgba.height().div_ceil(tile_height);
+
+    let mut tiles: Vec<RgbaImage> = Vec::new();
+    // Every allowed variant of every stored tile, mapped to the tile and the transform that draws it
+    let mut variants: HashMap<Vec<u8>, TileRef> = HashMap::new();
+    let mut cells = Vec::with_capacity(width as usize * height as usize);
+
+    for row in 0..height {
+        for column in 0..width {
+            let tile = cut_tile(
+                &rgba,
+                column * tile_width,
+                row * tile_height,
+                tile_width,
+                tile_height,
+            );
+
+            if options.skip_empty && tile.pixels().all(|pixel| pixel[3] == 0) {
+                cells.push(None);
+                continue;
+            }
+
+            if let Some(cell) = variants.get(tile.as_raw()) {
+                cells.push(Some(*cell));
+                continue;
+            }
+
+            // GIDs keep their top three bits for the flip flags
+            let index = tiles.len() as u32;
+            if index + 1 >= FLIPPED_DIAGONALLY {
+                return Err(tilemap_error(
+                    "The tileset has more tiles than GIDs can address",
+                ));
+            }
+
+            for &transform in options.matching.transforms() {
+                let variant = transform.apply(&tile).into_raw();
+                variants.entry(variant).or_insert(TileRef {
+                    tile: index,
+                    transform,
+                });
+            }
+
+            cells.push(Some(TileRef {
+                tile: index,
+                transform: Transform::default(),
+            }));
+            tiles.push(tile);
+        }
+    }
+
+    let tile_count = tiles.len() as u32;
+    let tileset_columns = match options.columns {
+        Some(columns) => columns.min(tile_count.max(1)),
+        None => (tile_count as f64).sqrt().ceil().max(1.0) as u32,
+    };
+    let tileset_rows = tile_count.div_ceil(tileset_columns);
+
+    let (tileset_width, tileset_height) = tile_width
+        .checked_mul(tileset_columns)
+        .zip(tile_height.checked_mul(tileset_rows))
+        .ok_or_else(|| tilemap_error("The tileset image would be too large"))?;
+
+    let mut tileset = RgbaImage::new(tileset_width, tileset_height);
+    for (i, tile) in tiles.iter().enumerate() {
+        let i = i as u32;
+        image::imageops::replace(
+            &mut tileset,
+            tile,
+            ((i % tileset_columns) * tile_width) as i64,
+            ((i / tileset_columns) * tile_height) as i64,
+        );
+    }
+
+    Ok(Tilemap {
+        tileset: PixelifyImage::from(tileset),
+        tile_width,
+        tile_height,
+        tile_count,
+        tileset_columns,
+        width,
+        height,
+        cells,
+    })
+}
+
+/// Copies one tile out of `image`, padding it with transparency past the image edges.
+///
+/// Fully transparent pixels are cleared to transparent black, so their hidden color cannot tell tiles apart.
+fn cut_tile(image: &RgbaImage, left: u32, top: u32, width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        match image.get_pixel_checked(left + x, top + y) {
+            Some(pixel) if pixel[3] > 0 => *pixel,
+            _ => Rgba([0, 0, 0, 0]),
+        }
+    })
+}
+
+fn tilemap_error(message: impl Into<String>) -> ImageProcessingError {
+    ImageProcessingError::failed("tilemap", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::imageops;
+    use std::collections::HashSet;
+
+    /// A 3x3 tile that looks different under every flip and rotation.
+    fn asymmetric_tile() -> RgbaImage {
+        RgbaImage::from_fn(3, 3, |x, y| {
+            if (x, y) == (0, 0) || (x, y) == (1, 0) || (x, y) == (0, 2) {
+                Rgba([255, x as u8 * 50, y as u8 * 50, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        })
+    }
+
+    #[test]
+    fn transforms_grow_with_the_matching_mode() {
+        assert_eq!(TileMatching::Exact.transforms(), &[Transform::default()]);
+
+        let flips = TileMatching::Flips.transforms();
+        assert_eq!(flips.len(), 4);
+        assert!(flips.iter().all(|transform| !transform.flip_diagonally));
+
+        let all = TileMatching::Rotations.transforms();
+        assert_eq!(all.len(), 8);
+        assert_eq!(&all[..4], flips);
+        assert_eq!(all.iter().collect::<HashSet<_>>().len(), 8);
+    }
+
+    #[test]
+    fn transforms_match_tiled_flips_and_rotations() {
+        let tile = asymmetric_tile();
+        let transpose = |image: &RgbaImage| {
+            RgbaImage::from_fn(image.height(), image.width(), |x, y| *image.get_pixel(y, x))
+        };
+
+        // Tiled applies the diagonal flip first, so the flag combinations below are its rotations
+        let expected = [
+            ((false, false, false), tile.clone()),
+            ((true, false, false), imageops::flip_horizontal(&tile)),
+            ((false, true, false), imageops::flip_vertical(&tile)),
+            ((true, true, false), imageops::rotate180(&tile)),
+            ((true, false, true), imageops::rotate90(&tile)),
+            ((false, true, true), imageops::rotate270(&tile)),
+            ((false, false, true), transpose(&tile)),
+            ((true, true, true), transpose(&imageops::rotate180(&tile))),
+        ];
+
+        for ((h, v, d), image) in expected {
+            assert_eq!(Transform::new(h, v, d).apply(&tile), image, "{h} {v} {d}");
+        }
+    }
+
+    #[test]
+    fn gids_hold_the_tile_and_flip_flags() {
+        let gid = |tile, h, v, d| {
+            TileRef {
+                tile,
+                transform: Transform::new(h, v, d),
+            }
+            .gid()
+        };
+
+        assert_eq!(gid(0, false, false, false), 1);
+        assert_eq!(gid(4, true, false, false), 0x8000_0005);
+        assert_eq!(gid(4, false, true, false), 0x4000_0005);
+        assert_eq!(gid(4, false, false, true), 0x2000_0005);
+        // A tile turned 90 degrees clockwise in Tiled
+        assert_eq!(gid(0, true, false, true), 0xA000_0001);
+        assert_eq!(gid(0, true, true, true), 0xE000_0001);
+    }
+
+    #[test]
+    fn slicing_stores_turned_copies_once() {
+        let tile = asymmetric_tile();
+        let copies = [
+            tile.clone(),
+            imageops::flip_horizontal(&tile),
+            imageops::rotate180(&tile),
+            imageops::rotate90(&tile),
+        ];
+        let mut image = RgbaImage::new(12, 3);
+        for (i, copy) in copies.iter().enumerate() {
+            imageops::replace(&mut image, copy, i as i64 * 3, 0);
+        }
+
+        let options = |matching| TilemapOptions {
+            tile_width: 3,
+            tile_height: 3,
+            matching,
+            ..TilemapOptions::default()
+        };
+
+        let tilemap = slice_tilemap(
+            &PixelifyImage::from(image.clone()),
+            &options(TileMatching::Rotations),
+        )
+        .unwrap();
+        assert_eq!(tilemap.tile_count, 1);
+        assert_eq!(
+            tilemap.gids(),
+            vec![1, 0x8000_0001, 0xC000_0001, 0xA000_0001]
+        );
+
+        // Drawing every cell's tile with its transform gives back the original image
+        let tileset = tilemap.tileset.to_rgba_image().unwrap();
+        for (i, cell) in tilemap.cells.iter().enumerate() {
+            let cell = cell.unwrap();
+            let stored = imageops::crop_imm(&tileset, cell.tile * 3, 0, 3, 3).to_image();
+            assert_eq!(cell.transform.apply(&stored), copies[i]);
+        }
+
+        // Without rotations, the turned copy is a tile of its own
+        let tilemap = slice_tilemap(
+            &PixelifyImage::from(image.clone()),
+            &options(TileMatching::Flips),
+        )
+        .unwrap();
+        assert_eq!(tilemap.tile_count, 2);
+        assert_eq!(tilemap.gids(), vec![1, 0x8000_0001, 0xC000_0001, 2]);
+
+        let tilemap =
+            slice_tilemap(&PixelifyImage::from(image), &options(TileMatching::Exact)).unwrap();
+        assert_eq!(tilemap.gids(), vec![1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,270 @@
+//! Scaling pixel art back up for previews and store listings.
+//!
+//! Pixel art only stays crisp when every source pixel becomes a whole block of output pixels,
+//! so upscaling always uses an integer factor. Nearest-neighbor sampling keeps the pixels square,
+//! and the filters in `scalers` smooth diagonal edges for high resolution promo art.
+
+use crate::PixelifyImage;
+use crate::pixelify_errors::ImageProcessingError;
+use crate::scalers::{scale_epx, scale_hqx, scale_xbr};
+use image::{Rgba, RgbaImage};
+use std::fmt;
+use std::str::FromStr;
+
+/// A translucent black, visible on light and dark pixels alike.
+pub const DEFAULT_GRID_COLOR: [u8; 4] = [0, 0, 0, 64];
+
+/// How large the upscaled image should be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaleSize {
+    /// Every pixel becomes a `factor` x `factor` block.
+    Factor(u32),
+    /// The largest integer factor that still fits within `width` x `height`.
+    Fit { width: u32, height: u32 },
+}
+
+/// The upscaling algorithms Pixelify offers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpscaleMethod {
+    /// Every pixel becomes a square block. Works with any factor.
+    #[default]
+    Nearest,
+    /// EPX, also known as Scale2x, and Scale3x. Works with factors made of 2s and 3s (2, 3, 4, 6, 8, 9...).
+    Epx,
+    /// hq2x, hq3x and hq4x, which blend along edges by lookup tables. Works with factors 2, 3 and 4.
+    Hqx,
+    /// xBR, which follows the slope of edges over a 5x5 window for rounder curves. Works with factors 2, 3 and 4.
+    Xbr,
+}
+
+impl UpscaleMethod {
+    pub const ALL: [UpscaleMethod; 4] = [
+        UpscaleMethod::Nearest,
+        UpscaleMethod::Epx,
+        UpscaleMethod::Hqx,
+        UpscaleMethod::Xbr,
+    ];
+
+    /// Returns true if this method can scale by `factor`. Every method supports a factor of 1.
+    pub fn supports(self, factor: u32) -> bool {
+        match self {
+            _ if factor == 1 => true,
+            UpscaleMethod::Nearest => factor > 0,
+            UpscaleMethod::Epx => {
+                let mut remaining = factor;
+                while remaining > 1 && remaining.is_multiple_of(2) {
+                    remaining /= 2;
+                }
+                while remaining > 1 && remaining.is_multiple_of(3) {
+                    remaining /= 3;
+                }
+                remaining == 1
+            }
+            UpscaleMethod::Hqx | UpscaleMethod::Xbr => (2..=4).contains(&factor),
+        }
+    }
+
+    fn supported_factors(self) -> &'static str {
+        match self {
+            UpscaleMethod::Nearest => "any factor",
+            UpscaleMethod::Epx => "factors made of 2s and 3s, such as 2, 3, 4, 6 or 8",
+            UpscaleMethod::Hqx | UpscaleMethod::Xbr => "factors 2, 3 and 4",
+        }
+    }
+}
+
+impl fmt::Display for UpscaleMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            UpscaleMethod::Nearest => "nearest",
+            UpscaleMethod::Epx => "epx",
+            UpscaleMethod::Hqx => "hqx",
+            UpscaleMethod::Xbr => "xbr",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for UpscaleMethod {
+    type Err = String;
+
+    /// Parses an upscaling method name, ignoring case, `-` and `_`.
+    ///
+    /// The scale factor is chosen separately, so `scale2x` and `scale3x` both select EPX,
+    /// and `hq2x` to `hq4x` all select hqx.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match normalized.as_str() {
+            "nearest" | "nearestneighbor" | "nn" => Ok(UpscaleMethod::Nearest),
+            "epx" | "scale2x" | "scale3x" | "scalex" | "advmame" => Ok(UpscaleMethod::Epx),
+            "hqx" | "hq2x" | "hq3x" | "hq4x" => Ok(UpscaleMethod::Hqx),
+            "xbr" => Ok(UpscaleMethod::Xbr),
+            _ => Err(format!(
+                "unknown upscale method '{s}', expected one of: nearest, epx, hqx, xbr"
+            )),
+        }
+    }
+}
+
+/// Options for `upscale`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpscaleOptions {
+    pub method: UpscaleMethod,
+    /// Draws a 1 pixel line of this RGBA color between the upscaled pixels.
+    /// The color is blended over the image, so a translucent color keeps the pixels visible.
+    pub pixel_grid: Option<[u8; 4]>,
+}
+
+impl UpscaleSize {
+    /// Returns the integer factor `method` uses for an image of `width` x `height`.
+    ///
+    /// When fitting a box, this is the largest factor that both fits and is supported by `method`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageProcessingError` if the factor is 0, `method` does not support it,
+    /// or the image is larger than the box to fit.
+    pub fn factor_for(
+        &self,
+        width: u32,
+        height: u32,
+        method: UpscaleMethod,
+    ) -> Result<u32, ImageProcessingError> {
+        match *self {
+            UpscaleSize::Factor(0) => Err(ImageProcessingError::failed(
+                "upscale",
+                "Scale factor must be at least 1",
+            )),
+            UpscaleSize::Factor(factor) if !method.supports(factor) => {
+                Err(ImageProcessingError::failed(
+                    "upscale",
+                    format!(
+                        "{method} cannot scale by {factor}, it supports {}",
+                        method.supported_factors()
+                    ),
+                ))
+            }
+            UpscaleSize::Factor(factor) => Ok(factor),
+            UpscaleSize::Fit {
+                width: box_width,
+                height: box_height,
+            } => {
+                let largest = (box_width / width.max(1)).min(box_height / height.max(1));
+                (1..=largest)
+                    .rev()
+                    .find(|factor| method.supports(*factor))
+                    .ok_or_else(|| {
+                        ImageProcessingError::failed(
+                            "upscale",
+                            format!(
+                                "A {width}x{height} image does not fit within {box_width}x{box_height}"
+                            ),
+                        )
+                    })
+            }
+        }
+    }
+}
+
+/// Upscales an image by an integer factor with the algorithm in `options.method`.
+///
+/// The factor is either given directly, or the largest one that fits a target box and the method supports.
+/// With `options.pixel_grid` set, a grid line is drawn between every upscaled pixel.
+///
+/// The result holds raw RGBA pixels.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - loading the bytes from memory fails,
+/// - the factor is 0 or not supported by the method, or the image does not fit within the target box,
+/// - a pixel grid is requested with a factor below 2, which would leave no room for the pixels,
+/// - the upscaled image would be too large to address.
+///
+/// Each error is of the type `ImageProcessingError` with a related message.
+pub fn upscale(
+    bytes: &[u8],
+    size: UpscaleSize,
+    options: &UpscaleOptions,
+) -> Result<PixelifyImage, ImageProcessingError> {
+    let image = image::load_from_memory(bytes)
+        .map_err(|_| ImageProcessingError::failed("upscale", "Failed to decode input image"))?
+        .to_rgba8();
+
+    upscale_image(&image, size, options).map(PixelifyImage::from)
+}
+
+/// Upscales an in-memory RGBA image, see `upscale`.
+pub(crate) fn upscale_image(
+    image: &RgbaImage,
+    size: UpscaleSize,
+    options: &UpscaleOptions,
+) -> Result<RgbaImage, ImageProcessingError> {
+    let (width, height) = image.dimensions();
+    let factor = size.factor_for(width, height, options.method)?;
+
+    if options.pixel_grid.is_some() && factor < 2 {
+        return Err(ImageProcessingError::failed(
+            "upscale",
+            "A pixel grid needs a scale factor of at least 2",
+        ));
+    }
+
+    let (new_width, new_height) = width
+        .checked_mul(factor)
+        .zip(height.checked_mul(factor))
+        .ok_or_else(|| ImageProcessingError::failed("upscale", "Upscaled image is too large"))?;
+
+    let mut upscaled = match options.method {
+        UpscaleMethod::Nearest => RgbaImage::from_fn(new_width, new_height, |x, y| {
+            *image.get_pixel(x / factor, y / factor)
+        }),
+        UpscaleMethod::Epx => scale_epx(image, factor),
+        UpscaleMethod::Hqx => scale_hqx(image, factor),
+        UpscaleMethod::Xbr => scale_xbr(image, factor),
+    };
+
+    if let Some(color) = options.pixel_grid {
+        draw_pixel_grid(&mut upscaled, factor, color);
+    }
+
+    Ok(upscaled)
+}
+
+/// Blends `color` over the first row and column of every block, except along the top and left edges.
+fn draw_pixel_grid(image: &mut RgbaImage, factor: u32, color: [u8; 4]) {
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let on_line = (x > 0 && x % factor == 0) || (y > 0 && y % factor == 0);
+        if on_line {
+            *pixel = blend(*pixel, color);
+        }
+    }
+}
+
+/// Composites `color` over `pixel` with the usual "over" operator.
+fn blend(pixel: Rgba<u8>, color: [u8; 4]) -> Rgba<u8> {
+    let alpha = color[3] as f32 / 255.0;
+    let below = pixel[3] as f32 / 255.0;
+    let out_alpha = alpha + below * (1.0 - alpha);
+
+    if out_alpha == 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let channel = |i: usize| {
+        let value = (color[i] as f32 * alpha + pixel[i] as f32 * below * (1.0 - alpha)) / out_alpha;
+        value.round() as u8
+    };
+
+    Rgba([
+        channel(0),
+        channel(1),
+        channel(2),
+        (out_alpha * 255.0).round() as u8,
+    ])
+}
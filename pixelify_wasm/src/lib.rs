@@ -1,14 +1,439 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! WebAssembly bindings for pixelify_core.
+//!
+//! Every operation accepts either a `Uint8Array` holding an encoded image file (PNG, JPEG, GIF, ...)
+//! or an `ImageData` taken from a canvas, and returns a `PixelifyImage` holding raw RGBA pixels.
+//! The result can be drawn back onto a canvas with `toImageData`, or encoded with `toPng`.
+//! Optional settings are passed as one plain object last, such as `{ reducer: "mode", edges: "partial" }`,
+//! with TypeScript definitions for each kind of options object.
+//!
+//! Failures are thrown as a JS `Error` named `ImageProcessingError`, with the failing operation in `op`.
+
+use image::RgbaImage;
+use pixelify_core::background::{BackgroundKey, BackgroundOptions};
+use pixelify_core::color::parse_hex_color;
+use pixelify_core::outline::OutlineOptions;
+use pixelify_core::pipeline::{Operation, Pipeline};
+use pixelify_core::pixelify::PixelifyOptions;
+use pixelify_core::pixelify_errors::ImageProcessingError;
+use pixelify_core::{PixelFormat, PixelifyImage};
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{Clamped, JsCast};
+use web_sys::ImageData;
+
+/// An image returned to JS, along with the format its bytes are in.
+#[wasm_bindgen(js_name = PixelifyImage)]
+pub struct WasmImage {
+    image: PixelifyImage,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[wasm_bindgen(js_class = PixelifyImage)]
+impl WasmImage {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.image.get_width()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.image.get_height()
+    }
+
+    /// `"rgba8"`, `"luma8"` or `"png"`.
+    #[wasm_bindgen(getter)]
+    pub fn format(&self) -> String {
+        let format = match self.image.get_format() {
+            PixelFormat::Rgba8 => "rgba8",
+            PixelFormat::Luma8 => "luma8",
+            PixelFormat::Png => "png",
+        };
+        String::from(format)
+    }
+
+    /// A copy of the image bytes, laid out as described by `format`.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.image.as_bytes().clone()
+    }
+
+    /// Encodes the image as a PNG file, e.g. to download it or show it in an `<img>` through a `Blob`.
+    #[wasm_bindgen(js_name = toPng)]
+    pub fn to_png(&self) -> Result<WasmImage, JsValue> {
+        self.image
+            .to_png()
+            .map(WasmImage::from)
+            .map_err(to_js_error)
+    }
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    /// Converts the image into an `ImageData` that can be drawn with `putImageData`.
+    #[wasm_bindgen(js_name = toImageData)]
+    pub fn to_image_data(&self) -> Result<ImageData, JsValue> {
+        let rgba = self.image.to_rgba_image().map_err(to_js_error)?;
+        ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(rgba.as_raw()),
+            rgba.width(),
+            rgba.height(),
+        )
     }
 }
+
+impl From<PixelifyImage> for WasmImage {
+    fn from(image: PixelifyImage) -> Self {
+        WasmImage { image }
+    }
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const OPTIONS_TYPES: &str = r#"
+/** Options of the downscale functions, a field left out keeps its default. */
+export interface PixelifyOptions {
+    /** Averages each block in linear light, off by default. */
+    linear?: boolean;
+    /** `mean` (default), `median`, `mode`, `dominant`, `center`, `darkest` or `brightest`. */
+    reducer?: string;
+    /** `crop` (default), `center`, `partial`, `pad-replicate`, `pad-transparent` or `pad-color:RRGGBB`. */
+    edges?: string;
+    /** Makes the blocks `pixelSize` wide and `pixelHeight` tall, they are square by default. */
+    pixelHeight?: number;
+    /** `stretch` (default), `fit`, `fit:RRGGBB`, `fill` or `fill:ANCHOR` such as `fill:top-left`. */
+    resize?: string;
+    /** `keep` (default), `threshold`, `threshold:N` or `levels:N`. */
+    alpha?: string;
+}
+
+/** Options of `outline`, a field left out keeps its default. */
+export interface OutlineOptions {
+    /** `outer` (default) or `inner`. */
+    placement?: string;
+    /** 4 (default) or 8. */
+    connectivity?: number;
+    /** 1 by default. */
+    thickness?: number;
+    /** `RRGGBB`, `RRGGBBAA` (black by default), `shade` or `shade:AMOUNT`. */
+    color?: string;
+}
+
+/** Options of `removeBackground`, a field left out keeps its default. */
+export interface BackgroundOptions {
+    /** `RRGGBB`, removes this color everywhere instead of flood filling from the corners. */
+    color?: string;
+    /** Flood fills from `[x0, y0, x1, y1, ...]` instead of the corners. */
+    seeds?: number[];
+    /** The OKLab distance that still counts as background, 0.08 by default. */
+    tolerance?: number;
+    /** Fades the edge of the kept pixels over this many pixels, 0 by default. */
+    feather?: number;
+}
+"#;
+
+/// See `pixelify::pixelify_downscale_by_pixel_size`.
+///
+/// `options` is a `PixelifyOptions` object, see its TypeScript definition for the fields and their defaults.
+#[wasm_bindgen(js_name = downscaleByPixelSize)]
+pub fn downscale_by_pixel_size(
+    input: &JsValue,
+    pixel_size: u32,
+    #[wasm_bindgen(unchecked_optional_param_type = "PixelifyOptions")] options: &JsValue,
+) -> Result<WasmImage, JsValue> {
+    let options = JsOptions::new(options, "pixelify_downscale_by_pixel_size");
+    let pixel_size = (
+        pixel_size,
+        options.u32("pixelHeight")?.unwrap_or(pixel_size),
+    );
+    run(
+        input,
+        Pipeline::new().downscale_by_pixel_size(pixel_size, pixelify_options(&options)?),
+    )
+}
+
+/// See `pixelify::pixelify_false_downscale_by_pixel_size`.
+///
+/// `options` is a `PixelifyOptions` object, see its TypeScript definition for the fields and their defaults.
+/// The image keeps its size whatever `edges` is.
+#[wasm_bindgen(js_name = falseDownscaleByPixelSize)]
+pub fn false_downscale_by_pixel_size(
+    input: &JsValue,
+    pixel_size: u32,
+    #[wasm_bindgen(unchecked_optional_param_type = "PixelifyOptions")] options: &JsValue,
+) -> Result<WasmImage, JsValue> {
+    let options = JsOptions::new(options, "pixelify_false_downscale_by_pixel_size");
+    let pixel_size = (
+        pixel_size,
+        options.u32("pixelHeight")?.unwrap_or(pixel_size),
+    );
+    run(
+        input,
+        Pipeline::new().false_downscale_by_pixel_size(pixel_size, pixelify_options(&options)?),
+    )
+}
+
+/// See `pixelify::pixelify_by_image_size`.
+///
+/// Leave `width` or `height` undefined to derive it from the aspect ratio of the image.
+/// `options` is a `PixelifyOptions` object, `edges` and `pixelHeight` do not apply since the blocks cover the whole image.
+#[wasm_bindgen(js_name = downscaleByImageSize)]
+pub fn downscale_by_image_size(
+    input: &JsValue,
+    width: Option<u32>,
+    height: Option<u32>,
+    #[wasm_bindgen(unchecked_optional_param_type = "PixelifyOptions")] options: &JsValue,
+) -> Result<WasmImage, JsValue> {
+    let options = pixelify_options(&JsOptions::new(options, "pixelify_by_image_size"))?;
+
+    run(
+        input,
+        Pipeline::new().then(Operation::DownscaleByImageSize {
+            width,
+            height,
+            options,
+        }),
+    )
+}
+
+/// See `crop::crop_png`.
+#[wasm_bindgen]
+pub fn crop(
+    input: &JsValue,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<WasmImage, JsValue> {
+    run(input, Pipeline::new().crop(x, y, width, height))
+}
+
+/// Converts to grayscale like `grayscale::grayscale_png`, but keeps the alpha channel
+/// so the result can be drawn onto a canvas as is.
+#[wasm_bindgen]
+pub fn grayscale(input: &JsValue) -> Result<WasmImage, JsValue> {
+    run(input, Pipeline::new().grayscale())
+}
+
+/// See `outline::outline`.
+///
+/// `options` is an `OutlineOptions` object, see its TypeScript definition for the fields and their defaults.
+#[wasm_bindgen]
+pub fn outline(
+    input: &JsValue,
+    #[wasm_bindgen(unchecked_optional_param_type = "OutlineOptions")] options: &JsValue,
+) -> Result<WasmImage, JsValue> {
+    let options = JsOptions::new(options, "outline");
+    let options = OutlineOptions {
+        placement: options.parse("placement")?,
+        connectivity: options.parse("connectivity")?,
+        thickness: options
+            .u32("thickness")?
+            .unwrap_or(OutlineOptions::default().thickness),
+        color: options.parse("color")?,
+    };
+    run(input, Pipeline::new().outline(options))
+}
+
+/// See `background::remove_background`.
+///
+/// `options` is a `BackgroundOptions` object, see its TypeScript definition for the fields and their defaults.
+/// Flood fills from the corners by default, `color` wins over `seeds` when both are given.
+#[wasm_bindgen(js_name = removeBackground)]
+pub fn remove_background(
+    input: &JsValue,
+    #[wasm_bindgen(unchecked_optional_param_type = "BackgroundOptions")] options: &JsValue,
+) -> Result<WasmImage, JsValue> {
+    let options = JsOptions::new(options, "remove_background");
+
+    let key = match (options.string("color")?, options.u32_array("seeds")?) {
+        (Some(color), _) => {
+            let [r, g, b, _] = parse_hex_color(&color).map_err(|message| options.error(message))?;
+            BackgroundKey::Color([r, g, b])
+        }
+        (None, Some(seeds)) => {
+            if !seeds.len().is_multiple_of(2) {
+                return Err(options.error("seeds must hold x, y pairs"));
+            }
+            BackgroundKey::Seeds(seeds.chunks(2).map(|point| (point[0], point[1])).collect())
+        }
+        (None, None) => BackgroundKey::Corners,
+    };
+
+    let defaults = BackgroundOptions::default();
+    let options = BackgroundOptions {
+        key,
+        tolerance: options
+            .number("tolerance")?
+            .map_or(defaults.tolerance, |tolerance| tolerance as f32),
+        feather: options.u32("feather")?.unwrap_or(defaults.feather),
+    };
+    run(input, Pipeline::new().remove_background(options))
+}
+
+/// Decodes an image file or reads an `ImageData`, and encodes it as a PNG file.
+#[wasm_bindgen(js_name = intoPng)]
+pub fn into_png(input: &JsValue) -> Result<WasmImage, JsValue> {
+    let image = PixelifyImage::from(read_input(input)?);
+    image.to_png().map(WasmImage::from).map_err(to_js_error)
+}
+
+fn run(input: &JsValue, pipeline: Pipeline) -> Result<WasmImage, JsValue> {
+    pipeline
+        .run_rgba(read_input(input)?)
+        .map(|image| WasmImage::from(PixelifyImage::from(image)))
+        .map_err(to_js_error)
+}
+
+fn pixelify_options(options: &JsOptions) -> Result<PixelifyOptions, JsValue> {
+    Ok(PixelifyOptions {
+        linear_light: options.bool("linear")?.unwrap_or(false),
+        reducer: options.parse("reducer")?,
+        edges: options.parse("edges")?,
+        resize: options.parse("resize")?,
+        alpha: options.parse("alpha")?,
+    })
+}
+
+/// The fields of a plain JS options object, where `undefined` or `null` counts as a missing field.
+///
+/// An options argument that is itself `undefined` or `null` leaves every field missing.
+struct JsOptions<'a> {
+    object: &'a JsValue,
+    op: &'static str,
+}
+
+impl<'a> JsOptions<'a> {
+    fn new(object: &'a JsValue, op: &'static str) -> JsOptions<'a> {
+        JsOptions { object, op }
+    }
+
+    fn get(&self, name: &str) -> Option<JsValue> {
+        if self.object.is_undefined() || self.object.is_null() {
+            return None;
+        }
+        js_sys::Reflect::get(self.object, &JsValue::from_str(name))
+            .ok()
+            .filter(|value| !value.is_undefined() && !value.is_null())
+    }
+
+    fn string(&self, name: &str) -> Result<Option<String>, JsValue> {
+        self.get(name)
+            .map(|value| {
+                value
+                    .as_string()
+                    .ok_or_else(|| self.error(format!("{name} must be a string")))
+            })
+            .transpose()
+    }
+
+    fn bool(&self, name: &str) -> Result<Option<bool>, JsValue> {
+        self.get(name)
+            .map(|value| {
+                value
+                    .as_bool()
+                    .ok_or_else(|| self.error(format!("{name} must be a boolean")))
+            })
+            .transpose()
+    }
+
+    fn number(&self, name: &str) -> Result<Option<f64>, JsValue> {
+        self.get(name)
+            .map(|value| {
+                value
+                    .as_f64()
+                    .ok_or_else(|| self.error(format!("{name} must be a number")))
+            })
+            .transpose()
+    }
+
+    fn u32(&self, name: &str) -> Result<Option<u32>, JsValue> {
+        self.number(name)?
+            .map(|number| self.whole_number(name, number))
+            .transpose()
+    }
+
+    fn u32_array(&self, name: &str) -> Result<Option<Vec<u32>>, JsValue> {
+        let Some(value) = self.get(name) else {
+            return Ok(None);
+        };
+        if !js_sys::Array::is_array(&value) {
+            return Err(self.error(format!("{name} must be an array of numbers")));
+        }
+
+        js_sys::Array::from(&value)
+            .iter()
+            .map(|item| {
+                item.as_f64()
+                    .ok_or_else(|| self.error(format!("{name} must be an array of numbers")))
+                    .and_then(|number| self.whole_number(name, number))
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+
+    /// Parses an option with `FromStr`, falling back to the default when it is missing.
+    /// Numbers are parsed from their text, so a connectivity can be given as `8` or `"8"`.
+    fn parse<T>(&self, name: &str) -> Result<T, JsValue>
+    where
+        T: FromStr<Err = String> + Default,
+    {
+        let Some(value) = self.get(name) else {
+            return Ok(T::default());
+        };
+        let text = match (value.as_string(), value.as_f64()) {
+            (Some(text), _) => text,
+            (None, Some(number)) => number.to_string(),
+            (None, None) => return Err(self.error(format!("{name} must be a string"))),
+        };
+        text.parse::<T>().map_err(|message| self.error(message))
+    }
+
+    fn whole_number(&self, name: &str, number: f64) -> Result<u32, JsValue> {
+        if number.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&number) {
+            Ok(number as u32)
+        } else {
+            Err(self.error(format!(
+                "{name} must be a whole number of at least 0, got {number}"
+            )))
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> JsValue {
+        to_js_error(ImageProcessingError::failed(self.op, message))
+    }
+}
+
+/// Reads a `Uint8Array` of encoded file bytes or an `ImageData` into RGBA pixels.
+fn read_input(input: &JsValue) -> Result<RgbaImage, JsValue> {
+    if let Some(image_data) = input.dyn_ref::<ImageData>() {
+        let (width, height) = (image_data.width(), image_data.height());
+        return RgbaImage::from_raw(width, height, image_data.data().0).ok_or_else(|| {
+            to_js_error(ImageProcessingError::failed("input", "Bad buffer length"))
+        });
+    }
+
+    if let Some(bytes) = input.dyn_ref::<js_sys::Uint8Array>() {
+        return image::load_from_memory(&bytes.to_vec())
+            .map(|decoded| decoded.to_rgba8())
+            .map_err(|_| {
+                to_js_error(ImageProcessingError::failed(
+                    "input",
+                    "Failed to decode input image",
+                ))
+            });
+    }
+
+    Err(to_js_error(ImageProcessingError::failed(
+        "input",
+        "Expected a Uint8Array or an ImageData",
+    )))
+}
+
+/// Builds a JS `Error` named `ImageProcessingError`, with `op` set to the failing operation.
+fn to_js_error(error: ImageProcessingError) -> JsValue {
+    let js_error = js_sys::Error::new(&error.to_string());
+    js_error.set_name("ImageProcessingError");
+
+    // Setting a plain string property on a fresh Error object cannot fail
+    let _ = js_sys::Reflect::set(
+        &js_error,
+        &JsValue::from_str("op"),
+        &JsValue::from_str(error.op),
+    );
+
+    js_error.into()
+}